@@ -0,0 +1,164 @@
+//! Benchmarks the eager transform path, which ingests the CSV files into `Movie` structs and
+//! converts them to a `DataFrame`, against the lazy path, which scans the files with Polars and
+//! runs ingestion, cleaning and validation as one query plan.
+//!
+//! Run with `cargo bench --bench transform`. The `BENCH_ROWS` and `BENCH_ITERATIONS` environment
+//! variables set the size of the synthetic dataset and the number of timed runs per path.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use rust_final_project::ingestion;
+use rust_final_project::transform::{self, TransformOptions};
+
+/// Default number of movies in the synthetic dataset.
+const DEFAULT_ROWS: usize = 200_000;
+
+/// Default number of timed runs per path.
+const DEFAULT_ITERATIONS: usize = 5;
+
+/// Data-quality rules applied by both paths, so validation is part of the measurement.
+const RULES: &str = r#"
+[[rules]]
+column = "avg_vote"
+check = "between"
+min = 0.0
+max = 10.0
+severity = "drop"
+
+[[rules]]
+column = "title"
+check = "not_empty"
+"#;
+
+fn main() {
+    let rows = env_or("BENCH_ROWS", DEFAULT_ROWS);
+    let iterations = env_or("BENCH_ITERATIONS", DEFAULT_ITERATIONS);
+
+    let temp_dir = tempfile::tempdir().expect("failed to create a temporary directory");
+    let path = temp_dir.path().join("movies.csv");
+    write_dataset(&path, rows).expect("failed to write the synthetic dataset");
+    let paths = vec![path];
+
+    let options = TransformOptions {
+        validation: toml::from_str(RULES).expect("invalid benchmark rules"),
+        ..TransformOptions::default()
+    };
+
+    println!(
+        "Transforming {} movies, {} iteration(s) per path.",
+        rows, iterations
+    );
+    let eager = bench("eager", iterations, || {
+        let movies =
+            ingestion::ingest_files(&paths, &Default::default()).expect("eager ingestion failed");
+        transform::transform_movies(movies, &options)
+            .expect("eager transform failed")
+            .0
+            .height()
+    });
+    let lazy = bench("lazy", iterations, || {
+        let lf = ingestion::scan_csv(&paths, &Default::default()).expect("lazy scan failed");
+        transform::transform_lazy(lf, &options)
+            .expect("lazy transform failed")
+            .0
+            .height()
+    });
+
+    println!(
+        "lazy is {:.2}x the speed of eager",
+        eager.as_secs_f64() / lazy.as_secs_f64()
+    );
+}
+
+/// Runs a transform path `iterations` times after one warm-up run and prints its mean duration.
+///
+/// # Arguments
+///
+/// * `name` - The name of the path.
+/// * `iterations` - The number of timed runs.
+/// * `run` - The path, returning the number of transformed movies.
+///
+/// # Returns
+///
+/// The mean duration of a run.
+fn bench(name: &str, iterations: usize, mut run: impl FnMut() -> usize) -> Duration {
+    let movies = run();
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        run();
+    }
+    let mean = start.elapsed() / iterations.max(1) as u32;
+
+    println!("{:>6}: {:>10.1?} per run, {} movies", name, mean, movies);
+    mean
+}
+
+/// Writes a synthetic FilmTV dataset with some duplicate ids, blank titles and invalid votes.
+///
+/// # Arguments
+///
+/// * `path` - The CSV file to write.
+/// * `rows` - The number of movies.
+///
+/// # Returns
+///
+/// A `Result` indicating success or an I/O error.
+fn write_dataset(path: &Path, rows: usize) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(
+        file,
+        "filmtv_id,title,year,genre,duration,country,directors,actors,avg_vote,critics_vote,\
+         public_vote,total_votes,description,notes,humor,rhythm,effort,tension,erotism"
+    )?;
+
+    for i in 0..rows {
+        // Every 50th movie repeats an earlier id and every 100th has a blank title
+        let filmtv_id = if i % 50 == 49 { i - 7 } else { i } + 1;
+        let title = if i % 100 == 99 {
+            String::new()
+        } else {
+            format!("Movie {}", i)
+        };
+        let avg_vote = if i % 200 == 199 {
+            12.0
+        } else {
+            (i % 100) as f64 / 10.0
+        };
+        writeln!(
+            file,
+            "{},{},{},Drama,{},Italy,Director {},\"Actor {}, Actor {}\",{},{},{},{},\
+             \"A movie, described.\",,{},{},{},{},{}",
+            filmtv_id,
+            title,
+            1950 + i % 70,
+            80 + i % 60,
+            i % 1000,
+            i % 500,
+            i % 700,
+            avg_vote,
+            avg_vote,
+            avg_vote,
+            i % 10_000,
+            i % 6,
+            (i + 1) % 6,
+            (i + 2) % 6,
+            (i + 3) % 6,
+            (i + 4) % 7,
+        )?;
+    }
+
+    file.flush()
+}
+
+/// Reads a positive number from an environment variable.
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .filter(|&value| value > 0)
+        .unwrap_or(default)
+}
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS Movie (
+    filmtv_id BIGINT PRIMARY KEY,
+    title TEXT NOT NULL,
+    year BIGINT NOT NULL,
+    genre TEXT NOT NULL,
+    duration BIGINT NOT NULL,
+    country TEXT NOT NULL,
+    avg_vote DOUBLE PRECISION NOT NULL,
+    critics_vote DOUBLE PRECISION NOT NULL,
+    public_vote DOUBLE PRECISION NOT NULL,
+    total_votes BIGINT NOT NULL
+);
@@ -0,0 +1,5 @@
+ALTER TABLE Movie
+    DROP COLUMN IF EXISTS directors,
+    DROP COLUMN IF EXISTS actors,
+    DROP COLUMN IF EXISTS description,
+    DROP COLUMN IF EXISTS notes;
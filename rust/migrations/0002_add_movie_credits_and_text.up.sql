@@ -0,0 +1,5 @@
+ALTER TABLE Movie
+    ADD COLUMN directors TEXT,
+    ADD COLUMN actors TEXT,
+    ADD COLUMN description TEXT,
+    ADD COLUMN notes TEXT;
@@ -0,0 +1,6 @@
+ALTER TABLE Movie
+    DROP COLUMN IF EXISTS humor,
+    DROP COLUMN IF EXISTS rhythm,
+    DROP COLUMN IF EXISTS effort,
+    DROP COLUMN IF EXISTS tension,
+    DROP COLUMN IF EXISTS erotism;
@@ -0,0 +1,6 @@
+ALTER TABLE Movie
+    ADD COLUMN humor BIGINT CHECK (humor BETWEEN 0 AND 5),
+    ADD COLUMN rhythm BIGINT CHECK (rhythm BETWEEN 0 AND 5),
+    ADD COLUMN effort BIGINT CHECK (effort BETWEEN 0 AND 5),
+    ADD COLUMN tension BIGINT CHECK (tension BETWEEN 0 AND 5),
+    ADD COLUMN erotism BIGINT CHECK (erotism BETWEEN 0 AND 5);
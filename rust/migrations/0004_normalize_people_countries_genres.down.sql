@@ -0,0 +1,7 @@
+DROP TABLE IF EXISTS movie_country;
+DROP TABLE IF EXISTS movie_actor;
+DROP TABLE IF EXISTS movie_director;
+ALTER TABLE Movie DROP COLUMN IF EXISTS genre_id;
+DROP TABLE IF EXISTS genre;
+DROP TABLE IF EXISTS country;
+DROP TABLE IF EXISTS person;
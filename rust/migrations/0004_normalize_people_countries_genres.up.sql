@@ -0,0 +1,49 @@
+CREATE TABLE person (
+    person_id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    normalized_name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE country (
+    country_id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    normalized_name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE genre (
+    genre_id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    normalized_name TEXT NOT NULL UNIQUE
+);
+
+ALTER TABLE Movie ADD COLUMN genre_id BIGINT REFERENCES genre (genre_id);
+CREATE INDEX movie_genre_id_idx ON Movie (genre_id);
+
+-- The link tables do not reference Movie because a full refresh writes the links of its movies
+-- while they are still in the staging table, before they are copied into Movie. The loader keeps
+-- them in sync instead: a full refresh and --drop-existing delete every link before loading, and
+-- an upsert replaces the links of every movie it writes, so only movies deleted by hand leave
+-- links behind.
+CREATE TABLE movie_director (
+    filmtv_id BIGINT NOT NULL,
+    person_id BIGINT NOT NULL REFERENCES person (person_id),
+    position INT NOT NULL,
+    PRIMARY KEY (filmtv_id, person_id)
+);
+CREATE INDEX movie_director_person_id_idx ON movie_director (person_id);
+
+CREATE TABLE movie_actor (
+    filmtv_id BIGINT NOT NULL,
+    person_id BIGINT NOT NULL REFERENCES person (person_id),
+    position INT NOT NULL,
+    PRIMARY KEY (filmtv_id, person_id)
+);
+CREATE INDEX movie_actor_person_id_idx ON movie_actor (person_id);
+
+CREATE TABLE movie_country (
+    filmtv_id BIGINT NOT NULL,
+    country_id BIGINT NOT NULL REFERENCES country (country_id),
+    position INT NOT NULL,
+    PRIMARY KEY (filmtv_id, country_id)
+);
+CREATE INDEX movie_country_country_id_idx ON movie_country (country_id);
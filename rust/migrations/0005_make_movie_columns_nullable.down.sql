@@ -0,0 +1,21 @@
+UPDATE Movie SET
+    title = coalesce(title, 'Unknown'),
+    year = coalesce(year, 0),
+    genre = coalesce(genre, 'Unknown'),
+    duration = coalesce(duration, 0),
+    country = coalesce(country, 'Unknown'),
+    avg_vote = coalesce(avg_vote, 0.0),
+    critics_vote = coalesce(critics_vote, 0.0),
+    public_vote = coalesce(public_vote, 0.0),
+    total_votes = coalesce(total_votes, 0);
+
+ALTER TABLE Movie
+    ALTER COLUMN title SET NOT NULL,
+    ALTER COLUMN year SET NOT NULL,
+    ALTER COLUMN genre SET NOT NULL,
+    ALTER COLUMN duration SET NOT NULL,
+    ALTER COLUMN country SET NOT NULL,
+    ALTER COLUMN avg_vote SET NOT NULL,
+    ALTER COLUMN critics_vote SET NOT NULL,
+    ALTER COLUMN public_vote SET NOT NULL,
+    ALTER COLUMN total_votes SET NOT NULL;
@@ -0,0 +1,11 @@
+-- Missing values are no longer replaced with 0 or 'Unknown' by default, see the null policies.
+ALTER TABLE Movie
+    ALTER COLUMN title DROP NOT NULL,
+    ALTER COLUMN year DROP NOT NULL,
+    ALTER COLUMN genre DROP NOT NULL,
+    ALTER COLUMN duration DROP NOT NULL,
+    ALTER COLUMN country DROP NOT NULL,
+    ALTER COLUMN avg_vote DROP NOT NULL,
+    ALTER COLUMN critics_vote DROP NOT NULL,
+    ALTER COLUMN public_vote DROP NOT NULL,
+    ALTER COLUMN total_votes DROP NOT NULL;
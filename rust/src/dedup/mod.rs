@@ -0,0 +1,1154 @@
+//! This module detects movies that share a `filmtv_id`, within one input file or across several,
+//! and resolves them according to the `DuplicatePolicy` declared in the `[duplicates]` section of
+//! the configuration file. Without resolution the database load would keep whichever row happened
+//! to come last in the input.
+//!
+//! The optional fuzzy stage, declared in the `[duplicates.fuzzy]` section, finds the same film
+//! under different `filmtv_id`s with slightly different titles, e.g. "The Godfather" and
+//! "Godfather, The", reports them as clusters and optionally merges them.
+
+use crate::config::ConfigError;
+use crate::models::{Movie, Provenance};
+use crate::relations::{normalize_name, split_list};
+use crate::transform;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Enum representing how movies that share a `filmtv_id` are resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// Keep the first occurrence in input order.
+    KeepFirst,
+    /// Keep the last occurrence in input order.
+    #[default]
+    KeepLast,
+    /// Keep the occurrence with the most votes, the last one on a tie.
+    MostVotes,
+    /// Merge the occurrences field by field. Later occurrences win, but missing or blank fields
+    /// never overwrite present ones.
+    Merge,
+    /// Abort the run.
+    Fail,
+}
+
+impl fmt::Display for DuplicatePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DuplicatePolicy::KeepFirst => write!(f, "keep_first"),
+            DuplicatePolicy::KeepLast => write!(f, "keep_last"),
+            DuplicatePolicy::MostVotes => write!(f, "most_votes"),
+            DuplicatePolicy::Merge => write!(f, "merge"),
+            DuplicatePolicy::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+/// Struct representing the `[duplicates]` section of the configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DuplicateConfig {
+    /// How movies that share a `filmtv_id` are resolved.
+    #[serde(default)]
+    pub policy: DuplicatePolicy,
+    /// The fuzzy stage settings, or `None` to skip the fuzzy stage.
+    pub fuzzy: Option<FuzzyConfig>,
+}
+
+/// Struct representing a `filmtv_id` that occurs more than once.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Duplicate {
+    /// The repeated `filmtv_id`.
+    pub filmtv_id: i64,
+    /// Where each occurrence was read from, in input order. `None` if it was not read from a file.
+    pub occurrences: Vec<Option<Provenance>>,
+    /// The index into `occurrences` of the movie that was kept, or `None` if the occurrences were
+    /// merged or the run was aborted.
+    pub kept: Option<usize>,
+}
+
+/// Enum representing the errors raised while resolving duplicates.
+#[derive(Debug)]
+pub enum DuplicateError {
+    /// Error that occurs when `filmtv_id`s repeat under the `fail` policy.
+    Found(Vec<Duplicate>),
+}
+
+impl fmt::Display for DuplicateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DuplicateError::Found(duplicates) => {
+                write!(f, "{} filmtv_id(s) occur more than once", duplicates.len())?;
+                if let Some(first) = duplicates.first() {
+                    let locations = first
+                        .occurrences
+                        .iter()
+                        .map(|source| match source {
+                            Some(source) => format!("{}:{}", source.file.display(), source.line),
+                            None => "unknown".to_string(),
+                        })
+                        .collect::<Vec<_>>();
+                    write!(f, ", e.g. {} at {}", first.filmtv_id, locations.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for DuplicateError {}
+
+/// Returns the later text unless it is missing or blank.
+fn or_text(later: Option<String>, earlier: Option<String>) -> Option<String> {
+    later.filter(|text| !text.trim().is_empty()).or(earlier)
+}
+
+/// Merges a later occurrence of a movie into an earlier one. Fields of the later occurrence win
+/// when they are present and not blank.
+fn merge(earlier: Movie, later: Movie) -> Movie {
+    Movie {
+        filmtv_id: later.filmtv_id.or(earlier.filmtv_id),
+        title: or_text(later.title, earlier.title),
+        year: later.year.or(earlier.year),
+        genre: or_text(later.genre, earlier.genre),
+        duration: later.duration.or(earlier.duration),
+        country: or_text(later.country, earlier.country),
+        directors: or_text(later.directors, earlier.directors),
+        actors: or_text(later.actors, earlier.actors),
+        avg_vote: later.avg_vote.or(earlier.avg_vote),
+        critics_vote: later.critics_vote.or(earlier.critics_vote),
+        public_vote: later.public_vote.or(earlier.public_vote),
+        total_votes: later.total_votes.or(earlier.total_votes),
+        description: or_text(later.description, earlier.description),
+        notes: or_text(later.notes, earlier.notes),
+        humor: later.humor.or(earlier.humor),
+        rhythm: later.rhythm.or(earlier.rhythm),
+        effort: later.effort.or(earlier.effort),
+        tension: later.tension.or(earlier.tension),
+        erotism: later.erotism.or(earlier.erotism),
+        source: later.source.or(earlier.source),
+    }
+}
+
+/// Returns the index of the occurrence a policy keeps.
+///
+/// # Arguments
+///
+/// * `policy` - How duplicates are resolved.
+/// * `total_votes` - The votes of each occurrence, in input order.
+///
+/// # Returns
+///
+/// The index of the kept occurrence, or `None` if the occurrences are merged or the run is
+/// aborted.
+fn kept_index(policy: DuplicatePolicy, total_votes: &[Option<i64>]) -> Option<usize> {
+    match policy {
+        DuplicatePolicy::KeepFirst => Some(0),
+        DuplicatePolicy::KeepLast => total_votes.len().checked_sub(1),
+        DuplicatePolicy::MostVotes => total_votes
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, votes)| **votes)
+            .map(|(i, _)| i),
+        DuplicatePolicy::Merge | DuplicatePolicy::Fail => None,
+    }
+}
+
+/// Name of the temporary column holding the input order of the movies of a `LazyFrame`.
+const ORDER_COLUMN: &str = "__order";
+
+/// Name of the temporary column holding the input order of the first occurrence of a `filmtv_id`.
+const FIRST_COLUMN: &str = "__first";
+
+/// Names of the temporary columns holding, for every movie, the source files, source lines and
+/// `total_votes` of all occurrences of its `filmtv_id`, in input order.
+const OCCURRENCE_COLUMNS: [&str; 3] = [
+    "__occurrence_files",
+    "__occurrence_lines",
+    "__occurrence_votes",
+];
+
+/// Resolves movies that share a `filmtv_id` in a `LazyFrame`, like `resolve_duplicates`.
+///
+/// Both the resolution and the search for duplicates are added to the query plan: every
+/// occurrence of a `filmtv_id` is listed, with a window over the `filmtv_id`, in temporary columns
+/// of the movie that is kept. Once the plan has been collected, `take_duplicates` reads the
+/// duplicates from these columns and removes them. Unlike `resolve_duplicates`, movies without a
+/// `filmtv_id` are dropped here rather than by the cleaning step.
+///
+/// # Arguments
+///
+/// * `lf` - A `LazyFrame` with the columns of `transform::output_schema`.
+/// * `policy` - How duplicates are resolved.
+///
+/// # Returns
+///
+/// A `LazyFrame` with one movie per `filmtv_id`, at the position of its first occurrence, and the
+/// columns of `transform::output_schema` followed by the temporary occurrence columns.
+pub fn resolve_duplicates_lazy(lf: LazyFrame, policy: DuplicatePolicy) -> LazyFrame {
+    let occurrences = [
+        transform::SOURCE_FILE,
+        transform::SOURCE_LINE,
+        "total_votes",
+    ]
+    .into_iter()
+    .zip(OCCURRENCE_COLUMNS)
+    .map(|(column, name)| {
+        col(column)
+            .over_with_options([col("filmtv_id")], None, WindowMapping::Join)
+            .alias(name)
+    })
+    .collect::<Vec<_>>();
+    let lf = lf
+        .filter(col("filmtv_id").is_not_null())
+        .with_row_index(ORDER_COLUMN, None)
+        .with_columns(occurrences)
+        .with_column(
+            col(ORDER_COLUMN)
+                .min()
+                .over([col("filmtv_id")])
+                .alias(FIRST_COLUMN),
+        );
+
+    let subset = Some(vec!["filmtv_id".to_string()]);
+    let resolved = match policy {
+        DuplicatePolicy::KeepFirst | DuplicatePolicy::Fail => {
+            lf.unique_stable(subset, UniqueKeepStrategy::First)
+        }
+        DuplicatePolicy::KeepLast => lf
+            .unique_stable(subset, UniqueKeepStrategy::Last)
+            .sort([FIRST_COLUMN], SortMultipleOptions::default()),
+        // Ties are won by the later occurrence and missing votes lose, like `kept_index`
+        DuplicatePolicy::MostVotes => lf
+            .sort_by_exprs(
+                [col("total_votes"), col(ORDER_COLUMN)],
+                SortMultipleOptions::default()
+                    .with_order_descending(true)
+                    .with_nulls_last(true),
+            )
+            .unique_stable(subset, UniqueKeepStrategy::First)
+            .sort([FIRST_COLUMN], SortMultipleOptions::default()),
+        DuplicatePolicy::Merge => {
+            // The merged movie keeps the source of its last occurrence
+            let merged = transform::movie_schema()
+                .iter()
+                .filter(|(name, _)| name.as_str() != "filmtv_id")
+                .map(|(name, dtype)| {
+                    let column = col(name);
+                    let present = match dtype {
+                        DataType::String => column
+                            .clone()
+                            .str()
+                            .strip_chars(lit(NULL))
+                            .str()
+                            .len_chars()
+                            .gt(0),
+                        _ => column.clone().is_not_null(),
+                    };
+                    column.filter(present).last().alias(name)
+                })
+                .chain([
+                    col(transform::SOURCE_FILE).last(),
+                    col(transform::SOURCE_LINE).last(),
+                ])
+                .chain(OCCURRENCE_COLUMNS.map(|name| col(name).first()))
+                .collect::<Vec<_>>();
+            lf.group_by_stable([col("filmtv_id")]).agg(merged)
+        }
+    };
+
+    let columns = transform::output_schema()
+        .iter_names()
+        .map(|name| col(name))
+        .chain(OCCURRENCE_COLUMNS.map(col))
+        .collect::<Vec<_>>();
+
+    resolved.select(columns)
+}
+
+/// Reads the duplicates found by `resolve_duplicates_lazy` from the collected movies and removes
+/// the temporary occurrence columns.
+///
+/// # Arguments
+///
+/// * `df` - The collected movies, in order of first occurrence, with the occurrence columns.
+/// * `policy` - How the duplicates were resolved.
+///
+/// # Returns
+///
+/// A `Result` containing the movies without the occurrence columns and the duplicates, in order of
+/// first occurrence, or an error, e.g. a `DuplicateError` under the `fail` policy.
+pub fn take_duplicates(
+    df: DataFrame,
+    policy: DuplicatePolicy,
+) -> Result<(DataFrame, Vec<Duplicate>), Box<dyn Error>> {
+    let filmtv_id = df.column("filmtv_id")?.i64()?;
+    let [files, lines, votes] = OCCURRENCE_COLUMNS.map(|name| df.column(name));
+    let (files, lines, votes) = (files?.list()?, lines?.list()?, votes?.list()?);
+
+    let mut duplicates = Vec::new();
+    for i in 0..df.height() {
+        let (Some(id), Some(files), Some(lines), Some(votes)) = (
+            filmtv_id.get(i),
+            files.get_as_series(i),
+            lines.get_as_series(i),
+            votes.get_as_series(i),
+        ) else {
+            continue;
+        };
+        if files.len() < 2 {
+            continue;
+        }
+
+        let occurrences = files
+            .str()?
+            .into_iter()
+            .zip(lines.u64()?)
+            .map(|(file, line)| {
+                file.zip(line).map(|(file, line)| Provenance {
+                    file: PathBuf::from(file),
+                    line,
+                })
+            })
+            .collect();
+        let votes = votes.i64()?.to_vec();
+        duplicates.push(Duplicate {
+            filmtv_id: id,
+            occurrences,
+            kept: kept_index(policy, &votes),
+        });
+    }
+
+    if policy == DuplicatePolicy::Fail && !duplicates.is_empty() {
+        return Err(DuplicateError::Found(duplicates).into());
+    }
+
+    Ok((df.drop_many(&OCCURRENCE_COLUMNS), duplicates))
+}
+
+/// Resolves movies that share a `filmtv_id`. Movies without a `filmtv_id` are passed through and
+/// dropped later by the cleaning step.
+///
+/// # Arguments
+///
+/// * `movies` - The ingested movies, in input order.
+/// * `policy` - How duplicates are resolved.
+///
+/// # Returns
+///
+/// A `Result` containing one movie per `filmtv_id`, at the position of its first occurrence, and
+/// the duplicates that were found, or a `DuplicateError` under the `fail` policy.
+pub fn resolve_duplicates(
+    movies: Vec<Movie>,
+    policy: DuplicatePolicy,
+) -> Result<(Vec<Movie>, Vec<Duplicate>), DuplicateError> {
+    // Group the occurrences of every filmtv_id, keeping the groups in order of first occurrence
+    let mut groups: Vec<Vec<Movie>> = Vec::new();
+    let mut group_of: HashMap<i64, usize> = HashMap::new();
+    for movie in movies {
+        match movie.filmtv_id.and_then(|id| group_of.get(&id)) {
+            Some(&group) => groups[group].push(movie),
+            None => {
+                if let Some(filmtv_id) = movie.filmtv_id {
+                    group_of.insert(filmtv_id, groups.len());
+                }
+                groups.push(vec![movie]);
+            }
+        }
+    }
+
+    let mut resolved = Vec::with_capacity(groups.len());
+    let mut duplicates = Vec::new();
+    for mut group in groups {
+        if group.len() == 1 {
+            resolved.extend(group);
+            continue;
+        }
+
+        let total_votes = group
+            .iter()
+            .map(|movie| movie.total_votes)
+            .collect::<Vec<_>>();
+        let kept = kept_index(policy, &total_votes);
+        duplicates.push(Duplicate {
+            filmtv_id: group[0].filmtv_id.unwrap_or_default(),
+            occurrences: group.iter().map(|movie| movie.source.clone()).collect(),
+            kept,
+        });
+
+        match (policy, kept) {
+            (DuplicatePolicy::Fail, _) => {}
+            (_, Some(i)) => resolved.push(group.swap_remove(i)),
+            (_, None) => resolved.extend(group.into_iter().reduce(merge)),
+        }
+    }
+
+    if policy == DuplicatePolicy::Fail && !duplicates.is_empty() {
+        return Err(DuplicateError::Found(duplicates));
+    }
+
+    Ok((resolved, duplicates))
+}
+
+/// Struct representing the `[duplicates.fuzzy]` section of the configuration file. The fuzzy
+/// stage only runs when the section is present.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FuzzyConfig {
+    /// The lowest similarity, from 0 to 1, at which two movies are clustered.
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    /// The lowest similarity at which a cluster is merged automatically. Clusters are only
+    /// reported if this is not set.
+    pub auto_merge: Option<f64>,
+    /// Where the clusters are written.
+    #[serde(default = "default_clusters_path")]
+    pub clusters: PathBuf,
+}
+
+impl Default for FuzzyConfig {
+    fn default() -> Self {
+        FuzzyConfig {
+            threshold: default_threshold(),
+            auto_merge: None,
+            clusters: default_clusters_path(),
+        }
+    }
+}
+
+/// Returns the default clustering threshold.
+fn default_threshold() -> f64 {
+    0.85
+}
+
+/// Returns the default path of the clusters file.
+fn default_clusters_path() -> PathBuf {
+    PathBuf::from("duplicate_clusters.json")
+}
+
+impl DuplicateConfig {
+    /// Checks that the fuzzy thresholds lie between 0 and 1 and that movies are not merged below
+    /// the clustering threshold.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `ConfigError` for the first invalid threshold.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let Some(fuzzy) = &self.fuzzy else {
+            return Ok(());
+        };
+
+        if !(fuzzy.threshold > 0.0 && fuzzy.threshold <= 1.0) {
+            return Err(ConfigError::InvalidValue {
+                key: "duplicates.fuzzy.threshold".to_string(),
+                value: fuzzy.threshold.to_string(),
+            });
+        }
+        match fuzzy.auto_merge {
+            Some(auto_merge) if !(fuzzy.threshold..=1.0).contains(&auto_merge) => {
+                Err(ConfigError::InvalidValue {
+                    key: "duplicates.fuzzy.auto_merge".to_string(),
+                    value: auto_merge.to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Struct representing a movie of a fuzzy duplicate cluster.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClusterMember {
+    pub filmtv_id: i64,
+    pub title: String,
+    pub directors: Option<String>,
+    pub total_votes: Option<i64>,
+}
+
+/// Struct representing movies with different `filmtv_id`s that are probably the same film.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FuzzyCluster {
+    /// The year all movies of the cluster share.
+    pub year: i64,
+    /// The movies of the cluster, ordered by `filmtv_id`.
+    pub movies: Vec<ClusterMember>,
+    /// The lowest similarity of the pairs that joined the cluster.
+    pub score: f64,
+    /// The `filmtv_id` the cluster was merged into, or `None` if it was only reported.
+    pub merged_into: Option<i64>,
+}
+
+/// Struct holding what is compared of a movie in the fuzzy stage.
+struct Candidate {
+    member: ClusterMember,
+    year: i64,
+    /// The first and the last characters of the normalized title, see `BLOCK_LENGTH`.
+    blocks: [String; 2],
+    /// The normalized title, see `normalized_title`.
+    title: String,
+    /// The words of the normalized title that are numbers, such as the "2" of "Alien 2" or the
+    /// "ii" of "Part II".
+    numbers: HashSet<String>,
+    /// The normalized director names.
+    directors: HashSet<String>,
+}
+
+/// Articles ignored at the start of titles, or at the end after a comma, in English and Italian.
+const ARTICLES: &str = "the|a|an|il|lo|la|i|gli|le|l|un|uno|una";
+
+/// Number of characters of the normalized title movies are blocked on, besides the year. Movies
+/// are blocked once on the first and once on the last characters, so a typo at one end of a title
+/// does not keep its movie apart.
+const BLOCK_LENGTH: usize = 3;
+
+/// Weight of the title similarity when both movies list directors. The director overlap makes up
+/// the rest.
+const TITLE_WEIGHT: f64 = 0.75;
+
+/// Returns an expression normalizing a title for comparison: lowercase, with a trailing article
+/// after a comma removed, punctuation replaced by spaces and a leading article removed, so "The
+/// Godfather" and "Godfather, The" both become "godfather". An article at the end without a comma
+/// is kept, as the "I" of "Rocky I" is a number.
+fn normalized_title(title: Expr) -> Expr {
+    title
+        .str()
+        .to_lowercase()
+        .str()
+        .replace_all(lit(format!(r",\s*(?:{})'?\s*$", ARTICLES)), lit(""), false)
+        .str()
+        .replace_all(lit(r"[^\p{L}\p{N}]+"), lit(" "), false)
+        .str()
+        .strip_chars(lit(NULL))
+        .str()
+        .replace_all(lit(format!(r"^(?:{})\s+", ARTICLES)), lit(""), false)
+}
+
+/// Returns whether a word of a normalized title is a number, in digits or in roman numerals.
+fn is_number(word: &str) -> bool {
+    word.chars().all(|c| c.is_ascii_digit()) || word.chars().all(|c| matches!(c, 'i' | 'v' | 'x'))
+}
+
+/// Returns the Levenshtein distance between two strings, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Returns the Jaccard similarity of two sets, or `None` if both are empty.
+fn jaccard<T: Eq + std::hash::Hash>(a: &HashSet<T>, b: &HashSet<T>) -> Option<f64> {
+    let union = a.union(b).count();
+    (union > 0).then(|| a.intersection(b).count() as f64 / union as f64)
+}
+
+/// Scores how likely two movies are the same film, from 0 to 1.
+///
+/// The title similarity is the higher of the edit-distance ratio and the token overlap of the
+/// normalized titles. When both movies list directors, it is combined with their overlap. Titles
+/// with different numbers, such as "Part I" and "Part II", are sequels and score 0.
+fn similarity(a: &Candidate, b: &Candidate) -> f64 {
+    if a.numbers != b.numbers {
+        return 0.0;
+    }
+    let length = a.title.chars().count().max(b.title.chars().count()).max(1);
+    let edit = 1.0 - edit_distance(&a.title, &b.title) as f64 / length as f64;
+    let tokens = |title: &str| title.split_whitespace().map(str::to_string).collect();
+    let overlap = jaccard(&tokens(&a.title), &tokens(&b.title)).unwrap_or(0.0);
+    let title = edit.max(overlap);
+
+    if a.directors.is_empty() || b.directors.is_empty() {
+        return title;
+    }
+    let directors = jaccard(&a.directors, &b.directors).unwrap_or(0.0);
+    TITLE_WEIGHT * title + (1.0 - TITLE_WEIGHT) * directors
+}
+
+/// Reads the movies that can be compared, i.e. that have a year and a title, sorted by year.
+fn candidates(df: &DataFrame) -> Result<Vec<Candidate>, PolarsError> {
+    let blocked = df
+        .clone()
+        .lazy()
+        .select([
+            col("filmtv_id"),
+            col("year"),
+            col("title"),
+            col("directors"),
+            col("total_votes"),
+            normalized_title(col("title")).alias("normalized_title"),
+        ])
+        .filter(
+            col("year")
+                .is_not_null()
+                .and(col("normalized_title").str().len_chars().gt(0)),
+        )
+        .sort_by_exprs(
+            [col("year"), col("filmtv_id")],
+            SortMultipleOptions::default(),
+        )
+        .collect()?;
+
+    let filmtv_id = blocked.column("filmtv_id")?.i64()?;
+    let year = blocked.column("year")?.i64()?;
+    let title = blocked.column("title")?.str()?;
+    let directors = blocked.column("directors")?.str()?;
+    let total_votes = blocked.column("total_votes")?.i64()?;
+    let normalized = blocked.column("normalized_title")?.str()?;
+
+    Ok((0..blocked.height())
+        .map(|i| {
+            let normalized = normalized.get(i).unwrap_or_default();
+            let chars = normalized.chars().collect::<Vec<_>>();
+            let length = chars.len().min(BLOCK_LENGTH);
+            Candidate {
+                member: ClusterMember {
+                    filmtv_id: filmtv_id.get(i).unwrap_or_default(),
+                    title: title.get(i).unwrap_or_default().to_string(),
+                    directors: directors.get(i).map(str::to_string),
+                    total_votes: total_votes.get(i),
+                },
+                year: year.get(i).unwrap_or_default(),
+                blocks: [
+                    chars[..length].iter().collect(),
+                    chars[chars.len() - length..].iter().collect(),
+                ],
+                title: normalized.to_string(),
+                numbers: normalized
+                    .split_whitespace()
+                    .filter(|word| is_number(word))
+                    .map(str::to_string)
+                    .collect(),
+                directors: directors
+                    .get(i)
+                    .map(split_list)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|name| normalize_name(name))
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+/// Returns the root of an element in a union-find forest, compressing the path to it.
+fn find(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut i = i;
+    while parents[i] != root {
+        let next = parents[i];
+        parents[i] = root;
+        i = next;
+    }
+    root
+}
+
+/// Finds movies with different `filmtv_id`s that are probably the same film.
+///
+/// Movies are blocked on their year and the first characters of their normalized title, then on
+/// their year and the last characters, and every pair within a block is scored with `similarity`. Pairs at or above the threshold are
+/// clustered transitively. If `auto_merge` is set, clusters whose every joining pair scored at
+/// least that much are merged into the movie with the most votes: the other movies of the cluster
+/// are folded into it with `merge`, its own fields winning, and dropped.
+///
+/// # Arguments
+///
+/// * `df` - A cleaned `DataFrame` with one movie per `filmtv_id`.
+/// * `config` - The fuzzy stage settings.
+///
+/// # Returns
+///
+/// A `Result` containing the remaining movies and the clusters, or a `PolarsError`.
+pub fn fuzzy_dedup(
+    df: DataFrame,
+    config: &FuzzyConfig,
+) -> Result<(DataFrame, Vec<FuzzyCluster>), PolarsError> {
+    let candidates = candidates(&df)?;
+
+    // Score every pair within a block and cluster the similar ones
+    let mut parents = (0..candidates.len()).collect::<Vec<_>>();
+    let mut scores = vec![f64::INFINITY; candidates.len()];
+    for blocking in 0..2 {
+        let key = |i: usize| (candidates[i].year, &candidates[i].blocks[blocking]);
+        let mut order = (0..candidates.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| key(i));
+
+        for block in order.chunk_by(|&i, &j| key(i) == key(j)) {
+            for (n, &i) in block.iter().enumerate() {
+                for &j in &block[n + 1..] {
+                    // A pair within one cluster does not join it, so it must not lower its score,
+                    // nor be scored again when both blockings put it in a block
+                    let (a, b) = (find(&mut parents, i), find(&mut parents, j));
+                    if a == b {
+                        continue;
+                    }
+                    let score = similarity(&candidates[i], &candidates[j]);
+                    if score >= config.threshold {
+                        scores[a] = scores[a].min(scores[b]).min(score);
+                        parents[b] = a;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); candidates.len()];
+    for i in 0..candidates.len() {
+        let root = find(&mut parents, i);
+        members[root].push(i);
+    }
+
+    let mut clusters = Vec::new();
+    let mut merges = Vec::new();
+    for (root, members) in members.into_iter().enumerate() {
+        if members.len() < 2 {
+            continue;
+        }
+        let movies = members
+            .iter()
+            .map(|&i| candidates[i].member.clone())
+            .collect::<Vec<_>>();
+
+        let merged_into = config
+            .auto_merge
+            .filter(|&auto_merge| scores[root] >= auto_merge)
+            .and_then(|_| {
+                movies
+                    .iter()
+                    .max_by_key(|movie| (movie.total_votes, std::cmp::Reverse(movie.filmtv_id)))
+                    .map(|movie| movie.filmtv_id)
+            });
+        if let Some(survivor) = merged_into {
+            let ids = movies.iter().map(|movie| movie.filmtv_id).collect();
+            merges.push((survivor, ids));
+        }
+
+        clusters.push(FuzzyCluster {
+            year: candidates[root].year,
+            movies,
+            score: scores[root],
+            merged_into,
+        });
+    }
+
+    if merges.is_empty() {
+        return Ok((df, clusters));
+    }
+
+    Ok((merge_clusters(&df, &merges)?, clusters))
+}
+
+/// Merges the movies of auto-merged clusters.
+///
+/// # Arguments
+///
+/// * `df` - A cleaned `DataFrame` with one movie per `filmtv_id`.
+/// * `merges` - The `filmtv_id` of each cluster's survivor, and the `filmtv_id`s of all of the
+///   cluster's movies.
+///
+/// # Returns
+///
+/// A `Result` containing the movies with every cluster folded into its survivor, at the survivor's
+/// position, or a `PolarsError`.
+fn merge_clusters(df: &DataFrame, merges: &[(i64, Vec<i64>)]) -> Result<DataFrame, PolarsError> {
+    let mut movies = transform::dataframe_to_movies(df)?
+        .into_iter()
+        .map(Some)
+        .collect::<Vec<_>>();
+    let positions = movies
+        .iter()
+        .enumerate()
+        .filter_map(|(i, movie)| Some((movie.as_ref()?.filmtv_id?, i)))
+        .collect::<HashMap<_, _>>();
+
+    for (survivor, ids) in merges {
+        // Later movies win in `merge`, so the survivor is folded in last
+        let merged = ids
+            .iter()
+            .filter(|&id| id != survivor)
+            .chain([survivor])
+            .filter_map(|id| movies[positions[id]].take())
+            .reduce(merge);
+        movies[positions[survivor]] = merged;
+    }
+
+    transform::movies_to_dataframe(movies.into_iter().flatten().collect())
+}
+
+/// Writes fuzzy duplicate clusters as pretty-printed JSON.
+///
+/// # Arguments
+///
+/// * `clusters` - The clusters to write.
+/// * `path` - The file to write.
+///
+/// # Returns
+///
+/// A `Result` indicating success or an `io::Error`.
+pub fn write_clusters(clusters: &[FuzzyCluster], path: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, clusters)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn movie(filmtv_id: i64, line: u64, title: Option<&str>, total_votes: Option<i64>) -> Movie {
+        Movie {
+            filmtv_id: Some(filmtv_id),
+            title: title.map(str::to_string),
+            total_votes,
+            source: Some(Provenance {
+                file: PathBuf::from("movies.csv"),
+                line,
+            }),
+            ..Movie::default()
+        }
+    }
+
+    fn movies() -> Vec<Movie> {
+        vec![
+            movie(1, 2, Some("First"), Some(10)),
+            movie(2, 3, Some("Other"), None),
+            movie(1, 4, None, Some(30)),
+            movie(1, 5, Some("Last"), Some(20)),
+        ]
+    }
+
+    fn lines(movies: &[Movie]) -> Vec<u64> {
+        movies
+            .iter()
+            .map(|movie| movie.source.as_ref().unwrap().line)
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_duplicates_keeps_one_movie_per_id() {
+        let (kept, duplicates) = resolve_duplicates(movies(), DuplicatePolicy::KeepFirst).unwrap();
+        assert_eq!(lines(&kept), vec![2, 3]);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].filmtv_id, 1);
+        assert_eq!(duplicates[0].occurrences.len(), 3);
+        assert_eq!(duplicates[0].kept, Some(0));
+
+        let (kept, _) = resolve_duplicates(movies(), DuplicatePolicy::KeepLast).unwrap();
+        assert_eq!(lines(&kept), vec![5, 3]);
+
+        let (kept, duplicates) = resolve_duplicates(movies(), DuplicatePolicy::MostVotes).unwrap();
+        assert_eq!(lines(&kept), vec![4, 3]);
+        assert_eq!(duplicates[0].kept, Some(1));
+    }
+
+    #[test]
+    fn test_resolve_duplicates_merges_non_null_fields() {
+        let (kept, duplicates) = resolve_duplicates(movies(), DuplicatePolicy::Merge).unwrap();
+
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].title, Some("Last".to_string()));
+        assert_eq!(kept[0].total_votes, Some(20));
+        assert_eq!(duplicates[0].kept, None);
+
+        let earlier = movie(3, 1, Some("Title"), Some(5));
+        let later = movie(3, 2, Some(" "), None);
+        let (kept, _) = resolve_duplicates(vec![earlier, later], DuplicatePolicy::Merge).unwrap();
+        assert_eq!(kept[0].title, Some("Title".to_string()));
+        assert_eq!(kept[0].total_votes, Some(5));
+    }
+
+    #[test]
+    fn test_resolve_duplicates_fail() {
+        let error = resolve_duplicates(movies(), DuplicatePolicy::Fail).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "1 filmtv_id(s) occur more than once, e.g. 1 at movies.csv:2, movies.csv:4, movies.csv:5"
+        );
+
+        let (kept, duplicates) = resolve_duplicates(
+            vec![movie(1, 2, None, None), movie(2, 3, None, None)],
+            DuplicatePolicy::Fail,
+        )
+        .unwrap();
+        assert_eq!(kept.len(), 2);
+        assert!(duplicates.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_duplicates_lazy_matches_eager() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("movies.csv");
+        let columns = transform::movie_schema()
+            .iter_names()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        let mut csv = columns.join(",") + "\n";
+        for (filmtv_id, title, total_votes) in [
+            ("1", "First", "10"),
+            ("2", "Other", ""),
+            ("1", "Middle", "30"),
+            ("1", "Last", "20"),
+            ("3", "Title", "5"),
+            ("3", " ", ""),
+        ] {
+            let mut row = vec![""; columns.len()];
+            row[0] = filmtv_id;
+            row[1] = title;
+            row[11] = total_votes;
+            csv += &(row.join(",") + "\n");
+        }
+        std::fs::write(&path, csv).unwrap();
+
+        for policy in [
+            DuplicatePolicy::KeepFirst,
+            DuplicatePolicy::KeepLast,
+            DuplicatePolicy::MostVotes,
+            DuplicatePolicy::Merge,
+        ] {
+            let movies = crate::ingestion::ingest_csv(&path, &Default::default()).unwrap();
+            let (kept, duplicates) = resolve_duplicates(movies, policy).unwrap();
+            let lf = crate::ingestion::scan_csv(std::slice::from_ref(&path), &Default::default())
+                .unwrap();
+            let collected = resolve_duplicates_lazy(lf, policy).collect().unwrap();
+            let (lazy_kept, lazy_duplicates) = take_duplicates(collected, policy).unwrap();
+
+            assert_eq!(lazy_duplicates, duplicates, "{}", policy);
+            let ids = kept.iter().map(|movie| movie.filmtv_id).collect::<Vec<_>>();
+            let titles = kept
+                .iter()
+                .map(|movie| movie.title.clone())
+                .collect::<Vec<_>>();
+            let votes = kept
+                .iter()
+                .map(|movie| movie.total_votes)
+                .collect::<Vec<_>>();
+            let lazy_column = |name| lazy_kept.column(name).unwrap().clone();
+            assert_eq!(
+                lazy_column("filmtv_id").i64().unwrap().to_vec(),
+                ids,
+                "{}",
+                policy
+            );
+            assert_eq!(
+                lazy_column("title")
+                    .str()
+                    .unwrap()
+                    .into_iter()
+                    .map(|title| title.map(str::to_string))
+                    .collect::<Vec<_>>(),
+                titles,
+                "{}",
+                policy
+            );
+            assert_eq!(
+                lazy_column("total_votes").i64().unwrap().to_vec(),
+                votes,
+                "{}",
+                policy
+            );
+        }
+
+        let lf = crate::ingestion::scan_csv(&[path], &Default::default()).unwrap();
+        let collected = resolve_duplicates_lazy(lf, DuplicatePolicy::Fail)
+            .collect()
+            .unwrap();
+        assert!(take_duplicates(collected, DuplicatePolicy::Fail).is_err());
+    }
+
+    fn film(
+        filmtv_id: i64,
+        title: &str,
+        year: i64,
+        directors: Option<&str>,
+        total_votes: Option<i64>,
+    ) -> Movie {
+        Movie {
+            filmtv_id: Some(filmtv_id),
+            title: Some(title.to_string()),
+            year: Some(year),
+            directors: directors.map(str::to_string),
+            total_votes,
+            ..Movie::default()
+        }
+    }
+
+    fn films() -> DataFrame {
+        let coppola = Some("Francis Ford Coppola");
+        let mut films = vec![
+            film(1, "The Godfather", 1972, coppola, Some(100)),
+            film(
+                2,
+                "Godfather, The",
+                1972,
+                Some("francis ford  coppola"),
+                Some(300),
+            ),
+            film(3, "The Godfathers", 1972, Some("Someone Else"), Some(5)),
+            film(4, "La dolce vita", 1960, None, None),
+            film(5, "Dolce vita, La", 1960, None, Some(1)),
+            film(6, "The Godfather", 1974, coppola, Some(50)),
+            film(7, "Il padrino", 1972, coppola, Some(10)),
+        ];
+        // Only the movies that are merged away know the genre and duration
+        films[0].genre = Some("Crime".to_string());
+        films[3].duration = Some(174);
+        transform::movies_to_dataframe(films).unwrap()
+    }
+
+    #[test]
+    fn test_similarity() {
+        let candidates = candidates(&films()).unwrap();
+        let by_id = |id: i64| {
+            candidates
+                .iter()
+                .find(|candidate| candidate.member.filmtv_id == id)
+                .unwrap()
+        };
+
+        assert_eq!(by_id(2).title, "godfather");
+        assert_eq!(by_id(4).title, "dolce vita");
+        assert_eq!(by_id(1).blocks, ["god", "her"]);
+        assert_eq!(similarity(by_id(1), by_id(2)), 1.0);
+        assert!(similarity(by_id(1), by_id(3)) < 0.85);
+        assert_eq!(edit_distance("godfather", "godfathers"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn test_fuzzy_dedup_clusters_and_merges() {
+        let config = FuzzyConfig::default();
+        let (kept, clusters) = fuzzy_dedup(films(), &config).unwrap();
+
+        assert_eq!(kept.height(), 7);
+        let ids = clusters
+            .iter()
+            .map(|cluster| {
+                cluster
+                    .movies
+                    .iter()
+                    .map(|movie| movie.filmtv_id)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![vec![4, 5], vec![1, 2]]);
+        assert_eq!(clusters[1].year, 1972);
+        assert_eq!(clusters[1].merged_into, None);
+
+        let config = FuzzyConfig {
+            auto_merge: Some(0.95),
+            ..FuzzyConfig::default()
+        };
+        let (kept, clusters) = fuzzy_dedup(films(), &config).unwrap();
+
+        assert_eq!(clusters[0].merged_into, Some(5));
+        assert_eq!(clusters[1].merged_into, Some(2));
+        assert_eq!(
+            kept.column("filmtv_id").unwrap().i64().unwrap().to_vec(),
+            vec![Some(2), Some(3), Some(5), Some(6), Some(7)]
+        );
+
+        // The survivors keep their own fields and take the missing ones from the merged movies
+        let kept = transform::dataframe_to_movies(&kept).unwrap();
+        assert_eq!(kept[0].title.as_deref(), Some("Godfather, The"));
+        assert_eq!(kept[0].genre.as_deref(), Some("Crime"));
+        assert_eq!(kept[2].title.as_deref(), Some("Dolce vita, La"));
+        assert_eq!(kept[2].duration, Some(174));
+    }
+
+    #[test]
+    fn test_fuzzy_dedup_keeps_sequels_apart() {
+        let df = transform::movies_to_dataframe(vec![
+            film(1, "Rocky", 1976, None, None),
+            film(2, "Rocky I", 1976, None, None),
+            film(3, "Kill Bill: Part I", 2003, None, None),
+            film(4, "Kill Bill: Part II", 2003, None, None),
+            film(5, "Casablanca", 1942, None, None),
+            film(6, "Kasablanca", 1942, None, None),
+            film(7, "Avventura, L'", 1960, None, None),
+            film(8, "L'avventura", 1960, None, None),
+        ])
+        .unwrap();
+        let candidates = candidates(&df).unwrap();
+        let by_id = |id: i64| {
+            candidates
+                .iter()
+                .find(|candidate| candidate.member.filmtv_id == id)
+                .unwrap()
+        };
+        assert_eq!(by_id(2).title, "rocky i");
+        assert_eq!(by_id(7).title, "avventura");
+        assert_eq!(similarity(by_id(3), by_id(4)), 0.0);
+
+        let (_, clusters) = fuzzy_dedup(df, &FuzzyConfig::default()).unwrap();
+        let ids = clusters
+            .iter()
+            .map(|cluster| {
+                cluster
+                    .movies
+                    .iter()
+                    .map(|movie| movie.filmtv_id)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        // "Kasablanca" is only blocked with "Casablanca" on the end of its title
+        assert_eq!(ids, vec![vec![5, 6], vec![7, 8]]);
+    }
+
+    #[test]
+    fn test_fuzzy_cluster_score_ignores_pairs_within_the_cluster() {
+        let df = transform::movies_to_dataframe(vec![
+            film(1, "Godfathers", 1972, None, None),
+            film(2, "Godfather", 1972, None, None),
+            film(3, "Godfathersx", 1972, None, None),
+        ])
+        .unwrap();
+        let candidates = candidates(&df).unwrap();
+        let score = |i: usize, j: usize| similarity(&candidates[i], &candidates[j]);
+        // 1 joins 2 and then 3; the weaker pair 2-3 is scored once both are in the cluster
+        assert!(score(1, 2) < score(0, 1).min(score(0, 2)));
+        assert!(score(1, 2) >= 0.8);
+
+        let config = FuzzyConfig {
+            threshold: 0.8,
+            ..FuzzyConfig::default()
+        };
+        let (_, clusters) = fuzzy_dedup(df, &config).unwrap();
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].score, score(0, 1).min(score(0, 2)));
+    }
+
+    #[test]
+    fn test_validate_fuzzy_thresholds() {
+        let config = |threshold, auto_merge| DuplicateConfig {
+            fuzzy: Some(FuzzyConfig {
+                threshold,
+                auto_merge,
+                ..FuzzyConfig::default()
+            }),
+            ..DuplicateConfig::default()
+        };
+
+        assert!(config(0.8, Some(0.9)).validate().is_ok());
+        assert!(config(0.0, None).validate().is_err());
+        assert!(config(0.9, Some(0.8)).validate().is_err());
+    }
+}
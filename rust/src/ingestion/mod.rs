@@ -1,74 +1,930 @@
-//! This module provides functionality to ingest movie data from a CSV file.
-//! It includes the `ingest_csv` function which reads a CSV file and converts it into a vector of `Movie` structs.
+//! This module provides functionality to ingest movie data from one or more CSV files.
+//! It includes the `ingest_csv` function which reads a CSV file and converts it into a vector of `Movie` structs,
+//! the `ingest_csv_batches` function which streams the file as fixed-size batches of `Movie` structs,
+//! and the `ingest_files_tolerant` function which quarantines bad rows instead of failing the whole ingest,
+//! or streams them with `ingest_files_tolerant_batches`.
+//! Multiple files can be selected with `resolve_inputs` and ingested with `ingest_files` or
+//! `ingest_files_batches`. Every `Movie` is tagged with the file and line it was read from.
+//! `scan_csv` instead returns a Polars `LazyFrame` over the files, for the lazy transform path.
+//! Every reader renames the input columns with a `ColumnMapping`, and the `[input]` section of
+//! the configuration file is read into an `InputConfig`.
 
+use std::collections::{BTreeMap, VecDeque};
 use std::fs::File;
-use std::io::{self};
-use std::path::Path;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::models::{IngestionError, Movie};
+use polars::io::RowIndex;
+use polars::prelude::{
+    col, concat, lit, DataType, Field, LazyCsvReader, LazyFileListReader, LazyFrame, PolarsResult,
+    Schema, UnionArgs,
+};
+use serde::Deserialize;
+
+use crate::config::{self, ConfigError};
+use crate::metrics;
+use crate::models::{IngestReport, IngestionError, Movie, Provenance, RejectedRow};
+use crate::retry;
+use crate::transform;
 use crate::utils::parse_field;
 
+/// Default number of movies per batch when streaming a CSV file.
+pub const DEFAULT_BATCH_SIZE: usize = 10_000;
+
+/// Struct mapping the column names of the input files to the columns of a `Movie`, from the
+/// `[input.columns]` section of the configuration file. Columns that are not mapped keep their
+/// name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct ColumnMapping(BTreeMap<String, String>);
+
+impl ColumnMapping {
+    /// Returns the `Movie` column an input column is read into.
+    pub fn target<'a>(&'a self, source: &'a str) -> &'a str {
+        self.0.get(source).map_or(source, String::as_str)
+    }
+
+    /// Returns the input column a `Movie` column is read from.
+    pub fn source<'a>(&'a self, target: &'a str) -> &'a str {
+        self.0
+            .iter()
+            .find(|(_, mapped)| mapped.as_str() == target)
+            .map_or(target, |(source, _)| source.as_str())
+    }
+
+    /// Renames the columns of a CSV header row.
+    fn rename_headers(&self, headers: &csv::StringRecord) -> csv::StringRecord {
+        headers.iter().map(|header| self.target(header)).collect()
+    }
+
+    /// Checks that every input column is mapped to a distinct `Movie` column.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `ConfigError` naming the offending mapping.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let schema = transform::movie_schema();
+        for (index, (source, target)) in self.0.iter().enumerate() {
+            let duplicate = self.0.values().take(index).any(|other| other == target);
+            if !schema.contains(target) || duplicate {
+                return Err(ConfigError::InvalidValue {
+                    key: format!("input.columns.{}", source),
+                    value: target.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: Into<String>, T: Into<String>> FromIterator<(S, T)> for ColumnMapping {
+    fn from_iter<I: IntoIterator<Item = (S, T)>>(iter: I) -> Self {
+        ColumnMapping(
+            iter.into_iter()
+                .map(|(source, target)| (source.into(), target.into()))
+                .collect(),
+        )
+    }
+}
+
+/// Struct representing the `[input]` section of the configuration file.
+///
+/// The `--input` flag and the `PIPELINE_*` environment variables take precedence over the
+/// section, see `InputConfig::from_env`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InputConfig {
+    /// Input files, directories or glob patterns.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Names of input columns that differ from the `Movie` columns.
+    #[serde(default)]
+    pub columns: ColumnMapping,
+    /// Number of movies per batch; setting it enables streaming mode.
+    pub batch_size: Option<usize>,
+    /// Maximum fraction of rejected rows; setting it enables tolerant mode.
+    pub max_error_rate: Option<f64>,
+    /// File rejected rows are written to; setting it enables tolerant mode.
+    pub dead_letter_path: Option<PathBuf>,
+}
+
+impl InputConfig {
+    /// Reads the `PIPELINE_BATCH_SIZE`, `PIPELINE_MAX_ERROR_RATE` and `PIPELINE_DEAD_LETTER_PATH`
+    /// environment variables.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `InputConfig` or a `ConfigError` if a numeric variable is invalid.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Ok(InputConfig {
+            batch_size: config::env_parsed("PIPELINE_BATCH_SIZE")?,
+            max_error_rate: config::env_parsed("PIPELINE_MAX_ERROR_RATE")?,
+            dead_letter_path: config::env_var("PIPELINE_DEAD_LETTER_PATH").map(PathBuf::from),
+            ..InputConfig::default()
+        })
+    }
+
+    /// Merges two configurations, with settings from `other` taking precedence. Column mappings
+    /// are combined.
+    pub fn merge(self, other: InputConfig) -> InputConfig {
+        let mut columns = self.columns;
+        columns.0.extend(other.columns.0);
+        InputConfig {
+            paths: if other.paths.is_empty() {
+                self.paths
+            } else {
+                other.paths
+            },
+            columns,
+            batch_size: other.batch_size.or(self.batch_size),
+            max_error_rate: other.max_error_rate.or(self.max_error_rate),
+            dead_letter_path: other.dead_letter_path.or(self.dead_letter_path),
+        }
+    }
+
+    /// Checks the batch size, the maximum error rate and the column mapping.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `ConfigError` naming the offending setting.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(0) = self.batch_size {
+            return Err(ConfigError::InvalidValue {
+                key: "input.batch_size".to_string(),
+                value: "0".to_string(),
+            });
+        }
+        if let Some(rate) = self.max_error_rate {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(ConfigError::InvalidValue {
+                    key: "input.max_error_rate".to_string(),
+                    value: rate.to_string(),
+                });
+            }
+        }
+
+        self.columns.validate()
+    }
+
+    /// Returns the tolerant mode options, or `None` if neither a maximum error rate nor a
+    /// dead-letter file has been configured.
+    pub fn tolerant_options(&self) -> Option<TolerantOptions> {
+        if self.max_error_rate.is_none() && self.dead_letter_path.is_none() {
+            return None;
+        }
+
+        let defaults = TolerantOptions::default();
+        Some(TolerantOptions {
+            max_error_rate: self.max_error_rate.unwrap_or(defaults.max_error_rate),
+            dead_letter_path: self.dead_letter_path.clone(),
+            ..defaults
+        })
+    }
+}
+
 /// Ingests movie data from a CSV file and converts it into a vector of `Movie` structs.
 ///
 /// # Arguments
 ///
 /// * `path` - A path to the CSV file.
+/// * `columns` - The mapping of the file's column names to the `Movie` columns.
 ///
 /// # Returns
 ///
 /// A `Result` containing a vector of `Movie` structs or an `IngestionError`.
-pub fn ingest_csv<P: AsRef<Path>>(path: P) -> Result<Vec<Movie>, IngestionError> {
-    let file = File::open(path)?;
-    let reader = io::BufReader::new(file);
-    let mut csv_reader = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .from_reader(reader);
+pub fn ingest_csv<P: AsRef<Path>>(
+    path: P,
+    columns: &ColumnMapping,
+) -> Result<Vec<Movie>, IngestionError> {
+    let path = path.as_ref();
+    let mut csv_reader = open_csv(path)?;
+    let headers = columns.rename_headers(csv_reader.headers()?);
 
-    let headers = csv_reader.headers()?.clone();
-
-    let movies: Result<Vec<Movie>, IngestionError> = csv_reader
+    let movies = csv_reader
         .records()
         .enumerate()
-        .map(|(index, record)| {
-            let record = record?;
-            let mut movie_data = serde_json::Map::new();
-
-            // Parse each field in the record and add it to movie_data
-            for (i, field) in record.iter().enumerate() {
-                let header = headers.get(i).unwrap_or("unknown");
-                let value = match header {
-                    "filmtv_id" | "year" | "duration" | "total_votes" | "humor" | "rhythm"
-                    | "effort" | "tension" | "erotism" => {
-                        if let Some(num) = parse_field::<i64>(field) {
-                            serde_json::Value::Number(num.into())
-                        } else {
-                            serde_json::Value::Null
-                        }
-                    }
-                    "avg_vote" | "critics_vote" | "public_vote" => {
-                        if let Some(num) = parse_field::<f64>(field) {
-                            serde_json::Value::Number(serde_json::Number::from_f64(num).unwrap())
-                        } else {
-                            serde_json::Value::Null
-                        }
-                    }
-                    _ => serde_json::Value::String(field.to_string()),
+        .map(|(index, record)| record_to_movie(&headers, path, index, record?, false))
+        .collect::<Result<Vec<Movie>, IngestionError>>()?;
+    metrics::ROWS_READ
+        .with_label_values(&["csv"])
+        .inc_by(movies.len() as u64);
+
+    Ok(movies)
+}
+
+/// Resolves an input specification into the list of CSV files to ingest.
+///
+/// The input may be a single file, a directory (all `*.csv` files directly inside it are used)
+/// or a glob pattern such as `exports/*.csv`.
+///
+/// # Arguments
+///
+/// * `input` - A file path, directory path or glob pattern.
+///
+/// # Returns
+///
+/// A `Result` containing the matching file paths in sorted order, or an `IngestionError` if the
+/// pattern is invalid or matches no files.
+pub fn resolve_inputs(input: &str) -> Result<Vec<PathBuf>, IngestionError> {
+    resolve_inputs_with_extension(input, "csv")
+}
+
+/// Resolves an input specification like `resolve_inputs`, but a directory selects the files with
+/// the given extension instead of the `*.csv` files.
+///
+/// # Arguments
+///
+/// * `input` - A file path, directory path or glob pattern.
+/// * `extension` - The extension of the files selected in a directory, without the dot.
+///
+/// # Returns
+///
+/// A `Result` containing the matching file paths in sorted order, or an `IngestionError` if the
+/// pattern is invalid or matches no files.
+pub fn resolve_inputs_with_extension(
+    input: &str,
+    extension: &str,
+) -> Result<Vec<PathBuf>, IngestionError> {
+    let path = Path::new(input);
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let pattern = if path.is_dir() {
+        path.join(format!("*.{}", extension))
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        input.to_string()
+    };
+
+    let mut paths = glob::glob(&pattern)?
+        .map(|entry| entry.map_err(|e| IngestionError::IoError(e.into())))
+        .filter(|entry| entry.as_ref().map_or(true, |path| path.is_file()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+
+    if paths.is_empty() {
+        return Err(IngestionError::NoInputFiles(input.to_string()));
+    }
+
+    Ok(paths)
+}
+
+/// Ingests movie data from several CSV files, reading the files in parallel.
+///
+/// # Arguments
+///
+/// * `paths` - The CSV files to ingest.
+/// * `columns` - The mapping of the files' column names to the `Movie` columns.
+///
+/// # Returns
+///
+/// A `Result` containing the `Movie` structs of all files, in the order of `paths`, or the first
+/// `IngestionError` encountered.
+pub fn ingest_files(
+    paths: &[PathBuf],
+    columns: &ColumnMapping,
+) -> Result<Vec<Movie>, IngestionError> {
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut movies = Vec::new();
+
+    for chunk in paths.chunks(workers) {
+        let results = std::thread::scope(|scope| {
+            let handles = chunk
+                .iter()
+                .map(|path| scope.spawn(move || ingest_csv(path, columns)))
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("ingestion thread panicked"))
+                .collect::<Vec<_>>()
+        });
+
+        for result in results {
+            movies.extend(result?);
+        }
+    }
+
+    Ok(movies)
+}
+
+/// Scans movie data from several CSV files into a single `LazyFrame`, without reading them.
+///
+/// The files are parsed with the explicit schema of `transform::movie_schema`, so no schema is
+/// inferred and only the columns and rows the query needs are read when it is collected. Every
+/// file must have all columns of the schema, in any order, under their mapped names. Like `ingest_csv`, fields that cannot
+/// be parsed as their column's type are read as NULL.
+///
+/// Every row is tagged with the `file` it was read from and its `line`, which is the record index
+/// plus two for the header row and the one-based numbering. Unlike the line `ingest_csv` records,
+/// it does not account for quoted fields spanning several lines.
+///
+/// # Arguments
+///
+/// * `paths` - The CSV files to scan.
+/// * `columns` - The mapping of the files' column names to the `Movie` columns.
+///
+/// # Returns
+///
+/// A `PolarsResult` containing a `LazyFrame` with the columns of `transform::movie_schema` and
+/// the `file` and `line` columns, in the order of `paths`.
+pub fn scan_csv(paths: &[PathBuf], columns: &ColumnMapping) -> PolarsResult<LazyFrame> {
+    let movie_schema = transform::movie_schema();
+    let schema = Arc::new(
+        movie_schema
+            .iter()
+            .map(|(name, dtype)| Field::new(columns.source(name), dtype.clone()))
+            .collect::<Schema>(),
+    );
+    let frames = paths
+        .iter()
+        .map(|path| {
+            let lf = LazyCsvReader::new(path)
+                .with_has_header(true)
+                .with_infer_schema_length(Some(0))
+                .with_dtype_overwrite(Some(schema.clone()))
+                .with_ignore_errors(true)
+                .with_row_index(Some(RowIndex {
+                    name: Arc::from(transform::SOURCE_LINE),
+                    offset: 2,
+                }))
+                .finish()?;
+
+            let mut selected = movie_schema
+                .iter_names()
+                .map(|name| col(columns.source(name)).alias(name))
+                .collect::<Vec<_>>();
+            selected.push(lit(path.display().to_string()).alias(transform::SOURCE_FILE));
+            selected.push(col(transform::SOURCE_LINE).cast(DataType::UInt64));
+            Ok(lf.select(selected))
+        })
+        .collect::<PolarsResult<Vec<_>>>()?;
+
+    concat(frames, UnionArgs::default())
+}
+
+/// Streams movie data from a CSV file as batches of `Movie` structs.
+///
+/// Only one batch is held in memory at a time, so arbitrarily large files can be
+/// processed with bounded memory.
+///
+/// # Arguments
+///
+/// * `path` - A path to the CSV file.
+/// * `columns` - The mapping of the file's column names to the `Movie` columns.
+/// * `batch_size` - The maximum number of movies per batch. A value of `0` is treated as `1`.
+///
+/// # Returns
+///
+/// A `Result` containing a `MovieBatches` iterator or an `IngestionError` if the file cannot be opened.
+pub fn ingest_csv_batches<P: AsRef<Path>>(
+    path: P,
+    columns: &ColumnMapping,
+    batch_size: usize,
+) -> Result<MovieBatches, IngestionError> {
+    let path = path.as_ref();
+    let mut csv_reader = open_csv(path)?;
+    let headers = columns.rename_headers(csv_reader.headers()?);
+
+    Ok(MovieBatches {
+        records: csv_reader.into_records(),
+        headers,
+        path: path.to_path_buf(),
+        batch_size: batch_size.max(1),
+        next_index: 0,
+    })
+}
+
+/// Iterator over batches of `Movie` structs read lazily from a CSV file.
+///
+/// Created by [`ingest_csv_batches`]. Each item is either a full batch of `batch_size`
+/// movies (the last batch may be shorter) or the error that interrupted the batch.
+pub struct MovieBatches {
+    records: csv::StringRecordsIntoIter<io::BufReader<File>>,
+    headers: csv::StringRecord,
+    path: PathBuf,
+    batch_size: usize,
+    next_index: usize,
+}
+
+impl Iterator for MovieBatches {
+    type Item = Result<Vec<Movie>, IngestionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut batch = Vec::with_capacity(self.batch_size.min(DEFAULT_BATCH_SIZE));
+
+        for record in self.records.by_ref().take(self.batch_size) {
+            let index = self.next_index;
+            self.next_index += 1;
+
+            match record
+                .map_err(IngestionError::from)
+                .and_then(|record| record_to_movie(&self.headers, &self.path, index, record, false))
+            {
+                Ok(movie) => batch.push(movie),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        if batch.is_empty() {
+            None
+        } else {
+            metrics::ROWS_READ
+                .with_label_values(&["csv"])
+                .inc_by(batch.len() as u64);
+            Some(Ok(batch))
+        }
+    }
+}
+
+/// Streams movie data from several CSV files as batches of `Movie` structs.
+///
+/// The files are read one after another; a batch never spans two files.
+///
+/// # Arguments
+///
+/// * `paths` - The CSV files to ingest.
+/// * `columns` - The mapping of the files' column names to the `Movie` columns.
+/// * `batch_size` - The maximum number of movies per batch.
+///
+/// # Returns
+///
+/// An iterator of batches of `Movie` structs, or the errors that interrupted them.
+pub fn ingest_files_batches(
+    paths: Vec<PathBuf>,
+    columns: ColumnMapping,
+    batch_size: usize,
+) -> impl Iterator<Item = Result<Vec<Movie>, IngestionError>> {
+    paths.into_iter().flat_map(
+        move |path| match ingest_csv_batches(&path, &columns, batch_size) {
+            Ok(batches) => {
+                Box::new(batches) as Box<dyn Iterator<Item = Result<Vec<Movie>, IngestionError>>>
+            }
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        },
+    )
+}
+
+/// Options controlling tolerant ingestion.
+#[derive(Debug, Clone)]
+pub struct TolerantOptions {
+    /// The maximum fraction of rows (between `0.0` and `1.0`) that may be rejected before the
+    /// ingest is aborted.
+    pub max_error_rate: f64,
+    /// Where to write rejected rows. Files ending in `.ndjson` or `.jsonl` are written as
+    /// newline-delimited JSON, anything else as CSV.
+    pub dead_letter_path: Option<PathBuf>,
+    /// The number of rows that must have been read before the error rate is checked after each
+    /// batch. Below it, the error rate is only checked once every file has been read, so a few
+    /// bad rows at the start of a file do not abort the ingest.
+    pub min_rows: usize,
+}
+
+impl Default for TolerantOptions {
+    fn default() -> Self {
+        TolerantOptions {
+            max_error_rate: 0.05,
+            dead_letter_path: None,
+            min_rows: 1000,
+        }
+    }
+}
+
+/// Ingests movie data from several CSV files, keeping good rows and quarantining rows that
+/// cannot be parsed.
+///
+/// Rejected rows are written to the dead-letter file configured in `options`, together with
+/// their file, line number and the reason they were rejected. The files are read one after
+/// another and share a single dead-letter file; the maximum error rate is checked against the
+/// combined counts of all files.
+///
+/// # Arguments
+///
+/// * `paths` - The CSV files to ingest.
+/// * `columns` - The mapping of the files' column names to the `Movie` columns.
+/// * `options` - The tolerant ingestion options.
+///
+/// # Returns
+///
+/// A `Result` containing the accepted `Movie` structs and an `IngestReport`, or an `IngestionError`
+/// if a file cannot be read or the rejected rows exceed `options.max_error_rate`.
+pub fn ingest_files_tolerant(
+    paths: &[PathBuf],
+    columns: &ColumnMapping,
+    options: &TolerantOptions,
+) -> Result<(Vec<Movie>, IngestReport), IngestionError> {
+    let mut batches = ingest_files_tolerant_batches(
+        paths.to_vec(),
+        columns.clone(),
+        DEFAULT_BATCH_SIZE,
+        options,
+    )?;
+
+    let mut movies = Vec::new();
+    for batch in batches.by_ref() {
+        movies.extend(batch?);
+    }
+
+    Ok((movies, batches.report))
+}
+
+/// Streams movie data from several CSV files in tolerant mode as batches of `Movie` structs.
+///
+/// Rows are accepted and rejected as by [`ingest_files_tolerant`]. The maximum error rate is
+/// checked after every batch once `options.min_rows` rows have been read, and after the last
+/// batch; if it is exceeded the last item is the `IngestionError::ErrorRateExceeded` error, and
+/// the remaining rows are not read.
+///
+/// # Arguments
+///
+/// * `paths` - The CSV files to ingest.
+/// * `columns` - The mapping of the files' column names to the `Movie` columns.
+/// * `batch_size` - The maximum number of movies per batch. A value of `0` is treated as `1`.
+/// * `options` - The tolerant ingestion options.
+///
+/// # Returns
+///
+/// A `Result` containing a `TolerantBatches` iterator, or an `IngestionError` if the dead-letter
+/// file cannot be created.
+pub fn ingest_files_tolerant_batches(
+    paths: Vec<PathBuf>,
+    columns: ColumnMapping,
+    batch_size: usize,
+    options: &TolerantOptions,
+) -> Result<TolerantBatches, IngestionError> {
+    let dead_letter = match &options.dead_letter_path {
+        Some(path) => Some(DeadLetterWriter::create(path)?),
+        None => None,
+    };
+
+    Ok(TolerantBatches {
+        paths: paths.into(),
+        columns,
+        batch_size: batch_size.max(1),
+        max_error_rate: options.max_error_rate,
+        min_rows: options.min_rows,
+        dead_letter,
+        report: IngestReport::default(),
+        current: None,
+        finished: false,
+    })
+}
+
+/// Iterator over batches of `Movie` structs read lazily from CSV files in tolerant mode.
+///
+/// Created by [`ingest_files_tolerant_batches`]. Unlike [`MovieBatches`], a batch may span two
+/// files, and rejected rows are written to the dead-letter file instead of interrupting the
+/// batch.
+pub struct TolerantBatches {
+    paths: VecDeque<PathBuf>,
+    columns: ColumnMapping,
+    batch_size: usize,
+    max_error_rate: f64,
+    min_rows: usize,
+    dead_letter: Option<DeadLetterWriter>,
+    report: IngestReport,
+    /// The file being read.
+    current: Option<TolerantReader>,
+    finished: bool,
+}
+
+impl TolerantBatches {
+    /// Returns the counts of the rows accepted and rejected so far.
+    pub fn report(&self) -> &IngestReport {
+        &self.report
+    }
+
+    /// Reads rows until a batch is full or every file has been read.
+    fn read_batch(&mut self) -> Result<Vec<Movie>, IngestionError> {
+        let mut batch = Vec::with_capacity(self.batch_size.min(DEFAULT_BATCH_SIZE));
+
+        while batch.len() < self.batch_size {
+            let Some(reader) = self.current.as_mut() else {
+                let Some(path) = self.paths.pop_front() else {
+                    break;
                 };
-                movie_data.insert(header.to_string(), value);
+                self.current = Some(TolerantReader::open(path, &self.columns)?);
+                continue;
+            };
+
+            match reader.next_row()? {
+                Some(Ok(movie)) => {
+                    batch.push(movie);
+                    self.report.accepted += 1;
+                }
+                Some(Err(rejected)) => {
+                    self.report.rejected += 1;
+                    if let Some(dead_letter) = self.dead_letter.as_mut() {
+                        dead_letter.write(&rejected)?;
+                    }
+                }
+                None => self.current = None,
             }
+        }
+
+        Ok(batch)
+    }
 
-            // Convert the JSON object to a Movie struct
-            let movie: Movie = serde_json::from_value(serde_json::Value::Object(movie_data))
-                .map_err(|e| {
-                    println!("Error parsing row {}: {:?}", index, e);
-                    IngestionError::DeserializationError(e)
-                })?;
+    /// Returns `true` if enough rows have been read to judge the error rate and it exceeds the
+    /// maximum.
+    fn error_rate_exceeded(&self) -> bool {
+        self.report.total() >= self.min_rows && self.report.error_rate() > self.max_error_rate
+    }
+
+    /// Flushes the dead-letter file, reports the counts and checks them against the maximum
+    /// error rate, once every file has been read or the ingest is aborted.
+    fn finish(&mut self) -> Result<(), IngestionError> {
+        self.finished = true;
+
+        if let Some(dead_letter) = self.dead_letter.as_mut() {
+            dead_letter.flush()?;
+        }
+        metrics::ROWS_READ
+            .with_label_values(&["csv"])
+            .inc_by(self.report.total() as u64);
+        metrics::ROWS_REJECTED
+            .with_label_values(&["parse"])
+            .inc_by(self.report.rejected as u64);
+        tracing::info!(
+            accepted = self.report.accepted,
+            rejected = self.report.rejected,
+            "Ingest report: {} accepted, {} rejected ({:.2}% error rate).",
+            self.report.accepted,
+            self.report.rejected,
+            self.report.error_rate() * 100.0
+        );
 
-            Ok(movie)
+        if self.report.error_rate() > self.max_error_rate {
+            return Err(IngestionError::ErrorRateExceeded {
+                rejected: self.report.rejected,
+                total: self.report.total(),
+                max_error_rate: self.max_error_rate,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for TolerantBatches {
+    type Item = Result<Vec<Movie>, IngestionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let batch = self.read_batch().and_then(|batch| {
+            // A batch that is not full means every file has been read
+            if batch.len() < self.batch_size || self.error_rate_exceeded() {
+                self.finish()?;
+            }
+            Ok(batch)
+        });
+
+        match batch {
+            Ok(batch) if batch.is_empty() => None,
+            Ok(batch) => Some(Ok(batch)),
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Reader of a single CSV file in tolerant mode.
+struct TolerantReader {
+    csv_reader: csv::Reader<io::BufReader<File>>,
+    headers: csv::StringRecord,
+    path: PathBuf,
+    record: csv::ByteRecord,
+    index: usize,
+}
+
+impl TolerantReader {
+    /// Opens a CSV file with a header row, accepting records with any number of fields.
+    fn open(path: PathBuf, columns: &ColumnMapping) -> Result<Self, IngestionError> {
+        let file = open_file(&path)?;
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .flexible(true)
+            .from_reader(io::BufReader::new(file));
+        let headers = columns.rename_headers(csv_reader.headers()?);
+
+        Ok(TolerantReader {
+            csv_reader,
+            headers,
+            path,
+            record: csv::ByteRecord::new(),
+            index: 0,
+        })
+    }
+
+    /// Reads the next row of the file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the accepted movie or the rejected row, `None` at the end of the
+    /// file, or an `IngestionError` if the file cannot be read.
+    fn next_row(&mut self) -> Result<Option<Result<Movie, RejectedRow>>, IngestionError> {
+        match self.csv_reader.read_byte_record(&mut self.record) {
+            Ok(false) => Ok(None),
+            Ok(true) => {
+                self.index += 1;
+                let row =
+                    parse_byte_record(&self.headers, &self.path, self.index - 1, &self.record)
+                        .map_err(|reason| RejectedRow {
+                            file: self.path.display().to_string(),
+                            line: self.record.position().map(|p| p.line()),
+                            raw_record: raw_record(&self.record),
+                            reason,
+                        });
+                Ok(Some(row))
+            }
+            Err(e) if e.is_io_error() => Err(e.into()),
+            Err(e) => {
+                self.index += 1;
+                Ok(Some(Err(RejectedRow {
+                    file: self.path.display().to_string(),
+                    line: e.position().map(|p| p.line()),
+                    raw_record: String::new(),
+                    reason: e.to_string(),
+                })))
+            }
+        }
+    }
+}
+
+/// Writer for rejected rows, in either CSV or newline-delimited JSON format.
+enum DeadLetterWriter {
+    Csv(Box<csv::Writer<File>>),
+    Ndjson(io::BufWriter<File>),
+}
+
+impl DeadLetterWriter {
+    /// Creates a dead-letter file, choosing the format from the file extension.
+    fn create(path: &Path) -> Result<Self, IngestionError> {
+        let file = File::create(path)?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ndjson") | Some("jsonl") => {
+                Ok(DeadLetterWriter::Ndjson(io::BufWriter::new(file)))
+            }
+            _ => Ok(DeadLetterWriter::Csv(Box::new(csv::Writer::from_writer(
+                file,
+            )))),
+        }
+    }
+
+    /// Appends a rejected row to the dead-letter file.
+    fn write(&mut self, row: &RejectedRow) -> Result<(), IngestionError> {
+        match self {
+            DeadLetterWriter::Csv(writer) => writer.serialize(row)?,
+            DeadLetterWriter::Ndjson(writer) => {
+                serde_json::to_writer(&mut *writer, row)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Flushes any buffered rows to disk.
+    fn flush(&mut self) -> Result<(), IngestionError> {
+        match self {
+            DeadLetterWriter::Csv(writer) => writer.flush()?,
+            DeadLetterWriter::Ndjson(writer) => writer.flush()?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Validates and converts a raw CSV record, returning the rejection reason on failure.
+fn parse_byte_record(
+    headers: &csv::StringRecord,
+    path: &Path,
+    index: usize,
+    record: &csv::ByteRecord,
+) -> Result<Movie, String> {
+    if record.len() != headers.len() {
+        return Err(format!(
+            "expected {} fields, found {}",
+            headers.len(),
+            record.len()
+        ));
+    }
+
+    let record = csv::StringRecord::from_byte_record(record.clone()).map_err(|e| e.to_string())?;
+
+    record_to_movie(headers, path, index, record, true).map_err(|e| e.to_string())
+}
+
+/// Re-encodes a raw CSV record as a single CSV line for the dead-letter file.
+fn raw_record(record: &csv::ByteRecord) -> String {
+    record
+        .iter()
+        .map(|field| {
+            let field = String::from_utf8_lossy(field);
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.into_owned()
+            }
         })
-        .collect();
+        .collect::<Vec<_>>()
+        .join(",")
+}
 
-    movies
+/// Opens a file for reading, retrying transient failures according to the pipeline's retry policy.
+pub(crate) fn open_file(path: &Path) -> Result<File, IngestionError> {
+    let operation = format!("Opening {}", path.display());
+    Ok(retry::retry(retry::policy(), &operation, || {
+        File::open(path)
+    })?)
+}
+
+/// Opens a CSV file with a header row for reading.
+fn open_csv<P: AsRef<Path>>(path: P) -> Result<csv::Reader<io::BufReader<File>>, IngestionError> {
+    let file = open_file(path.as_ref())?;
+    let reader = io::BufReader::new(file);
+
+    Ok(csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_reader(reader))
+}
+
+/// Converts a single CSV record into a `Movie` struct.
+///
+/// Numeric fields that cannot be parsed are read as NULL, unless `strict` is set: tolerant
+/// ingestion rejects a row whose numeric field is neither blank nor a number.
+///
+/// # Arguments
+///
+/// * `headers` - The header row of the CSV file.
+/// * `path` - The CSV file the record was read from, recorded as the movie's provenance.
+/// * `index` - The zero-based index of the record, used for error reporting.
+/// * `record` - The CSV record to convert.
+/// * `strict` - Whether an unparseable numeric field is an error.
+///
+/// # Returns
+///
+/// A `Result` containing a `Movie` struct or an `IngestionError`, e.g. an
+/// `IngestionError::InvalidField` in strict mode.
+fn record_to_movie(
+    headers: &csv::StringRecord,
+    path: &Path,
+    index: usize,
+    record: csv::StringRecord,
+    strict: bool,
+) -> Result<Movie, IngestionError> {
+    let mut movie_data = serde_json::Map::new();
+
+    // Parse each field in the record and add it to movie_data
+    for (i, field) in record.iter().enumerate() {
+        let header = headers.get(i).unwrap_or("unknown");
+        let number = match header {
+            "filmtv_id" | "year" | "duration" | "total_votes" | "humor" | "rhythm" | "effort"
+            | "tension" | "erotism" => {
+                Some(parse_field::<i64>(field).map(serde_json::Number::from))
+            }
+            "avg_vote" | "critics_vote" | "public_vote" => {
+                Some(parse_field::<f64>(field).and_then(serde_json::Number::from_f64))
+            }
+            _ => None,
+        };
+        let value = match number {
+            Some(Some(number)) => serde_json::Value::Number(number),
+            Some(None) if strict && !field.trim().is_empty() => {
+                return Err(IngestionError::InvalidField {
+                    column: header.to_string(),
+                    value: field.to_string(),
+                });
+            }
+            Some(None) => serde_json::Value::Null,
+            None => serde_json::Value::String(field.to_string()),
+        };
+        movie_data.insert(header.to_string(), value);
+    }
+
+    // Convert the JSON object to a Movie struct
+    let mut movie: Movie =
+        serde_json::from_value(serde_json::Value::Object(movie_data)).map_err(|e| {
+            tracing::warn!(row = index, "Error parsing row {}: {:?}", index, e);
+            IngestionError::DeserializationError(e)
+        })?;
+
+    movie.source = Some(Provenance {
+        file: path.to_path_buf(),
+        line: record.position().map_or(index as u64 + 2, |p| p.line()),
+    });
+
+    Ok(movie)
 }
 
 #[cfg(test)]
@@ -99,7 +955,7 @@ mod tests {
         )
         .unwrap();
 
-        let movies = ingest_csv(&file_path).unwrap();
+        let movies = ingest_csv(&file_path, &ColumnMapping::default()).unwrap();
 
         assert_eq!(movies.len(), 2);
         assert_eq!(movies[0].filmtv_id, Some(1));
@@ -156,7 +1012,7 @@ mod tests {
         )
         .unwrap();
 
-        let movies = ingest_csv(&file_path).unwrap();
+        let movies = ingest_csv(&file_path, &ColumnMapping::default()).unwrap();
 
         assert_eq!(movies.len(), 2);
         assert_eq!(movies[1].filmtv_id, Some(2));
@@ -175,4 +1031,405 @@ mod tests {
         assert_eq!(movies[1].tension, Some(4));
         assert_eq!(movies[1].erotism, Some(3));
     }
+
+    #[test]
+    fn test_ingest_csv_batches() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("test_movies_batches.csv");
+
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(file, "filmtv_id,title,year").unwrap();
+        writeln!(file, "1,Example Movie,2021").unwrap();
+        writeln!(file, "2,Another Movie,2020").unwrap();
+        writeln!(file, "3,Third Movie,2019").unwrap();
+
+        let batches = ingest_csv_batches(&file_path, &ColumnMapping::default(), 2)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), 2);
+        assert_eq!(batches[1].len(), 1);
+        assert_eq!(batches[0][0].filmtv_id, Some(1));
+        assert_eq!(batches[0][1].filmtv_id, Some(2));
+        assert_eq!(batches[1][0].filmtv_id, Some(3));
+        assert_eq!(batches[1][0].title, Some("Third Movie".to_string()));
+    }
+
+    #[test]
+    fn test_ingest_csv_tolerant_quarantines_bad_rows() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("test_movies_bad_rows.csv");
+        let dead_letter_path = temp_dir.path().join("rejected.ndjson");
+
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(file, "filmtv_id,title,year").unwrap();
+        writeln!(file, "1,Example Movie,2021").unwrap();
+        writeln!(file, "2,Broken Movie").unwrap();
+        writeln!(file, "3,Another Movie,2020").unwrap();
+
+        let options = TolerantOptions {
+            max_error_rate: 0.5,
+            dead_letter_path: Some(dead_letter_path.clone()),
+            ..TolerantOptions::default()
+        };
+        let (movies, report) =
+            ingest_files_tolerant(&[file_path], &ColumnMapping::default(), &options).unwrap();
+
+        assert_eq!(movies.len(), 2);
+        assert_eq!(movies[0].filmtv_id, Some(1));
+        assert_eq!(movies[1].filmtv_id, Some(3));
+        assert_eq!(report.accepted, 2);
+        assert_eq!(report.rejected, 1);
+
+        let dead_letter = std::fs::read_to_string(&dead_letter_path).unwrap();
+        let rejected: serde_json::Value =
+            serde_json::from_str(dead_letter.lines().next().unwrap()).unwrap();
+        assert_eq!(rejected["line"], 3);
+        assert_eq!(rejected["raw_record"], "2,Broken Movie");
+        assert_eq!(rejected["reason"], "expected 3 fields, found 2");
+    }
+
+    #[test]
+    fn test_ingest_csv_tolerant_rejects_unparseable_values() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("test_movies_bad_values.csv");
+        let dead_letter_path = temp_dir.path().join("rejected.ndjson");
+
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(file, "filmtv_id,title,year,avg_vote").unwrap();
+        writeln!(file, "1,Example Movie,2021,").unwrap();
+        writeln!(file, "2,Bad Year,20x1,7.5").unwrap();
+        writeln!(file, "3,Bad Vote,2020,seven").unwrap();
+
+        let options = TolerantOptions {
+            max_error_rate: 1.0,
+            dead_letter_path: Some(dead_letter_path.clone()),
+            ..TolerantOptions::default()
+        };
+        let (movies, report) =
+            ingest_files_tolerant(&[file_path], &ColumnMapping::default(), &options).unwrap();
+
+        // A blank value is still a missing value
+        assert_eq!(movies.len(), 1);
+        assert_eq!(movies[0].avg_vote, None);
+        assert_eq!(report.rejected, 2);
+
+        let reasons = std::fs::read_to_string(&dead_letter_path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["reason"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            [
+                "invalid value \"20x1\" for column year",
+                "invalid value \"seven\" for column avg_vote"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ingest_files_tolerant_batches() {
+        let temp_dir = tempdir().unwrap();
+        let first_path = temp_dir.path().join("first.csv");
+        let second_path = temp_dir.path().join("second.csv");
+        let dead_letter_path = temp_dir.path().join("rejected.csv");
+
+        let mut file = File::create(&first_path).unwrap();
+        writeln!(file, "filmtv_id,title,year").unwrap();
+        writeln!(file, "1,First Movie,2021").unwrap();
+        writeln!(file, "2,Broken Movie").unwrap();
+        writeln!(file, "3,Third Movie,2019").unwrap();
+        let mut file = File::create(&second_path).unwrap();
+        writeln!(file, "filmtv_id,title,year").unwrap();
+        writeln!(file, "4,Fourth Movie,2018").unwrap();
+
+        let options = TolerantOptions {
+            max_error_rate: 0.5,
+            dead_letter_path: Some(dead_letter_path.clone()),
+            ..TolerantOptions::default()
+        };
+        let mut batches = ingest_files_tolerant_batches(
+            vec![first_path.clone(), second_path.clone()],
+            ColumnMapping::default(),
+            2,
+            &options,
+        )
+        .unwrap();
+        let ids = batches
+            .by_ref()
+            .map(|batch| {
+                batch
+                    .unwrap()
+                    .iter()
+                    .map(|movie| movie.filmtv_id.unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        // Rejected rows do not count towards the batch size, and a batch may span two files
+        assert_eq!(ids, [vec![1, 3], vec![4]]);
+        assert_eq!(batches.report().accepted, 3);
+        assert_eq!(batches.report().rejected, 1);
+        assert_eq!(
+            std::fs::read_to_string(&dead_letter_path)
+                .unwrap()
+                .lines()
+                .count(),
+            2
+        );
+
+        // The error rate is checked after the last batch
+        let options = TolerantOptions {
+            max_error_rate: 0.1,
+            ..TolerantOptions::default()
+        };
+        let results = ingest_files_tolerant_batches(
+            vec![first_path.clone(), second_path.clone()],
+            ColumnMapping::default(),
+            2,
+            &options,
+        )
+        .unwrap()
+        .collect::<Vec<_>>();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(IngestionError::ErrorRateExceeded {
+                rejected: 1,
+                total: 4,
+                ..
+            })
+        ));
+
+        // and after every batch once enough rows have been read, without reading the rest
+        let options = TolerantOptions {
+            max_error_rate: 0.1,
+            min_rows: 3,
+            ..TolerantOptions::default()
+        };
+        let results = ingest_files_tolerant_batches(
+            vec![first_path, second_path],
+            ColumnMapping::default(),
+            2,
+            &options,
+        )
+        .unwrap()
+        .collect::<Vec<_>>();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            results[0],
+            Err(IngestionError::ErrorRateExceeded {
+                rejected: 1,
+                total: 3,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_ingest_csv_tolerant_aborts_above_max_error_rate() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("test_movies_mostly_bad.csv");
+
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(file, "filmtv_id,title,year").unwrap();
+        writeln!(file, "1,Example Movie,2021").unwrap();
+        writeln!(file, "2,Broken Movie").unwrap();
+
+        let options = TolerantOptions {
+            max_error_rate: 0.1,
+            ..TolerantOptions::default()
+        };
+
+        match ingest_files_tolerant(&[file_path], &ColumnMapping::default(), &options) {
+            Err(IngestionError::ErrorRateExceeded {
+                rejected, total, ..
+            }) => {
+                assert_eq!(rejected, 1);
+                assert_eq!(total, 2);
+            }
+            other => panic!("expected ErrorRateExceeded, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resolve_inputs() {
+        let temp_dir = tempdir().unwrap();
+        for name in ["b.csv", "a.csv", "notes.txt"] {
+            let mut file = File::create(temp_dir.path().join(name)).unwrap();
+            writeln!(file, "filmtv_id,title").unwrap();
+        }
+
+        let from_dir = resolve_inputs(temp_dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(
+            from_dir,
+            vec![temp_dir.path().join("a.csv"), temp_dir.path().join("b.csv")]
+        );
+
+        let pattern = temp_dir.path().join("b*.csv");
+        let from_glob = resolve_inputs(pattern.to_str().unwrap()).unwrap();
+        assert_eq!(from_glob, vec![temp_dir.path().join("b.csv")]);
+
+        let missing = temp_dir.path().join("missing*.csv");
+        assert!(matches!(
+            resolve_inputs(missing.to_str().unwrap()),
+            Err(IngestionError::NoInputFiles(_))
+        ));
+    }
+
+    #[test]
+    fn test_ingest_files_tags_provenance() {
+        let temp_dir = tempdir().unwrap();
+        let first_path = temp_dir.path().join("first.csv");
+        let second_path = temp_dir.path().join("second.csv");
+
+        let mut first = File::create(&first_path).unwrap();
+        writeln!(first, "filmtv_id,title").unwrap();
+        writeln!(first, "1,Example Movie").unwrap();
+        writeln!(first, "2,Another Movie").unwrap();
+        let mut second = File::create(&second_path).unwrap();
+        writeln!(second, "filmtv_id,title").unwrap();
+        writeln!(second, "3,Third Movie").unwrap();
+
+        let movies = ingest_files(
+            &[first_path.clone(), second_path.clone()],
+            &ColumnMapping::default(),
+        )
+        .unwrap();
+
+        assert_eq!(movies.len(), 3);
+        assert_eq!(
+            movies[1].source,
+            Some(Provenance {
+                file: first_path,
+                line: 3,
+            })
+        );
+        assert_eq!(
+            movies[2].source,
+            Some(Provenance {
+                file: second_path,
+                line: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_scan_csv() {
+        let temp_dir = tempdir().unwrap();
+        let first_path = temp_dir.path().join("first.csv");
+        let second_path = temp_dir.path().join("second.csv");
+
+        let mut first = File::create(&first_path).unwrap();
+        writeln!(first, "filmtv_id,title,year,genre,duration,country,directors,actors,avg_vote,critics_vote,public_vote,total_votes,description,notes,humor,rhythm,effort,tension,erotism").unwrap();
+        writeln!(
+            first,
+            "1,Example Movie,2021,Drama,120,USA,,,8.5,9.0,8.0,1000,,,5,6,7,8,4"
+        )
+        .unwrap();
+        writeln!(
+            first,
+            "2,Another Movie,n/a,Comedy,90,UK,,,7.5,,7.0,500,,,6,5,6,4,3"
+        )
+        .unwrap();
+        let mut second = File::create(&second_path).unwrap();
+        writeln!(second, "filmtv_id,title,year,genre,duration,country,directors,actors,avg_vote,critics_vote,public_vote,total_votes,description,notes,humor,rhythm,effort,tension,erotism").unwrap();
+        writeln!(
+            second,
+            "3,Third Movie,2019,Drama,100,Italy,,,6.0,6.0,6.0,10,,,1,1,1,1,1"
+        )
+        .unwrap();
+
+        let df = scan_csv(
+            &[first_path.clone(), second_path.clone()],
+            &ColumnMapping::default(),
+        )
+        .unwrap()
+        .collect()
+        .unwrap();
+
+        assert_eq!(df.height(), 3);
+        assert_eq!(df.schema(), transform::output_schema());
+
+        let year = df.column("year").unwrap().i64().unwrap();
+        assert_eq!(year.get(0), Some(2021));
+        assert_eq!(year.get(1), None);
+        let critics_vote = df.column("critics_vote").unwrap().f64().unwrap();
+        assert_eq!(critics_vote.get(1), None);
+
+        let file = df.column(transform::SOURCE_FILE).unwrap().str().unwrap();
+        assert_eq!(file.get(1), Some(first_path.display().to_string().as_str()));
+        assert_eq!(
+            file.get(2),
+            Some(second_path.display().to_string().as_str())
+        );
+        let line = df.column(transform::SOURCE_LINE).unwrap().u64().unwrap();
+        assert_eq!(line.get(1), Some(3));
+        assert_eq!(line.get(2), Some(2));
+    }
+
+    #[test]
+    fn test_column_mapping_renames_input_columns() {
+        let temp_dir = tempdir().unwrap();
+        let file_path = temp_dir.path().join("renamed.csv");
+        let columns = ColumnMapping::from_iter([("id", "filmtv_id"), ("anno", "year")]);
+
+        let mut file = File::create(&file_path).unwrap();
+        writeln!(file, "id,title,anno").unwrap();
+        writeln!(file, "1,Example Movie,2021").unwrap();
+
+        let movies = ingest_csv(&file_path, &columns).unwrap();
+        assert_eq!(movies[0].filmtv_id, Some(1));
+        assert_eq!(movies[0].year, Some(2021));
+        assert_eq!(columns.target("anno"), "year");
+        assert_eq!(columns.source("year"), "anno");
+        assert_eq!(columns.source("title"), "title");
+        assert!(columns.validate().is_ok());
+
+        let unknown = ColumnMapping::from_iter([("anno", "released")]);
+        assert!(matches!(
+            unknown.validate(),
+            Err(ConfigError::InvalidValue { key, .. }) if key == "input.columns.anno"
+        ));
+        let duplicate = ColumnMapping::from_iter([("anno", "year"), ("jahr", "year")]);
+        assert!(matches!(
+            duplicate.validate(),
+            Err(ConfigError::InvalidValue { key, .. }) if key == "input.columns.jahr"
+        ));
+    }
+
+    #[test]
+    fn test_input_config_merge_and_tolerant_options() {
+        let file = InputConfig {
+            paths: vec!["exports/*.csv".to_string()],
+            columns: ColumnMapping::from_iter([("anno", "year")]),
+            batch_size: Some(500),
+            max_error_rate: Some(0.2),
+            ..InputConfig::default()
+        };
+        let env = InputConfig {
+            batch_size: Some(100),
+            ..InputConfig::default()
+        };
+
+        let merged = file.merge(env);
+        assert_eq!(merged.paths, vec!["exports/*.csv".to_string()]);
+        assert_eq!(merged.batch_size, Some(100));
+        assert_eq!(merged.columns.target("anno"), "year");
+        assert_eq!(merged.tolerant_options().unwrap().max_error_rate, 0.2);
+        assert!(InputConfig::default().tolerant_options().is_none());
+
+        let invalid = InputConfig {
+            max_error_rate: Some(1.5),
+            ..InputConfig::default()
+        };
+        assert!(matches!(
+            invalid.validate(),
+            Err(ConfigError::InvalidValue { key, .. }) if key == "input.max_error_rate"
+        ));
+    }
 }
@@ -0,0 +1,22 @@
+//! The FilmTV movie data pipeline as a library, so benchmarks and other tools can use the
+//! ingestion, transformation and database loading stages without the command-line interface in
+//! `main.rs`.
+
+pub mod config;
+pub mod db;
+pub mod dedup;
+pub mod ingestion;
+pub mod logging;
+pub mod metrics;
+pub mod migrate;
+pub mod models;
+pub mod parquet;
+pub mod pipeline;
+pub mod profile;
+pub mod relations;
+pub mod retry;
+pub mod sink;
+pub mod source;
+pub mod transform;
+pub mod utils;
+pub mod validation;
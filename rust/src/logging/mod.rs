@@ -0,0 +1,335 @@
+//! This module provides the `tracing` subscriber of the pipeline binary. Events and spans are
+//! written to standard error, filtered by the level selected with the `--log-level` flag, either
+//! as human-readable lines or as one JSON object per line (see `LogFormat`). Messages logged
+//! through the `log` macros by dependencies are forwarded to the subscriber too.
+//!
+//! A `StageSpan` marks a stage of the run, such as `ingest`, `transform` or `load`, or of one
+//! batch of it in streaming mode. Events emitted inside its span carry the stage, and finishing
+//! it logs the stage's row count and duration. The duration of every stage is recorded in the
+//! `metrics::STAGE_DURATION` histogram too.
+
+use std::fmt;
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use tracing::level_filters::LevelFilter;
+use tracing::{field, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::metrics;
+
+/// Enum representing the levels that can be selected with `--log-level`, from the quietest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LogLevel {
+    /// Only errors.
+    Error,
+    /// Errors and warnings.
+    Warn,
+    /// Errors, warnings and the progress of every stage.
+    #[default]
+    Info,
+    /// Everything above plus sample rows and other details.
+    Debug,
+    /// Everything.
+    Trace,
+}
+
+impl LogLevel {
+    /// Returns the `tracing` filter of the level.
+    pub fn filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self
+            .to_possible_value()
+            .expect("every log level has a possible value");
+        f.write_str(value.get_name())
+    }
+}
+
+/// Enum representing the formats that can be selected with `--log-format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LogFormat {
+    /// `LEVEL spans: message key=value ...` lines.
+    #[default]
+    Human,
+    /// One JSON object per line with the timestamp, level, target, message, fields and entered
+    /// spans, for log aggregators.
+    Json,
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self
+            .to_possible_value()
+            .expect("every log format has a possible value");
+        f.write_str(value.get_name())
+    }
+}
+
+/// Installs the subscriber with the given level and format. Calling it again has no effect.
+///
+/// # Arguments
+///
+/// * `level` - The most verbose level that is written.
+/// * `format` - The format of the written messages.
+pub fn init(level: LogLevel, format: LogFormat) {
+    let ansi = std::io::stderr().is_terminal();
+    // The subscriber can only be installed once; this also forwards the `log` messages to it
+    let _ = subscriber(level, format, ansi, std::io::stderr).try_init();
+}
+
+/// Builds a subscriber writing every enabled event to a writer.
+///
+/// # Arguments
+///
+/// * `level` - The most verbose level that is written.
+/// * `format` - The format of the written messages.
+/// * `ansi` - Whether human-readable lines are colored.
+/// * `writer` - Creates the writer of every event.
+fn subscriber<W>(
+    level: LogLevel,
+    format: LogFormat,
+    ansi: bool,
+    writer: W,
+) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level.filter())
+        .with_writer(writer);
+    match format {
+        LogFormat::Human => Box::new(
+            builder
+                .without_time()
+                .with_target(false)
+                .with_ansi(ansi)
+                .finish(),
+        ),
+        LogFormat::Json => Box::new(
+            builder
+                .json()
+                .flatten_event(true)
+                .with_current_span(true)
+                .with_span_list(true)
+                .finish(),
+        ),
+    }
+}
+
+/// Generates a random run ID of 16 hexadecimal digits.
+pub fn new_run_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+/// Struct representing a stage of a run, such as `ingest`, `transform` or `load`, as a span.
+///
+/// The span is only entered by `in_scope`, or by instrumenting a future with `span`, so it can be
+/// held across `.await` points. `finish` logs the number of rows the stage produced and its
+/// duration; a stage dropped without being finished, because it failed, logs its duration as a
+/// warning instead.
+#[must_use = "the stage ends when it is dropped"]
+pub struct StageSpan {
+    stage: &'static str,
+    span: tracing::Span,
+    started: Instant,
+    finished: bool,
+}
+
+impl StageSpan {
+    /// Starts a stage inside the current span.
+    ///
+    /// # Arguments
+    ///
+    /// * `stage` - The name of the stage.
+    pub fn new(stage: &'static str) -> Self {
+        Self::start(
+            stage,
+            tracing::info_span!("stage", stage = %stage, batch = field::Empty),
+        )
+    }
+
+    /// Starts the stage of a single batch in streaming mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `stage` - The name of the stage.
+    /// * `batch` - The one-based number of the batch.
+    /// * `parent` - The span of the run, as the stages of a batch run while the load is entered.
+    pub fn batch(stage: &'static str, batch: usize, parent: &tracing::Span) -> Self {
+        Self::start(
+            stage,
+            tracing::info_span!(parent: parent, "stage", stage = %stage, batch = batch),
+        )
+    }
+
+    fn start(stage: &'static str, span: tracing::Span) -> Self {
+        span.in_scope(|| tracing::debug!("Stage {} started.", stage));
+        StageSpan {
+            stage,
+            span,
+            started: Instant::now(),
+            finished: false,
+        }
+    }
+
+    /// Returns the span of the stage, to instrument the futures that run in it.
+    pub fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+
+    /// Runs a function inside the span of the stage.
+    pub fn in_scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        self.span.in_scope(f)
+    }
+
+    /// Finishes the stage, logging its row count and duration.
+    ///
+    /// # Arguments
+    ///
+    /// * `rows` - The number of rows the stage produced.
+    ///
+    /// # Returns
+    ///
+    /// The duration of the stage.
+    pub fn finish(mut self, rows: usize) -> Duration {
+        let elapsed = self.started.elapsed();
+        self.span.in_scope(|| {
+            tracing::info!(
+                rows = rows,
+                duration_ms = elapsed.as_secs_f64() * 1000.0,
+                "Stage {} finished: {} rows in {:.2?}.",
+                self.stage,
+                rows,
+                elapsed
+            )
+        });
+        metrics::STAGE_DURATION
+            .with_label_values(&[self.stage, "succeeded"])
+            .observe(elapsed.as_secs_f64());
+        self.finished = true;
+        elapsed
+    }
+}
+
+impl Drop for StageSpan {
+    fn drop(&mut self) {
+        if !self.finished {
+            let elapsed = self.started.elapsed();
+            self.span.in_scope(|| {
+                tracing::warn!(
+                    duration_ms = elapsed.as_secs_f64() * 1000.0,
+                    "Stage {} failed after {:.2?}.",
+                    self.stage,
+                    elapsed
+                )
+            });
+            metrics::STAGE_DURATION
+                .with_label_values(&[self.stage, "failed"])
+                .observe(elapsed.as_secs_f64());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    /// Writer collecting the written lines in memory.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn lines(&self) -> Vec<String> {
+            let bytes = self.0.lock().unwrap();
+            String::from_utf8_lossy(&bytes)
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_log_level() {
+        assert_eq!(LogLevel::default(), LogLevel::Info);
+        assert_eq!(LogLevel::Warn.to_string(), "warn");
+        assert_eq!(LogLevel::from_str("debug", false), Ok(LogLevel::Debug));
+        assert!(LogLevel::Error.filter() < LogLevel::Trace.filter());
+        assert_eq!(LogFormat::from_str("json", false), Ok(LogFormat::Json));
+        assert_eq!(new_run_id().len(), 16);
+
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = subscriber(LogLevel::Warn, LogFormat::Human, false, move || {
+            writer.clone()
+        });
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("hidden");
+            tracing::warn!(rows = 3, "shown");
+        });
+        assert_eq!(buffer.lines(), vec![" WARN shown rows=3".to_string()]);
+    }
+
+    #[test]
+    fn test_stage_span_tags_events_with_its_stage() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = subscriber(LogLevel::Info, LogFormat::Json, false, move || {
+            writer.clone()
+        });
+        tracing::subscriber::with_default(subscriber, || {
+            let run = tracing::info_span!("run", run_id = "run-1");
+            let _entered = run.enter();
+            let load = StageSpan::new("load");
+            load.in_scope(|| {
+                let transform = StageSpan::batch("transform", 2, &run);
+                transform.finish(2);
+            });
+            drop(load);
+        });
+
+        let lines: Vec<serde_json::Value> = buffer
+            .lines()
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["level"], "INFO");
+        assert!(lines[0]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Stage transform finished: 2 rows in "));
+        assert_eq!(lines[0]["rows"], 2);
+        assert_eq!(lines[0]["span"]["stage"], "transform");
+        assert_eq!(lines[0]["span"]["batch"], 2);
+        assert_eq!(lines[0]["spans"][0]["run_id"], "run-1");
+        assert_eq!(lines[0]["spans"].as_array().unwrap().len(), 2);
+        assert_eq!(lines[1]["level"], "WARN");
+        assert_eq!(lines[1]["span"]["stage"], "load");
+    }
+}
@@ -1,66 +1,1165 @@
 //! This is the main module for the data pipeline application.
 //! It orchestrates the ingestion, transformation, and database insertion of movie data.
+//!
+//! Without a subcommand, or with `run`, the whole pipeline is run; the other subcommands run a
+//! single stage. Every setting lives in the configuration file passed with `--config`, see
+//! `config::PipelineConfig` and the README for the available sections.
+//!
+//! A failed run exits with the code of the stage that failed, see `pipeline::Stage::exit_code`.
 
-use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+use futures::{stream, Stream, StreamExt};
+use polars::prelude::DataFrame;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
+use tracing::Instrument;
 
-mod db;
-mod ingestion;
-mod models;
-mod transform;
-mod utils;
+use rust_final_project::config::{DbConfig, PipelineConfig};
+use rust_final_project::db::{self, LoadMode, LoadOptions, LoadStrategy};
+use rust_final_project::logging::{self, LogFormat, LogLevel, StageSpan};
+use rust_final_project::metrics::{self, MetricsConfig};
+use rust_final_project::models::{IngestionError, Movie};
+use rust_final_project::pipeline::{PipelineError, Stage};
+use rust_final_project::sink::{self, Sink, SinkConfig};
+use rust_final_project::source::{
+    self, FileFormat, FileSource, PostgresSource, Source, SourceConfig, TolerantSource,
+};
+use rust_final_project::transform::{self, TransformOptions};
+use rust_final_project::validation::DataQualityReport;
+use rust_final_project::{dedup, ingestion, migrate, profile, retry};
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let dataset_path = "dataset/filmtv_movies.csv";
-
-    // Ingest movie data from the CSV file
-    let df = match ingestion::ingest_csv(dataset_path) {
-        Ok(df) => {
-            println!("DataFrame loaded successfully.");
-            println!("First row:");
-            for movie in df.iter().take(1) {
-                println!("{:?}", movie);
+/// The input used when neither `--input`, `PIPELINE_INPUT` nor the `[input]` section gives one.
+const DEFAULT_INPUT: &str = "dataset/filmtv_movies.csv";
+
+/// Command-line arguments of the data pipeline.
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Ingest, transform and load FilmTV movie data into PostgreSQL"
+)]
+struct Cli {
+    /// Pipeline configuration file, in TOML or, with a `.yaml` or `.yml` extension, in YAML.
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Override a setting of the configuration file, e.g. `--set load.mode=append`. Can be
+    /// repeated.
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+
+    /// Input file, directory of CSV files or glob pattern such as `exports/*.csv`. Can be
+    /// repeated. Defaults to the `[input]` section, then to `dataset/filmtv_movies.csv`.
+    #[arg(
+        long = "input",
+        short,
+        global = true,
+        value_name = "PATH",
+        env = "PIPELINE_INPUT"
+    )]
+    inputs: Vec<String>,
+
+    /// Most verbose level of the messages logged to standard error.
+    #[arg(long, global = true, value_name = "LEVEL", default_value_t = LogLevel::default())]
+    log_level: LogLevel,
+
+    /// Format of the messages logged to standard error: human or json.
+    #[arg(
+        long,
+        global = true,
+        value_name = "FORMAT",
+        env = "PIPELINE_LOG_FORMAT",
+        default_value_t = LogFormat::default()
+    )]
+    log_format: LogFormat,
+
+    /// ID of the run included in the log messages, e.g. the job ID of a scheduler. A random ID
+    /// is generated by default.
+    #[arg(long, global = true, value_name = "ID", env = "PIPELINE_RUN_ID")]
+    run_id: Option<String>,
+
+    /// Run every stage but discard the output: sinks are aborted instead of committed and
+    /// migrations are rolled back.
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    #[command(flatten)]
+    run: RunArgs,
+
+    #[command(flatten, next_help_heading = "Database")]
+    db: DbConfig,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Arguments selecting how movies are written to the database.
+#[derive(Debug, Clone, Args)]
+struct LoadArgs {
+    /// How movies are written to the database: row-by-row, values or copy. Defaults to the
+    /// `[load]` section, then to copy.
+    #[arg(long, value_name = "STRATEGY")]
+    load_strategy: Option<LoadStrategy>,
+
+    /// How existing data is treated: full-refresh, upsert or append. Defaults to the `[load]`
+    /// section, then to upsert.
+    #[arg(long, value_name = "MODE")]
+    load_mode: Option<LoadMode>,
+
+    /// Delete every movie and its links before loading, keeping the schema, the person, country
+    /// and genre tables and the indexes, grants and views of Movie.
+    #[arg(long)]
+    drop_existing: bool,
+}
+
+impl LoadArgs {
+    /// Builds the `LoadOptions` of the PostgreSQL sinks, with the flags taking precedence over
+    /// the `[load]` section.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The shared settings.
+    fn options(&self, ctx: &Context) -> LoadOptions {
+        ctx.config
+            .load
+            .options(self.load_strategy, self.load_mode, self.drop_existing)
+    }
+}
+
+/// Arguments of the `run` subcommand, which are also accepted without a subcommand.
+#[derive(Debug, Clone, Args)]
+struct RunArgs {
+    #[command(flatten)]
+    load: LoadArgs,
+
+    /// Scan the CSV files lazily, running ingestion, cleaning and validation as one Polars query.
+    #[arg(long)]
+    lazy: bool,
+}
+
+/// Subcommands of the data pipeline. Without a subcommand the pipeline is run.
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Ingest, transform and write the movies to the configured sinks.
+    Run(RunArgs),
+    /// Ingest the movies and write them, as they were read, to an intermediate file.
+    Ingest {
+        /// File the movies are written to, as CSV, NDJSON or Parquet by its extension.
+        #[arg(long, short, value_name = "FILE")]
+        output: PathBuf,
+    },
+    /// Ingest and transform the movies and write the cleaned movies to an intermediate file.
+    Transform {
+        /// File the cleaned movies are written to, as CSV, NDJSON or Parquet by its extension.
+        #[arg(long, short, value_name = "FILE")]
+        output: PathBuf,
+
+        /// Scan the CSV files lazily, as for `run`.
+        #[arg(long)]
+        lazy: bool,
+    },
+    /// Write already cleaned movies, e.g. the output of `transform`, to the configured sinks.
+    Load(LoadArgs),
+    /// Ingest and transform the movies and check the data-quality rules without writing them.
+    Validate {
+        /// Scan the CSV files lazily, as for `run`.
+        #[arg(long)]
+        lazy: bool,
+    },
+    /// Print the type, null rate, distinct values and range of every column of the input.
+    Profile {
+        /// Print the column profiles as JSON instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Manage the database schema migrations.
+    Migrate {
+        #[command(subcommand)]
+        action: MigrateAction,
+    },
+    /// Run a read-only SQL query against the database and print the result.
+    Query {
+        /// The query to run, in a transaction that is rolled back afterwards.
+        sql: String,
+    },
+}
+
+impl Command {
+    /// Returns the name of the subcommand.
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Run(_) => "run",
+            Command::Ingest { .. } => "ingest",
+            Command::Transform { .. } => "transform",
+            Command::Load(_) => "load",
+            Command::Validate { .. } => "validate",
+            Command::Profile { .. } => "profile",
+            Command::Migrate { .. } => "migrate",
+            Command::Query { .. } => "query",
+        }
+    }
+}
+
+/// Actions of the `migrate` subcommand.
+#[derive(Debug, Subcommand)]
+enum MigrateAction {
+    /// Apply the pending migrations.
+    Up {
+        /// Only apply migrations up to and including this version.
+        #[arg(long, value_name = "VERSION")]
+        to: Option<i64>,
+    },
+    /// Revert the most recently applied migrations.
+    Down {
+        /// Number of migrations to revert.
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+    },
+    /// Show which migrations are applied and which are pending.
+    Status,
+}
+
+/// Struct holding the settings shared by every subcommand.
+struct Context {
+    /// The pipeline configuration, with the overrides applied.
+    config: PipelineConfig,
+    /// The input files, directories or glob patterns.
+    inputs: Vec<String>,
+    /// The mapping of the input column names to the `Movie` columns.
+    columns: ingestion::ColumnMapping,
+    /// Whether the output of the run is discarded.
+    dry_run: bool,
+    /// The database settings passed on the command line.
+    db: DbConfig,
+    /// The maximum number of movies per batch in streaming mode, if it is enabled.
+    batch_size: Option<usize>,
+    /// The tolerant mode options, if tolerant mode has been configured.
+    tolerant_options: Option<ingestion::TolerantOptions>,
+    /// Where the metrics of the run are exported.
+    metrics: MetricsConfig,
+}
+
+impl Context {
+    /// Loads the pipeline configuration and resolves the settings shared by every subcommand.
+    ///
+    /// # Arguments
+    ///
+    /// * `cli` - The command-line arguments.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Context`, or a `PipelineError` of the config stage.
+    fn new(cli: &Cli) -> Result<Self, PipelineError> {
+        let config = PipelineConfig::load(cli.config.as_deref(), &cli.overrides)?;
+        if let Some(path) = &cli.config {
+            tracing::debug!("Loaded the pipeline configuration from {}.", path.display());
+        }
+        let input_config = config.input_config()?;
+        retry::configure(config.retry_config()?.policy());
+
+        let inputs = if !cli.inputs.is_empty() {
+            cli.inputs.clone()
+        } else if !input_config.paths.is_empty() {
+            input_config.paths.clone()
+        } else {
+            vec![DEFAULT_INPUT.to_string()]
+        };
+
+        Ok(Context {
+            inputs,
+            columns: input_config.columns.clone(),
+            dry_run: cli.dry_run,
+            db: cli.db.clone(),
+            batch_size: input_config.batch_size,
+            tolerant_options: input_config.tolerant_options(),
+            metrics: config.metrics_config()?,
+            config,
+        })
+    }
+
+    /// Resolves the database connection settings.
+    fn db_config(&self) -> Result<tokio_postgres::Config, PipelineError> {
+        let db_config = self.config.db_config(self.db.clone())?;
+        Ok(db_config.to_pg_config()?)
+    }
+
+    /// Resolves the input from `--input`, the `[input]` section and the `[source]` section.
+    fn input(&self) -> Result<Input, PipelineError> {
+        let input = match self.config.source.clone() {
+            Some(SourceConfig::Postgres { table }) => {
+                tracing::info!("Re-reading movies from table {}.", table);
+                Input {
+                    paths: Vec::new(),
+                    format: None,
+                    columns: self.columns.clone(),
+                    table: Some(table),
+                    db_config: Some(self.db_config()?),
+                }
+            }
+            source_config => {
+                let format = source_config.as_ref().and_then(SourceConfig::format);
+                let extension = format.map_or("csv", FileFormat::extension);
+                let mut paths = Vec::new();
+                for input in &self.inputs {
+                    let resolved = ingestion::resolve_inputs_with_extension(input, extension)?;
+                    tracing::info!("Ingesting {} file(s) matching {}.", resolved.len(), input);
+                    paths.extend(resolved);
+                }
+                Input {
+                    paths,
+                    format,
+                    columns: self.columns.clone(),
+                    table: None,
+                    db_config: None,
+                }
             }
-            df
+        };
+
+        if self.tolerant_options.is_some() && !input.is_csv() {
+            return Err(PipelineError::new(
+                Stage::Config,
+                "tolerant mode only supports CSV input files",
+            ));
         }
-        Err(e) => {
-            eprintln!("Failed to load DataFrame: {:?}", e);
-            return Ok(());
+
+        Ok(input)
+    }
+
+    /// Creates the sinks, resolving the database connection only if a PostgreSQL sink is
+    /// configured.
+    fn create_sinks(
+        &self,
+        sink_configs: &[SinkConfig],
+        load_options: &LoadOptions,
+    ) -> Result<Vec<Box<dyn Sink>>, PipelineError> {
+        let db_config = if sink_configs.contains(&SinkConfig::Postgres) {
+            Some(self.db_config()?)
+        } else {
+            None
+        };
+
+        Ok(sink::create_sinks(
+            sink_configs,
+            db_config.as_ref(),
+            load_options,
+        )?)
+    }
+}
+
+/// Struct representing the movies selected with `--input` or the `[source]` section.
+struct Input {
+    /// The input files, if the movies are read from files.
+    paths: Vec<PathBuf>,
+    /// The format of every input file, or `None` to choose it by extension.
+    format: Option<FileFormat>,
+    /// The mapping of the input column names to the `Movie` columns.
+    columns: ingestion::ColumnMapping,
+    /// The table the movies are re-read from, if any.
+    table: Option<String>,
+    /// The database connection settings, if the movies are re-read from a table.
+    db_config: Option<tokio_postgres::Config>,
+}
+
+impl Input {
+    /// Returns whether every input file is CSV, which `--lazy` and tolerant mode require.
+    fn is_csv(&self) -> bool {
+        self.table.is_none()
+            && self.paths.iter().all(|path| {
+                self.format.unwrap_or_else(|| FileFormat::from_path(path)) == FileFormat::Csv
+            })
+    }
+
+    /// Opens a source reading the movies in batches, reading CSV files in tolerant mode if
+    /// options are given.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch_size` - The maximum number of movies per batch.
+    /// * `tolerant_options` - The tolerant mode options, if tolerant mode has been configured.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the source or an `IngestionError`.
+    async fn open(
+        &self,
+        batch_size: usize,
+        tolerant_options: Option<&ingestion::TolerantOptions>,
+    ) -> Result<Box<dyn Source>, IngestionError> {
+        match (&self.table, &self.db_config, tolerant_options) {
+            (Some(table), Some(db_config), _) => {
+                let source = PostgresSource::connect(db_config, table, batch_size).await?;
+                Ok(Box::new(source))
+            }
+            (_, _, Some(options)) => Ok(Box::new(TolerantSource::new(
+                self.paths.clone(),
+                self.columns.clone(),
+                batch_size,
+                options,
+            )?)),
+            _ => Ok(Box::new(FileSource::new(
+                self.paths.clone(),
+                self.format,
+                self.columns.clone(),
+                batch_size,
+            ))),
         }
-    };
+    }
 
-    // Transform the ingested movie data
-    let transformed_movies = match transform::transform_movies(df) {
-        Ok(transformed_movies) => {
-            println!("Movies transformed successfully.");
-            println!("First transformed movie:");
-            if let Some(first_transformed) = transformed_movies.first() {
-                println!("{:?}", first_transformed);
+    /// Reads every movie, ingesting CSV files in tolerant mode if options are given.
+    ///
+    /// # Arguments
+    ///
+    /// * `tolerant_options` - The tolerant mode options, if tolerant mode has been configured.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a vector of `Movie` structs or an `IngestionError`.
+    async fn read_all(
+        &self,
+        tolerant_options: Option<&ingestion::TolerantOptions>,
+    ) -> Result<Vec<Movie>, IngestionError> {
+        let stage = StageSpan::new("ingest");
+        let movies = async {
+            if self.is_csv() {
+                // The CSV files are parsed by worker threads that the runtime must not wait on
+                let paths = self.paths.clone();
+                let columns = self.columns.clone();
+                let tolerant_options = tolerant_options.cloned();
+                source::blocking(move || ingest(&paths, &columns, tolerant_options.as_ref())).await
+            } else {
+                let mut source = self.open(ingestion::DEFAULT_BATCH_SIZE, None).await?;
+                source::read_all(source.as_mut()).await
             }
-            println!(
-                "Total number of transformed movies: {}",
-                transformed_movies.len()
+        }
+        .instrument(stage.span().clone())
+        .await?;
+        stage.finish(movies.len());
+
+        Ok(movies)
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    logging::init(cli.log_level, cli.log_format);
+
+    let run_id = cli.run_id.clone().unwrap_or_else(logging::new_run_id);
+    let command_name = cli.command.as_ref().map_or("run", Command::name);
+    // Every event of the run is emitted inside its span, which carries the run ID
+    let span = tracing::info_span!("run", run_id = %run_id, command = command_name);
+    execute(cli, &run_id).instrument(span).await
+}
+
+/// Runs the command given on the command line and logs whether the run succeeded.
+///
+/// # Arguments
+///
+/// * `cli` - The command-line arguments.
+/// * `run_id` - The ID of the run.
+///
+/// # Returns
+///
+/// The exit code of the run.
+async fn execute(cli: Cli, run_id: &str) -> ExitCode {
+    let started = Instant::now();
+    let dry_run = cli.dry_run;
+    let ctx = Context::new(&cli);
+    let command = cli.command.unwrap_or(Command::Run(cli.run));
+    let command_name = command.name();
+    tracing::info!("Data pipeline run {} started: {}.", run_id, command_name);
+    let (result, textfile) = match ctx {
+        Ok(ctx) => (
+            serve_and_dispatch(&ctx, command).await,
+            ctx.metrics.textfile,
+        ),
+        Err(e) => (Err(e), None),
+    };
+    let duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let exit_code = result
+        .as_ref()
+        .map_or_else(PipelineError::exit_code, |()| 0);
+    metrics::record_run(command_name, started.elapsed(), exit_code);
+    if let Some(path) = textfile {
+        match metrics::write_textfile(&path) {
+            Ok(()) => tracing::debug!("Metrics written to {}.", path.display()),
+            Err(e) => tracing::warn!("Cannot write the metrics to {}: {}", path.display(), e),
+        }
+    }
+
+    // The last line always tells whether the run succeeded, and which stage failed if it did not
+    match result {
+        Ok(()) if dry_run => {
+            tracing::info!(
+                status = "succeeded",
+                exit_code = 0,
+                duration_ms = duration_ms,
+                "Data pipeline finished successfully in {:.2?} (dry run, nothing was committed).",
+                started.elapsed()
             );
-            transformed_movies
+            ExitCode::SUCCESS
+        }
+        Ok(()) => {
+            tracing::info!(
+                status = "succeeded",
+                exit_code = 0,
+                duration_ms = duration_ms,
+                "Data pipeline finished successfully in {:.2?}.",
+                started.elapsed()
+            );
+            ExitCode::SUCCESS
         }
         Err(e) => {
-            eprintln!("Failed to transform movies: {:?}", e);
-            return Ok(());
+            tracing::error!(
+                status = "failed",
+                stage = %e.stage(),
+                exit_code = e.exit_code(),
+                duration_ms = duration_ms,
+                "Data pipeline failed after {:.2?} with exit code {}: {}",
+                started.elapsed(),
+                e.exit_code(),
+                e
+            );
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+/// Serves the metrics on the `/metrics` endpoint while a subcommand runs, if an address is
+/// configured, and runs the subcommand.
+///
+/// # Arguments
+///
+/// * `ctx` - The shared settings.
+/// * `command` - The subcommand to run.
+///
+/// # Returns
+///
+/// A `Result` indicating success or the `PipelineError` that ended the run.
+async fn serve_and_dispatch(ctx: &Context, command: Command) -> Result<(), PipelineError> {
+    let Some(address) = ctx.metrics.listen else {
+        return dispatch(ctx, command).await;
+    };
+    let server = metrics::serve(address).await.map_err(|e| {
+        PipelineError::new(
+            Stage::Config,
+            format!("cannot serve the metrics on {}: {}", address, e),
+        )
+    })?;
+    tracing::info!(
+        address = %server.address(),
+        "Serving the metrics on http://{}/metrics.",
+        server.address()
+    );
+
+    let result = dispatch(ctx, command).await;
+    server.shutdown().await;
+    tracing::debug!("Stopped serving the metrics.");
+    result
+}
+
+/// Runs a subcommand.
+///
+/// # Arguments
+///
+/// * `ctx` - The shared settings.
+/// * `command` - The subcommand to run.
+///
+/// # Returns
+///
+/// A `Result` indicating success or the `PipelineError` that ended the run.
+async fn dispatch(ctx: &Context, command: Command) -> Result<(), PipelineError> {
+    match command {
+        Command::Run(args) => run(ctx, args).await,
+        Command::Ingest { output } => run_ingest(ctx, &output).await,
+        Command::Transform { output, lazy } => run_transform(ctx, &output, lazy).await,
+        Command::Load(args) => run_load(ctx, args).await,
+        Command::Validate { lazy } => run_validate(ctx, lazy).await,
+        Command::Profile { json } => run_profile(ctx, json).await,
+        Command::Migrate { action } => run_migrate(ctx, action).await,
+        Command::Query { sql } => run_query(ctx, &sql).await,
+    }
+}
+
+/// Runs the whole pipeline, writing the cleaned movies to the configured sinks.
+///
+/// # Arguments
+///
+/// * `ctx` - The shared settings.
+/// * `args` - The load options and whether to scan the CSV files lazily.
+///
+/// # Returns
+///
+/// A `Result` indicating success or the `PipelineError` that ended the run.
+async fn run(ctx: &Context, args: RunArgs) -> Result<(), PipelineError> {
+    let sink_configs = ctx.config.sink_configs();
+    let load_options = args.load.options(ctx);
+
+    run_pipeline(ctx, &sink_configs, &load_options, args.lazy).await?;
+
+    Ok(())
+}
+
+/// Runs the `ingest` subcommand, writing the movies to a file as they were read.
+///
+/// # Arguments
+///
+/// * `ctx` - The shared settings.
+/// * `output` - The file the movies are written to.
+///
+/// # Returns
+///
+/// A `Result` indicating success or the `PipelineError` that ended the run.
+async fn run_ingest(ctx: &Context, output: &Path) -> Result<(), PipelineError> {
+    let input = ctx.input()?;
+    let sink_config = SinkConfig::for_output(output)?;
+    let mut sinks = ctx.create_sinks(&[sink_config], &LoadOptions::default())?;
+
+    let batch_size = ctx.batch_size.unwrap_or(ingestion::DEFAULT_BATCH_SIZE);
+    let mut source = input
+        .open(batch_size, ctx.tolerant_options.as_ref())
+        .await?;
+    let batches =
+        ingest_batches(source.as_mut()).map(|(_, batch)| -> Result<DataFrame, PipelineError> {
+            transform::movies_to_dataframe(batch?).map_err(|e| PipelineError::new(Stage::Ingest, e))
+        });
+    let rows = write_batches(ctx, &mut sinks, batches).await?;
+
+    let rejected = source.report().map_or(0, |report| report.rejected);
+    tracing::info!(
+        accepted = rows,
+        rejected = rejected,
+        "Ingested {} movies into {} and rejected {} row(s).",
+        rows,
+        output.display(),
+        rejected
+    );
+
+    Ok(())
+}
+
+/// Runs the `transform` subcommand, writing the cleaned movies to a file.
+///
+/// # Arguments
+///
+/// * `ctx` - The shared settings.
+/// * `output` - The file the cleaned movies are written to.
+/// * `lazy` - Whether to scan the CSV files lazily.
+///
+/// # Returns
+///
+/// A `Result` indicating success or the `PipelineError` that ended the run.
+async fn run_transform(ctx: &Context, output: &Path, lazy: bool) -> Result<(), PipelineError> {
+    let sink_config = SinkConfig::for_output(output)?;
+
+    run_pipeline(ctx, &[sink_config], &LoadOptions::default(), lazy).await?;
+
+    Ok(())
+}
+
+/// Runs the `load` subcommand, writing already cleaned movies to the configured sinks without
+/// transforming them again. Movies without a `filmtv_id` cannot be loaded and are skipped.
+///
+/// # Arguments
+///
+/// * `ctx` - The shared settings.
+/// * `args` - The load options.
+///
+/// # Returns
+///
+/// A `Result` indicating success or the `PipelineError` that ended the run.
+async fn run_load(ctx: &Context, args: LoadArgs) -> Result<(), PipelineError> {
+    let sink_configs = ctx.config.sink_configs();
+    let input = ctx.input()?;
+    let load_options = args.options(ctx);
+    let mut sinks = ctx.create_sinks(&sink_configs, &load_options)?;
+
+    let batch_size = ctx.batch_size.unwrap_or(ingestion::DEFAULT_BATCH_SIZE);
+    let mut source = input
+        .open(batch_size, ctx.tolerant_options.as_ref())
+        .await?;
+
+    let mut skipped = 0;
+    let batches =
+        ingest_batches(source.as_mut()).map(|(_, batch)| -> Result<DataFrame, PipelineError> {
+            let (movies, without_id): (Vec<_>, Vec<_>) = batch?
+                .into_iter()
+                .partition(|movie| movie.filmtv_id.is_some());
+            skipped += without_id.len();
+            transform::movies_to_dataframe(movies).map_err(|e| PipelineError::new(Stage::Load, e))
+        });
+    write_batches(ctx, &mut sinks, batches).await?;
+
+    if skipped > 0 {
+        tracing::warn!(
+            "Skipped {} movies without a filmtv_id; run the transform stage first to clean them.",
+            skipped
+        );
+    }
+
+    Ok(())
+}
+
+/// Runs the `validate` subcommand, checking the data-quality rules without writing any movie.
+///
+/// # Arguments
+///
+/// * `ctx` - The shared settings.
+/// * `lazy` - Whether to scan the CSV files lazily.
+///
+/// # Returns
+///
+/// A `Result` indicating success or the `PipelineError` that ended the run, of the validation
+/// stage if a rule with the fail severity failed.
+async fn run_validate(ctx: &Context, lazy: bool) -> Result<(), PipelineError> {
+    let rows = run_pipeline(ctx, &[], &LoadOptions::default(), lazy).await?;
+    tracing::info!(
+        "Validation passed: {} movies passed the data-quality rules.",
+        rows
+    );
+
+    Ok(())
+}
+
+/// Runs the `profile` subcommand, printing a summary of every column of the input.
+///
+/// # Arguments
+///
+/// * `ctx` - The shared settings.
+/// * `json` - Whether to print the profiles as JSON instead of a table.
+///
+/// # Returns
+///
+/// A `Result` indicating success or the `PipelineError` that ended the run.
+async fn run_profile(ctx: &Context, json: bool) -> Result<(), PipelineError> {
+    let input = ctx.input()?;
+    let movies = input.read_all(ctx.tolerant_options.as_ref()).await?;
+
+    let df =
+        transform::movies_to_dataframe(movies).map_err(|e| PipelineError::new(Stage::Ingest, e))?;
+    let profiles = profile::profile(&df).map_err(|e| PipelineError::new(Stage::Transform, e))?;
+    if json {
+        let json = serde_json::to_string_pretty(&profiles)
+            .map_err(|e| PipelineError::new(Stage::Transform, e))?;
+        println!("{}", json);
+    } else {
+        print!("{}", profile::format_profiles(&profiles));
+    }
+    tracing::info!(
+        "Profiled {} columns of {} movies.",
+        profiles.len(),
+        df.height()
+    );
+
+    Ok(())
+}
+
+/// Ingests and transforms the input and writes the cleaned movies to sinks, one batch at a time
+/// in streaming mode. The data-quality report is written either way.
+///
+/// # Arguments
+///
+/// * `ctx` - The shared settings.
+/// * `sink_configs` - The sinks the cleaned movies are written to, which may be none.
+/// * `load_options` - The load options of the PostgreSQL sinks.
+/// * `lazy` - Whether to scan the CSV files lazily.
+///
+/// # Returns
+///
+/// A `Result` containing the number of cleaned movies, or the `PipelineError` that ended the run.
+async fn run_pipeline(
+    ctx: &Context,
+    sink_configs: &[SinkConfig],
+    load_options: &LoadOptions,
+    lazy: bool,
+) -> Result<usize, PipelineError> {
+    let transform_options = ctx.config.transform_options();
+
+    if lazy && (ctx.batch_size.is_some() || ctx.tolerant_options.is_some()) {
+        return Err(PipelineError::new(
+            Stage::Config,
+            "--lazy cannot be combined with streaming or tolerant mode",
+        ));
+    }
+
+    let input = ctx.input()?;
+    if lazy && !input.is_csv() {
+        return Err(PipelineError::new(
+            Stage::Config,
+            "--lazy only supports CSV input files",
+        ));
+    }
+
+    if ctx.batch_size.is_some() {
+        if let Some(setting) = transform_options.whole_input_setting() {
+            return Err(PipelineError::new(
+                Stage::Config,
+                format!(
+                    "streaming mode transforms one batch at a time and cannot be combined with \
+                     the {}, which needs every movie at once",
+                    setting
+                ),
+            ));
+        }
+    }
+
+    let mut sinks = ctx.create_sinks(sink_configs, load_options)?;
+
+    if let Some(batch_size) = ctx.batch_size {
+        return run_streaming(ctx, &mut sinks, &transform_options, &input, batch_size).await;
+    }
+
+    // Ingest and transform the movie data
+    let (df, report) = if lazy {
+        let lf = tracing::info_span!("stage", stage = "ingest").in_scope(|| {
+            tracing::info!("Scanning the CSV files lazily.");
+            ingestion::scan_csv(&input.paths, &input.columns)
+                .map_err(|e| PipelineError::new(Stage::Ingest, e))
+        })?;
+        // The lazy query reads the files while it transforms them
+        let stage = StageSpan::new("transform");
+        let transformed = stage
+            .in_scope(|| transform::transform_lazy(lf, &transform_options))
+            .map_err(|e| PipelineError::new(Stage::Transform, e))?;
+        stage.finish(transformed.0.height());
+        transformed
+    } else {
+        let movies = input.read_all(ctx.tolerant_options.as_ref()).await?;
+        if let Some(movie) = movies.first() {
+            tracing::debug!("First movie: {:?}", movie);
         }
+        let stage = StageSpan::new("transform");
+        let transformed = stage
+            .in_scope(|| transform::transform_movies(movies, &transform_options))
+            .map_err(|e| PipelineError::new(Stage::Transform, e))?;
+        stage.finish(transformed.0.height());
+        transformed
     };
 
-    println!("Movies transformed successfully.");
+    if let Ok(first) = transform::dataframe_to_transformed_movies(&df.head(Some(1))) {
+        if let Some(first) = first.first() {
+            tracing::debug!("First transformed movie: {:?}", first);
+        }
+    }
+    write_quality_report(&report, &transform_options);
+    report.check()?;
+
+    // Write the transformed movies to every sink
+    let batches = stream::once(async { Ok::<_, PipelineError>(df) });
+    write_batches(ctx, &mut sinks, batches).await
+}
+
+/// Ingests movie data from the CSV files, in tolerant mode if options are given.
+///
+/// # Arguments
+///
+/// * `dataset_paths` - The CSV files to ingest.
+/// * `columns` - The mapping of the input column names to the `Movie` columns.
+/// * `tolerant_options` - The tolerant mode options, if tolerant mode has been configured.
+///
+/// # Returns
+///
+/// A `Result` containing a vector of `Movie` structs or an `IngestionError`.
+fn ingest(
+    dataset_paths: &[PathBuf],
+    columns: &ingestion::ColumnMapping,
+    tolerant_options: Option<&ingestion::TolerantOptions>,
+) -> Result<Vec<Movie>, IngestionError> {
+    match tolerant_options {
+        Some(options) => Ok(ingestion::ingest_files_tolerant(dataset_paths, columns, options)?.0),
+        None => ingestion::ingest_files(dataset_paths, columns),
+    }
+}
+
+/// Runs the pipeline in streaming mode, ingesting, transforming and writing the movie data
+/// one batch at a time.
+///
+/// # Arguments
+///
+/// * `ctx` - The shared settings.
+/// * `sinks` - The sinks the movies are written to. They are only committed if the whole stream
+///   was written.
+/// * `transform_options` - The duplicate policy, null policies and data-quality rules.
+/// * `input` - The input the movies are read from.
+/// * `batch_size` - The maximum number of movies per batch, used by the source.
+///
+/// # Returns
+///
+/// A `Result` containing the number of cleaned movies, or the `PipelineError` that ended the run.
+async fn run_streaming(
+    ctx: &Context,
+    sinks: &mut [Box<dyn Sink>],
+    transform_options: &TransformOptions,
+    input: &Input,
+    batch_size: usize,
+) -> Result<usize, PipelineError> {
+    let mut source = input
+        .open(batch_size, ctx.tolerant_options.as_ref())
+        .await?;
+    tracing::info!(
+        "Streaming movies from {} in batches of {}.",
+        source.name(),
+        batch_size
+    );
+    // The batches are transformed while the load is entered, so their stages are attached to
+    // the span of the run instead
+    let run_span = tracing::Span::current();
+    let batches = ingest_batches(source.as_mut());
+
+    // Transform each batch as it is pulled by the sinks, aborting them as soon as a rule with the
+    // fail severity fails
+    let mut report = DataQualityReport::default();
+    let transformed_batches = batches.map(|(batch, movies)| -> Result<DataFrame, PipelineError> {
+        let movies = movies?;
+        let stage = StageSpan::batch("transform", batch, &run_span);
+        let (df, batch_report) = stage
+            .in_scope(|| transform::transform_movies(movies, transform_options))
+            .map_err(|e| PipelineError::new(Stage::Transform, e))?;
+        stage.finish(df.height());
+        report.merge(batch_report);
+        report.check()?;
+        Ok(df)
+    });
+
+    let rows = write_batches(ctx, sinks, transformed_batches).await;
+
+    write_quality_report(&report, transform_options);
+
+    rows
+}
+
+/// Returns the batches of a source as a stream, reading every batch in its own `ingest` stage.
+/// The stages are attached to the current span, as the batches are pulled by the sinks while the
+/// load is entered.
+///
+/// # Arguments
+///
+/// * `source` - The source to read.
+///
+/// # Returns
+///
+/// A stream of the one-based number of every batch and the batch, or the `IngestionError` that
+/// interrupted it.
+fn ingest_batches(
+    source: &mut dyn Source,
+) -> impl Stream<Item = (usize, Result<Vec<Movie>, IngestionError>)> + '_ {
+    let parent = tracing::Span::current();
+    stream::unfold((source, 1), move |(source, batch)| {
+        let stage = StageSpan::batch("ingest", batch, &parent);
+        async move {
+            let result = match source.next_batch().instrument(stage.span().clone()).await {
+                Ok(Some(movies)) => {
+                    stage.finish(movies.len());
+                    Ok(movies)
+                }
+                // The last read flushes the source, e.g. the dead-letter file in tolerant mode
+                Ok(None) => {
+                    stage.finish(0);
+                    return None;
+                }
+                Err(e) => Err(e),
+            };
+            Some(((batch, result), (source, batch + 1)))
+        }
+    })
+}
+
+/// Writes batches of movies to every sink, and aborts the sinks instead of committing them in a
+/// dry run.
+///
+/// # Arguments
+///
+/// * `ctx` - The shared settings.
+/// * `sinks` - The sinks to write to.
+/// * `batches` - A stream of batches of movies, or the errors that prevented them.
+///
+/// # Returns
+///
+/// A `Result` containing the number of movies written, or the `PipelineError` of the stage that
+/// failed. Nothing is committed if an error is returned.
+async fn write_batches<S>(
+    ctx: &Context,
+    sinks: &mut [Box<dyn Sink>],
+    batches: S,
+) -> Result<usize, PipelineError>
+where
+    S: Stream<Item = Result<DataFrame, PipelineError>>,
+{
+    // The validate subcommand writes to no sink
+    if sinks.is_empty() {
+        return Ok(sink::write_to_sinks(sinks, batches, ctx.dry_run).await?);
+    }
+
+    let stage = StageSpan::new("load");
+    let rows = sink::write_to_sinks(sinks, batches, ctx.dry_run)
+        .instrument(stage.span().clone())
+        .await?;
+    let outcome = if ctx.dry_run {
+        "written and discarded (dry run)"
+    } else {
+        "successfully written"
+    };
+    stage.in_scope(|| {
+        tracing::info!(
+            sinks = sinks.len(),
+            "{} movies {} to {} sink(s).",
+            rows,
+            outcome,
+            sinks.len()
+        )
+    });
+    stage.finish(rows);
+
+    Ok(rows)
+}
 
-    // Create a new list with only the first 10 transformed movies
-    // let transformed_movies = transformed_movies.into_iter().take(10).collect();
+/// Logs a summary of the data-quality report and writes it, and the fuzzy duplicate clusters if
+/// the fuzzy stage is enabled, to the configured paths.
+///
+/// # Arguments
+///
+/// * `report` - The data-quality report of the run.
+/// * `transform_options` - The transform options holding the report path.
+fn write_quality_report(report: &DataQualityReport, transform_options: &TransformOptions) {
+    let failed_rules = report.rules.iter().filter(|rule| rule.failed > 0).count();
+    tracing::info!(
+        "Data-quality report: {} duplicate filmtv_id(s), {} fuzzy duplicate cluster(s), {} movies \
+         checked, {} dropped, {} of {} rule(s) failed.",
+        report.duplicates.len(),
+        report.fuzzy_clusters.len(),
+        report.rows_checked,
+        report.rows_dropped,
+        failed_rules,
+        report.rules.len()
+    );
 
-    // Process the transformed movies (insert into database)
-    match db::process_movies(transformed_movies).await {
-        Ok(_) => println!("Movies successfully inserted into database."),
-        Err(e) => eprintln!("Error inserting movies into database: {:?}", e),
+    let path = &transform_options.validation.report;
+    match report.write(path) {
+        Ok(()) => tracing::info!("Data-quality report written to {}.", path.display()),
+        Err(e) => tracing::error!(
+            "Failed to write data-quality report to {}: {}",
+            path.display(),
+            e
+        ),
     }
 
-    println!("Data pipeline finished successfully.");
+    if let Some(fuzzy) = &transform_options.duplicates.fuzzy {
+        match dedup::write_clusters(&report.fuzzy_clusters, &fuzzy.clusters) {
+            Ok(()) => tracing::info!(
+                "Fuzzy duplicate clusters written to {}.",
+                fuzzy.clusters.display()
+            ),
+            Err(e) => tracing::error!(
+                "Failed to write fuzzy duplicate clusters to {}: {}",
+                fuzzy.clusters.display(),
+                e
+            ),
+        }
+    }
+}
+
+/// Runs a `migrate` subcommand. Migrations applied or reverted by a single invocation are run in
+/// one transaction, so either all of them succeed or none does. In a dry run the transaction is
+/// rolled back.
+///
+/// # Arguments
+///
+/// * `ctx` - The shared settings.
+/// * `action` - The migration action to run.
+///
+/// # Returns
+///
+/// A `Result` indicating success or the `PipelineError` that ended the run.
+async fn run_migrate(ctx: &Context, action: MigrateAction) -> Result<(), PipelineError> {
+    let mut client = db::connect_db(&ctx.db_config()?).await?;
+
+    match action {
+        MigrateAction::Status => {
+            for status in migrate::status(&client).await? {
+                println!(
+                    "{:>4}  {:<40} {}",
+                    status.version, status.name, status.state
+                );
+            }
+        }
+        MigrateAction::Up { to } => {
+            let transaction = client.transaction().await?;
+            let applied = migrate::up(&transaction, to).await?;
+            for migration in &applied {
+                tracing::info!(
+                    "Applied migration {} ({}).",
+                    migration.version,
+                    migration.name
+                );
+            }
+            if ctx.dry_run {
+                transaction.rollback().await?;
+                tracing::info!(
+                    "Dry run: rolled back {} applied migration(s).",
+                    applied.len()
+                );
+            } else {
+                transaction.commit().await?;
+                tracing::info!("{} migration(s) applied.", applied.len());
+            }
+        }
+        MigrateAction::Down { steps } => {
+            let transaction = client.transaction().await?;
+            let reverted = migrate::down(&transaction, steps).await?;
+            for migration in &reverted {
+                tracing::info!(
+                    "Reverted migration {} ({}).",
+                    migration.version,
+                    migration.name
+                );
+            }
+            if ctx.dry_run {
+                transaction.rollback().await?;
+                tracing::info!(
+                    "Dry run: rolled back {} reverted migration(s).",
+                    reverted.len()
+                );
+            } else {
+                transaction.commit().await?;
+                tracing::info!("{} migration(s) reverted.", reverted.len());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the `query` subcommand, printing the result as tab-separated values with a header row.
+///
+/// # Arguments
+///
+/// * `ctx` - The shared settings.
+/// * `sql` - The query to run.
+///
+/// # Returns
+///
+/// A `Result` indicating success or the `PipelineError` that ended the run.
+async fn run_query(ctx: &Context, sql: &str) -> Result<(), PipelineError> {
+    let mut client = db::connect_db(&ctx.db_config()?).await?;
+
+    let result = db::query_read_only(&mut client, sql).await.map_err(|e| {
+        // The message of the server is more helpful than the generic "db error"
+        let message = e
+            .as_db_error()
+            .map_or_else(|| e.to_string(), |db_error| db_error.message().to_string());
+        PipelineError::new(Stage::Load, format!("query failed: {}", message))
+    })?;
+
+    println!("{}", result.columns.join("\t"));
+    for row in &result.rows {
+        let values = row
+            .iter()
+            .map(|value| value.as_deref().unwrap_or("NULL"))
+            .collect::<Vec<_>>();
+        println!("{}", values.join("\t"));
+    }
+    tracing::info!("{} row(s).", result.rows.len());
 
     Ok(())
 }
@@ -0,0 +1,421 @@
+//! This module provides the metrics of a pipeline run, collected with the `prometheus` crate and
+//! exported in the Prometheus text format.
+//!
+//! The stages record what they do in the process-wide metrics declared below, which are
+//! registered in the registry of the module: the ingestion counts the rows it reads, the
+//! data-quality rules the movies they reject, the database load the movies it inserts and updates
+//! and the duration of its round trips, the retry helpers every retry and the logging stages the
+//! duration of every stage. `serve` exposes them on a local HTTP `/metrics` endpoint while a
+//! command runs, for long runs such as streaming a large input, and `write_textfile` writes them
+//! to a file at the end of a run, for the textfile collector of the Prometheus node exporter.
+
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use prometheus::core::Collector;
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::config::{self, ConfigError};
+use crate::utils;
+
+/// Upper bounds in seconds of the buckets of `STAGE_DURATION`.
+const STAGE_BUCKETS: &[f64] = &[
+    0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0,
+];
+
+/// Upper bounds in seconds of the buckets of `DB_ROUND_TRIP`.
+const DB_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Maximum size of the request head the `/metrics` endpoint reads.
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// Time a client of the `/metrics` endpoint has to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    /// The registry every metric of the pipeline is registered in.
+    static ref REGISTRY: Registry = Registry::new();
+
+    /// Movies read from the input, by format: `csv`, `ndjson`, `json`, `parquet`, `arrow` or
+    /// `postgres`. Rows rejected by tolerant ingestion are included.
+    pub static ref ROWS_READ: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("pipeline_rows_read_total", "Movies read from the input."),
+        &["format"],
+    ));
+
+    /// Rows rejected, by rule: movies that failed a data-quality rule with the `drop` or `fail`
+    /// severity, and rows tolerant ingestion could not parse, under the rule `parse`.
+    pub static ref ROWS_REJECTED: IntCounterVec = register(IntCounterVec::new(
+        Opts::new(
+            "pipeline_rows_rejected_total",
+            "Rows rejected by a data-quality rule, or by tolerant ingestion under the rule parse.",
+        ),
+        &["rule"],
+    ));
+
+    /// Movies written to the `Movie` table by committed loads, by operation: `inserted` or
+    /// `updated`.
+    pub static ref DB_ROWS_WRITTEN: IntCounterVec = register(IntCounterVec::new(
+        Opts::new(
+            "pipeline_db_rows_written_total",
+            "Movies inserted into or updated in the Movie table by committed loads.",
+        ),
+        &["operation"],
+    ));
+
+    /// Operations retried after a transient failure.
+    pub static ref RETRIES: IntCounter = register(IntCounter::new(
+        "pipeline_retries_total",
+        "Operations retried after a transient failure.",
+    ));
+
+    /// Duration of the stages, by stage and status: `succeeded` or `failed`.
+    pub static ref STAGE_DURATION: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new(
+            "pipeline_stage_duration_seconds",
+            "Duration of the ingest, transform and load stages.",
+        )
+        .buckets(STAGE_BUCKETS.to_vec()),
+        &["stage", "status"],
+    ));
+
+    /// Duration of the database round trips of a load, by operation: `connect`, `batch` for
+    /// every attempt to write a batch, and `commit`.
+    pub static ref DB_ROUND_TRIP: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new(
+            "pipeline_db_round_trip_seconds",
+            "Duration of the database round trips of a load.",
+        )
+        .buckets(DB_BUCKETS.to_vec()),
+        &["operation"],
+    ));
+
+    /// The subcommand of a finished run, with the value 1.
+    pub static ref RUN_INFO: IntGaugeVec = register(IntGaugeVec::new(
+        Opts::new("pipeline_run_info", "Subcommand of the finished run."),
+        &["command"],
+    ));
+
+    /// Duration of a finished run.
+    pub static ref RUN_DURATION: Gauge = register(Gauge::new(
+        "pipeline_run_duration_seconds",
+        "Duration of the finished run.",
+    ));
+
+    /// Exit code of a finished run.
+    pub static ref RUN_EXIT_CODE: IntGauge = register(IntGauge::new(
+        "pipeline_run_exit_code",
+        "Exit code of the finished run; 0 if it succeeded.",
+    ));
+
+    /// Time a run finished at.
+    pub static ref RUN_END: Gauge = register(Gauge::new(
+        "pipeline_run_end_timestamp_seconds",
+        "Unix time the run finished at.",
+    ));
+}
+
+/// Registers a metric in the registry of the module.
+///
+/// # Panics
+///
+/// If the metric is invalid or its name is already registered, which is a programming error.
+fn register<M>(metric: prometheus::Result<M>) -> M
+where
+    M: Collector + Clone + 'static,
+{
+    let metric = metric.expect("the metrics of the pipeline are valid");
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("the metrics of the pipeline have distinct names");
+    metric
+}
+
+/// Struct representing the `[metrics]` section of the configuration file.
+///
+/// The `PIPELINE_METRICS_*` environment variables take precedence over the section, see
+/// `MetricsConfig::from_env`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Address the `/metrics` endpoint listens on while the command runs, e.g. `127.0.0.1:9898`.
+    pub listen: Option<SocketAddr>,
+    /// File the metrics are written to at the end of the run.
+    pub textfile: Option<PathBuf>,
+}
+
+impl MetricsConfig {
+    /// Reads the `PIPELINE_METRICS_LISTEN` and `PIPELINE_METRICS_TEXTFILE` environment variables.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `MetricsConfig` or a `ConfigError` if the address is invalid.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Ok(MetricsConfig {
+            listen: config::env_parsed("PIPELINE_METRICS_LISTEN")?,
+            textfile: config::env_var("PIPELINE_METRICS_TEXTFILE").map(PathBuf::from),
+        })
+    }
+
+    /// Merges two configurations, with settings from `other` taking precedence.
+    pub fn merge(self, other: MetricsConfig) -> MetricsConfig {
+        MetricsConfig {
+            listen: other.listen.or(self.listen),
+            textfile: other.textfile.or(self.textfile),
+        }
+    }
+}
+
+/// Renders every metric in the Prometheus text format. Metrics with labels are only rendered
+/// once they have a series.
+pub fn render() -> String {
+    // The metrics are registered on first use, so those the run never touched are registered
+    // here to be rendered too
+    lazy_static::initialize(&ROWS_READ);
+    lazy_static::initialize(&ROWS_REJECTED);
+    lazy_static::initialize(&DB_ROWS_WRITTEN);
+    lazy_static::initialize(&RETRIES);
+    lazy_static::initialize(&STAGE_DURATION);
+    lazy_static::initialize(&DB_ROUND_TRIP);
+    lazy_static::initialize(&RUN_INFO);
+    lazy_static::initialize(&RUN_DURATION);
+    lazy_static::initialize(&RUN_EXIT_CODE);
+    lazy_static::initialize(&RUN_END);
+
+    let mut out = Vec::new();
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut out)
+        .expect("the text encoding of valid metrics cannot fail");
+    String::from_utf8(out).expect("the text format is UTF-8")
+}
+
+/// Records the outcome of a finished run in the `pipeline_run_*` gauges. The ID of the run is
+/// not a label, as every run would create a new series; it is in the logs of the run instead.
+///
+/// # Arguments
+///
+/// * `command` - The subcommand that was run.
+/// * `elapsed` - The duration of the run.
+/// * `exit_code` - The exit code of the run.
+pub fn record_run(command: &str, elapsed: Duration, exit_code: u8) {
+    RUN_INFO.with_label_values(&[command]).set(1);
+    RUN_DURATION.set(elapsed.as_secs_f64());
+    RUN_EXIT_CODE.set(i64::from(exit_code));
+    RUN_END.set(Utc::now().timestamp_millis() as f64 / 1000.0);
+}
+
+/// Writes the metrics to a file in the Prometheus text format.
+///
+/// The file is written next to its path and then renamed, so the node exporter, which only reads
+/// files ending in `.prom`, never reads a partially written file.
+///
+/// # Arguments
+///
+/// * `path` - The file to write, e.g. `/var/lib/node_exporter/pipeline.prom`.
+///
+/// # Returns
+///
+/// A `Result` indicating success or an `io::Error`.
+pub fn write_textfile(path: &Path) -> io::Result<()> {
+    let staging = utils::staging_path(path);
+    fs::write(&staging, render())?;
+    fs::rename(&staging, path)
+}
+
+/// Struct representing the running `/metrics` endpoint, see `serve`.
+pub struct MetricsServer {
+    address: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl MetricsServer {
+    /// Returns the address the endpoint listens on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Stops accepting requests and waits for the listener to be closed. Requests already
+    /// accepted are still answered.
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(());
+        let _ = self.task.await;
+    }
+}
+
+/// Starts serving the metrics on `http://<address>/metrics` in the background of the current
+/// tokio runtime, until `MetricsServer::shutdown` is called.
+///
+/// # Arguments
+///
+/// * `address` - The address to listen on. With port 0, a free port is chosen.
+///
+/// # Returns
+///
+/// A `Result` containing the running `MetricsServer`, or an `io::Error` if the address cannot be
+/// bound.
+pub async fn serve(address: SocketAddr) -> io::Result<MetricsServer> {
+    let listener = TcpListener::bind(address).await?;
+    let address = listener.local_addr()?;
+    let (shutdown, mut stopped) = oneshot::channel();
+
+    let task = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = &mut stopped => break,
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, peer)) => {
+                        tokio::spawn(async move {
+                            if let Err(e) = respond(stream).await {
+                                tracing::debug!("Metrics request from {} failed: {}", peer, e);
+                            }
+                        });
+                    }
+                    Err(e) => tracing::warn!("Cannot accept a metrics request: {}", e),
+                },
+            }
+        }
+    });
+
+    Ok(MetricsServer {
+        address,
+        shutdown,
+        task,
+    })
+}
+
+/// Reads an HTTP request from a connection and answers it.
+async fn respond(mut stream: TcpStream) -> io::Result<()> {
+    let head = tokio::time::timeout(REQUEST_TIMEOUT, read_head(&mut stream))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no request received"))??;
+
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let target = request_line.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default();
+
+    let (status, content_type, body) = if method != "GET" {
+        (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Only GET is supported.\n".to_string(),
+        )
+    } else if path != "/metrics" {
+        (
+            "404 Not Found",
+            "text/plain",
+            "Metrics are served at /metrics.\n".to_string(),
+        )
+    } else {
+        ("200 OK", prometheus::TEXT_FORMAT, render())
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Reads the head of an HTTP request, up to the empty line that ends it.
+async fn read_head(stream: &mut TcpStream) -> io::Result<String> {
+    let mut head = Vec::new();
+    let mut buffer = [0; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request head too large",
+            ));
+        }
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_registered_metrics() {
+        ROWS_READ.with_label_values(&["test"]).inc_by(3);
+        ROWS_READ.with_label_values(&["test"]).inc_by(2);
+        STAGE_DURATION
+            .with_label_values(&["profile", "succeeded"])
+            .observe(0.5);
+        record_run("profile", Duration::from_millis(1500), 4);
+
+        let rendered = render();
+        assert!(rendered.contains("# TYPE pipeline_rows_read_total counter\n"));
+        assert!(rendered.contains("pipeline_rows_read_total{format=\"test\"} 5\n"));
+        assert!(rendered.contains(
+            "pipeline_stage_duration_seconds_bucket{stage=\"profile\",status=\"succeeded\",le=\"0.5\"} 1\n"
+        ));
+        assert!(rendered.contains("pipeline_retries_total 0\n"));
+        assert!(rendered.contains("pipeline_run_info{command=\"profile\"} 1\n"));
+        assert!(rendered.contains("pipeline_run_exit_code 4\n"));
+    }
+
+    #[test]
+    fn test_write_textfile() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("pipeline.prom");
+
+        write_textfile(&path).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("# TYPE pipeline_retries_total counter\n"));
+        assert!(!utils::staging_path(&path).exists());
+    }
+
+    #[tokio::test]
+    async fn test_serve_metrics_endpoint() {
+        let server = serve("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let address = server.address();
+
+        let get = |request: &'static str| async move {
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        RETRIES.inc_by(0);
+        let response = get("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+        assert!(response.contains("# TYPE pipeline_retries_total counter\n"));
+
+        let response = get("GET / HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        server.shutdown().await;
+        assert!(TcpStream::connect(address).await.is_err());
+    }
+}
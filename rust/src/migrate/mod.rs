@@ -0,0 +1,516 @@
+//! This module provides versioned schema migrations for the movie database.
+//! The numbered SQL scripts in the `migrations` directory are compiled into the binary, and the
+//! versions applied to a database are tracked in the `schema_migrations` table together with a
+//! checksum of their script, so a migration that was modified after it was applied is detected
+//! before anything else runs.
+
+use sha2::{Digest, Sha256};
+use std::fmt;
+use tokio_postgres::{Error as PgError, GenericClient};
+
+/// Struct representing a migration compiled into the binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    /// The version of the migration. Migrations are applied in increasing order of version.
+    pub version: i64,
+    /// A short description of the migration.
+    pub name: &'static str,
+    /// The SQL script applying the migration.
+    pub up: &'static str,
+    /// The SQL script reverting the migration.
+    pub down: &'static str,
+}
+
+impl Migration {
+    /// Returns the hex-encoded SHA-256 checksum of the migration's `up` script.
+    pub fn checksum(&self) -> String {
+        Sha256::digest(self.up.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// The migrations of the movie database, in increasing order of version.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_movie",
+        up: include_str!("../../migrations/0001_create_movie.up.sql"),
+        down: include_str!("../../migrations/0001_create_movie.down.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "add_movie_credits_and_text",
+        up: include_str!("../../migrations/0002_add_movie_credits_and_text.up.sql"),
+        down: include_str!("../../migrations/0002_add_movie_credits_and_text.down.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "add_movie_mood_scores",
+        up: include_str!("../../migrations/0003_add_movie_mood_scores.up.sql"),
+        down: include_str!("../../migrations/0003_add_movie_mood_scores.down.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "normalize_people_countries_genres",
+        up: include_str!("../../migrations/0004_normalize_people_countries_genres.up.sql"),
+        down: include_str!("../../migrations/0004_normalize_people_countries_genres.down.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "make_movie_columns_nullable",
+        up: include_str!("../../migrations/0005_make_movie_columns_nullable.up.sql"),
+        down: include_str!("../../migrations/0005_make_movie_columns_nullable.down.sql"),
+    },
+];
+
+/// Key of the advisory lock that serializes concurrent migration runs.
+const MIGRATION_LOCK_KEY: i64 = 0x6d6f_7669_655f_6462;
+
+/// Enum representing various errors that can occur while migrating the database.
+#[derive(Debug)]
+pub enum MigrationError {
+    /// Error reported by the database.
+    Database(PgError),
+    /// Error that occurs when an applied migration's script has been modified since it was
+    /// applied.
+    ChecksumMismatch {
+        /// The version of the modified migration.
+        version: i64,
+        /// The name of the modified migration.
+        name: String,
+    },
+    /// Error that occurs when the database has a migration applied that this binary does not know.
+    UnknownMigration {
+        /// The version of the unknown migration.
+        version: i64,
+        /// The name the unknown migration was applied with.
+        name: String,
+    },
+    /// Error that occurs when the requested target version does not exist.
+    UnknownTarget(i64),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Database(e) => write!(f, "database error: {}", e),
+            MigrationError::ChecksumMismatch { version, name } => write!(
+                f,
+                "migration {} ({}) was modified after it was applied",
+                version, name
+            ),
+            MigrationError::UnknownMigration { version, name } => write!(
+                f,
+                "migration {} ({}) is applied to the database but unknown to this binary",
+                version, name
+            ),
+            MigrationError::UnknownTarget(version) => {
+                write!(f, "there is no migration with version {}", version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MigrationError::Database(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<PgError> for MigrationError {
+    fn from(error: PgError) -> Self {
+        MigrationError::Database(error)
+    }
+}
+
+/// Struct representing a migration recorded in the `schema_migrations` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedMigration {
+    /// The version of the migration.
+    pub version: i64,
+    /// The name of the migration when it was applied.
+    pub name: String,
+    /// The checksum of the migration's script when it was applied.
+    pub checksum: String,
+    /// When the migration was applied.
+    pub applied_at: String,
+}
+
+/// Enum representing the state of a migration in a database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationState {
+    /// The migration has not been applied.
+    Pending,
+    /// The migration has been applied at the given time.
+    Applied(String),
+    /// The migration has been applied at the given time, but its script has changed since.
+    Modified(String),
+    /// The migration has been applied at the given time, but is unknown to this binary.
+    Unknown(String),
+}
+
+impl fmt::Display for MigrationState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationState::Pending => write!(f, "pending"),
+            MigrationState::Applied(at) => write!(f, "applied at {}", at),
+            MigrationState::Modified(at) => write!(f, "MODIFIED since applied at {}", at),
+            MigrationState::Unknown(at) => write!(f, "UNKNOWN, applied at {}", at),
+        }
+    }
+}
+
+/// Struct representing the status of a single migration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    /// The version of the migration.
+    pub version: i64,
+    /// The name of the migration.
+    pub name: String,
+    /// The state of the migration in the database.
+    pub state: MigrationState,
+}
+
+/// Creates the `schema_migrations` table if it does not exist.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a `Client` or `Transaction` for database interaction.
+///
+/// # Returns
+///
+/// A `Result` indicating success or a `PgError`.
+pub async fn create_migrations_table(client: &impl GenericClient) -> Result<(), PgError> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version BIGINT PRIMARY KEY,
+            name TEXT NOT NULL,
+            checksum TEXT NOT NULL,
+            applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )",
+        )
+        .await
+}
+
+/// Retrieves the migrations recorded in the `schema_migrations` table, in increasing order of
+/// version.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a `Client` or `Transaction` for database interaction.
+///
+/// # Returns
+///
+/// A `Result` containing a vector of `AppliedMigration` structs or a `PgError`.
+pub async fn applied_migrations(
+    client: &impl GenericClient,
+) -> Result<Vec<AppliedMigration>, PgError> {
+    let rows = client
+        .query(
+            "SELECT version, name, checksum, applied_at::TEXT FROM schema_migrations
+             ORDER BY version",
+            &[],
+        )
+        .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| AppliedMigration {
+            version: row.get(0),
+            name: row.get(1),
+            checksum: row.get(2),
+            applied_at: row.get(3),
+        })
+        .collect())
+}
+
+/// Checks that every applied migration is known and unmodified.
+///
+/// # Arguments
+///
+/// * `migrations` - The migrations compiled into the binary.
+/// * `applied` - The migrations recorded in the database.
+///
+/// # Returns
+///
+/// A `Result` indicating success or the `MigrationError` for the first offending migration.
+pub fn verify(
+    migrations: &[Migration],
+    applied: &[AppliedMigration],
+) -> Result<(), MigrationError> {
+    for record in applied {
+        match migrations.iter().find(|m| m.version == record.version) {
+            Some(migration) if migration.checksum() == record.checksum => {}
+            Some(migration) => {
+                return Err(MigrationError::ChecksumMismatch {
+                    version: migration.version,
+                    name: migration.name.to_string(),
+                })
+            }
+            None => {
+                return Err(MigrationError::UnknownMigration {
+                    version: record.version,
+                    name: record.name.clone(),
+                })
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Computes the status of every known or applied migration, in increasing order of version.
+///
+/// # Arguments
+///
+/// * `migrations` - The migrations compiled into the binary.
+/// * `applied` - The migrations recorded in the database.
+///
+/// # Returns
+///
+/// A vector of `MigrationStatus` structs.
+pub fn statuses(migrations: &[Migration], applied: &[AppliedMigration]) -> Vec<MigrationStatus> {
+    let mut statuses = migrations
+        .iter()
+        .map(|migration| {
+            let record = applied.iter().find(|r| r.version == migration.version);
+            let state = match record {
+                None => MigrationState::Pending,
+                Some(r) if r.checksum == migration.checksum() => {
+                    MigrationState::Applied(r.applied_at.clone())
+                }
+                Some(r) => MigrationState::Modified(r.applied_at.clone()),
+            };
+            MigrationStatus {
+                version: migration.version,
+                name: migration.name.to_string(),
+                state,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    statuses.extend(
+        applied
+            .iter()
+            .filter(|r| !migrations.iter().any(|m| m.version == r.version))
+            .map(|r| MigrationStatus {
+                version: r.version,
+                name: r.name.clone(),
+                state: MigrationState::Unknown(r.applied_at.clone()),
+            }),
+    );
+    statuses.sort_by_key(|status| status.version);
+
+    statuses
+}
+
+/// Retrieves the status of every migration in the database.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a `Client` or `Transaction` for database interaction.
+///
+/// # Returns
+///
+/// A `Result` containing a vector of `MigrationStatus` structs or a `MigrationError`.
+pub async fn status(client: &impl GenericClient) -> Result<Vec<MigrationStatus>, MigrationError> {
+    create_migrations_table(client).await?;
+    let applied = applied_migrations(client).await?;
+
+    Ok(statuses(MIGRATIONS, &applied))
+}
+
+/// Applies the pending migrations up to and including `target`, or all pending migrations if no
+/// target is given. Run inside a transaction, either every migration is applied or none is.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a `Client` or `Transaction` for database interaction.
+/// * `target` - The version to migrate up to, if any.
+///
+/// # Returns
+///
+/// A `Result` containing the migrations that were applied or a `MigrationError`. Nothing is
+/// applied if an applied migration has been modified or is unknown.
+pub async fn up(
+    client: &impl GenericClient,
+    target: Option<i64>,
+) -> Result<Vec<&'static Migration>, MigrationError> {
+    if let Some(version) = target {
+        if !MIGRATIONS.iter().any(|m| m.version == version) {
+            return Err(MigrationError::UnknownTarget(version));
+        }
+    }
+
+    let applied = lock_and_verify(client).await?;
+    let pending = MIGRATIONS
+        .iter()
+        .filter(|m| !applied.iter().any(|r| r.version == m.version))
+        .filter(|m| target.is_none_or(|version| m.version <= version))
+        .collect::<Vec<_>>();
+
+    for migration in &pending {
+        client.batch_execute(migration.up).await?;
+        client
+            .execute(
+                "INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, $2, $3)",
+                &[&migration.version, &migration.name, &migration.checksum()],
+            )
+            .await?;
+    }
+
+    Ok(pending)
+}
+
+/// Reverts the last `steps` applied migrations, most recent first. Run inside a transaction,
+/// either every migration is reverted or none is.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a `Client` or `Transaction` for database interaction.
+/// * `steps` - The number of migrations to revert.
+///
+/// # Returns
+///
+/// A `Result` containing the migrations that were reverted or a `MigrationError`. Nothing is
+/// reverted if an applied migration has been modified or is unknown.
+pub async fn down(
+    client: &impl GenericClient,
+    steps: usize,
+) -> Result<Vec<&'static Migration>, MigrationError> {
+    let applied = lock_and_verify(client).await?;
+    let reverted = applied
+        .iter()
+        .rev()
+        .take(steps)
+        .filter_map(|r| MIGRATIONS.iter().find(|m| m.version == r.version))
+        .collect::<Vec<_>>();
+
+    for migration in &reverted {
+        client.batch_execute(migration.down).await?;
+        client
+            .execute(
+                "DELETE FROM schema_migrations WHERE version = $1",
+                &[&migration.version],
+            )
+            .await?;
+    }
+
+    Ok(reverted)
+}
+
+/// Creates the `schema_migrations` table if needed, takes the migration lock for the rest of the
+/// transaction and verifies the applied migrations.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a `Client` or `Transaction` for database interaction.
+///
+/// # Returns
+///
+/// A `Result` containing the applied migrations or a `MigrationError`.
+async fn lock_and_verify(
+    client: &impl GenericClient,
+) -> Result<Vec<AppliedMigration>, MigrationError> {
+    client
+        .execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK_KEY])
+        .await?;
+    create_migrations_table(client).await?;
+    let applied = applied_migrations(client).await?;
+    verify(MIGRATIONS, &applied)?;
+
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: 1,
+            name: "create_movie",
+            up: "CREATE TABLE movie (id BIGINT)",
+            down: "DROP TABLE movie",
+        },
+        Migration {
+            version: 2,
+            name: "add_title",
+            up: "ALTER TABLE movie ADD COLUMN title TEXT",
+            down: "ALTER TABLE movie DROP COLUMN title",
+        },
+    ];
+
+    fn applied(migration: &Migration, checksum: String) -> AppliedMigration {
+        AppliedMigration {
+            version: migration.version,
+            name: migration.name.to_string(),
+            checksum,
+            applied_at: "2024-06-01 12:00:00+00".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        assert!(MIGRATIONS
+            .windows(2)
+            .all(|pair| pair[0].version < pair[1].version));
+        assert!(MIGRATIONS[0]
+            .up
+            .contains("CREATE TABLE IF NOT EXISTS Movie"));
+    }
+
+    #[test]
+    fn test_checksum_depends_on_up_script() {
+        let checksum = TEST_MIGRATIONS[0].checksum();
+
+        assert_eq!(checksum.len(), 64);
+        assert_eq!(checksum, TEST_MIGRATIONS[0].checksum());
+        assert_ne!(checksum, TEST_MIGRATIONS[1].checksum());
+    }
+
+    #[test]
+    fn test_verify_rejects_modified_and_unknown_migrations() {
+        let first = applied(&TEST_MIGRATIONS[0], TEST_MIGRATIONS[0].checksum());
+        assert!(verify(TEST_MIGRATIONS, std::slice::from_ref(&first)).is_ok());
+
+        let modified = applied(&TEST_MIGRATIONS[1], "0".repeat(64));
+        assert!(matches!(
+            verify(TEST_MIGRATIONS, &[first.clone(), modified]),
+            Err(MigrationError::ChecksumMismatch { version: 2, .. })
+        ));
+
+        let unknown = AppliedMigration {
+            version: 3,
+            ..first.clone()
+        };
+        assert!(matches!(
+            verify(TEST_MIGRATIONS, &[first, unknown]),
+            Err(MigrationError::UnknownMigration { version: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_statuses() {
+        let first = applied(&TEST_MIGRATIONS[0], TEST_MIGRATIONS[0].checksum());
+        let unknown = AppliedMigration {
+            version: 5,
+            name: "removed".to_string(),
+            ..first.clone()
+        };
+
+        let statuses = statuses(TEST_MIGRATIONS, &[first, unknown]);
+
+        assert_eq!(statuses.len(), 3);
+        assert!(matches!(statuses[0].state, MigrationState::Applied(_)));
+        assert_eq!(statuses[1].state, MigrationState::Pending);
+        assert_eq!(statuses[2].name, "removed");
+        assert!(matches!(statuses[2].state, MigrationState::Unknown(_)));
+    }
+}
@@ -1,11 +1,16 @@
 //! This module provides structures and error handling for ingesting and transforming movie data.
-//! It includes definitions for `IngestionError`, `Movie`, and `TransformedMovie` structs.
+//! It includes definitions for `IngestionError`, `Movie`, `Provenance`, `TransformedMovie`, `RejectedRow`
+//! and `IngestReport`.
 
-use serde::Deserialize;
+use polars::prelude::PolarsError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::io::{self};
+use std::path::PathBuf;
 
 /// Enum representing various errors that can occur during the ingestion process.
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum IngestionError {
     /// Error that occurs during I/O operations.
     IoError(io::Error),
@@ -13,6 +18,73 @@ pub enum IngestionError {
     CsvError(csv::Error),
     /// Error that occurs during JSON deserialization.
     DeserializationError(serde_json::Error),
+    /// Error that occurs while reading a Parquet or Arrow IPC file.
+    PolarsError(PolarsError),
+    /// Error that occurs while reading movies from the database.
+    DatabaseError(tokio_postgres::Error),
+    /// Error that occurs when an input glob pattern is invalid.
+    PatternError(glob::PatternError),
+    /// Error that occurs when an input specification matches no files.
+    NoInputFiles(String),
+    /// Error that occurs when tolerant ingestion reads a value that cannot be parsed as the type
+    /// of its column.
+    InvalidField {
+        /// The `Movie` column of the value.
+        column: String,
+        /// The raw value.
+        value: String,
+    },
+    /// Error that occurs when tolerant ingestion rejects more rows than allowed.
+    ErrorRateExceeded {
+        /// Number of rejected rows.
+        rejected: usize,
+        /// Number of rows read in total.
+        total: usize,
+        /// The maximum allowed fraction of rejected rows.
+        max_error_rate: f64,
+    },
+}
+
+impl fmt::Display for IngestionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IngestionError::IoError(e) => write!(f, "I/O error: {}", e),
+            IngestionError::CsvError(e) => write!(f, "CSV error: {}", e),
+            IngestionError::DeserializationError(e) => write!(f, "deserialization error: {}", e),
+            IngestionError::PolarsError(e) => write!(f, "Polars error: {}", e),
+            IngestionError::DatabaseError(e) => write!(f, "database error: {}", e),
+            IngestionError::PatternError(e) => write!(f, "invalid input pattern: {}", e),
+            IngestionError::NoInputFiles(input) => write!(f, "no input files match {}", input),
+            IngestionError::InvalidField { column, value } => {
+                write!(f, "invalid value {:?} for column {}", value, column)
+            }
+            IngestionError::ErrorRateExceeded {
+                rejected,
+                total,
+                max_error_rate,
+            } => write!(
+                f,
+                "{} of {} rows rejected, exceeding the maximum error rate of {}",
+                rejected, total, max_error_rate
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IngestionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IngestionError::IoError(e) => Some(e),
+            IngestionError::CsvError(e) => Some(e),
+            IngestionError::DeserializationError(e) => Some(e),
+            IngestionError::PolarsError(e) => Some(e),
+            IngestionError::DatabaseError(e) => Some(e),
+            IngestionError::PatternError(e) => Some(e),
+            IngestionError::NoInputFiles(_)
+            | IngestionError::InvalidField { .. }
+            | IngestionError::ErrorRateExceeded { .. } => None,
+        }
+    }
 }
 
 impl From<io::Error> for IngestionError {
@@ -36,9 +108,40 @@ impl From<serde_json::Error> for IngestionError {
     }
 }
 
+impl From<PolarsError> for IngestionError {
+    /// Converts a `PolarsError` into an `IngestionError`.
+    fn from(err: PolarsError) -> Self {
+        IngestionError::PolarsError(err)
+    }
+}
+
+impl From<tokio_postgres::Error> for IngestionError {
+    /// Converts a `tokio_postgres::Error` into an `IngestionError`.
+    fn from(err: tokio_postgres::Error) -> Self {
+        IngestionError::DatabaseError(err)
+    }
+}
+
+impl From<glob::PatternError> for IngestionError {
+    /// Converts a `glob::PatternError` into an `IngestionError`.
+    fn from(err: glob::PatternError) -> Self {
+        IngestionError::PatternError(err)
+    }
+}
+
+/// Struct recording where a movie was read from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Provenance {
+    /// The file the movie was read from.
+    pub file: PathBuf,
+    /// The line of the file the movie was read from, or its one-based position in a JSON array,
+    /// Parquet or Arrow IPC file.
+    pub line: u64,
+}
+
 /// Struct representing a movie with optional fields.
 /// This struct is used for deserializing movie data from various sources.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Movie {
     pub filmtv_id: Option<i64>,
     pub title: Option<String>,
@@ -59,22 +162,109 @@ pub struct Movie {
     pub effort: Option<i64>,
     pub tension: Option<i64>,
     pub erotism: Option<i64>,
+    /// Where the movie was read from, if it was ingested from a file.
+    #[serde(skip)]
+    pub source: Option<Provenance>,
 }
 
-/// Struct representing a transformed movie with non-optional fields.
-/// This struct is used for storing movie data after transformation.
-#[derive(Debug)]
+/// Struct representing a row that was rejected during tolerant ingestion.
+/// This struct is written to the dead-letter file.
+#[derive(Debug, Serialize)]
+pub struct RejectedRow {
+    /// The file the row was read from.
+    pub file: String,
+    /// The line number of the row in the source file, if known.
+    pub line: Option<u64>,
+    /// The raw CSV record as it appeared in the source file.
+    pub raw_record: String,
+    /// Why the row was rejected.
+    pub reason: String,
+}
+
+/// Struct summarizing the outcome of a tolerant ingestion run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IngestReport {
+    /// Number of rows that were ingested successfully.
+    pub accepted: usize,
+    /// Number of rows that were rejected.
+    pub rejected: usize,
+}
+
+impl IngestReport {
+    /// Returns the total number of rows read.
+    pub fn total(&self) -> usize {
+        self.accepted + self.rejected
+    }
+
+    /// Returns the fraction of rows that were rejected, or `0.0` if no rows were read.
+    pub fn error_rate(&self) -> f64 {
+        if self.total() == 0 {
+            0.0
+        } else {
+            self.rejected as f64 / self.total() as f64
+        }
+    }
+}
+
+/// Struct representing a transformed movie with a required `filmtv_id`.
+/// This struct is used for storing movie data after transformation. Every other field is optional:
+/// missing values are kept as NULL unless a null policy fills them, because a missing score or
+/// year is not the same as zero.
+#[derive(Debug, Clone, Serialize)]
 pub struct TransformedMovie {
     pub filmtv_id: i64,
-    pub title: String,
-    pub year: i64,
-    pub genre: String,
-    pub duration: i64,
-    pub country: String,
-    pub avg_vote: f64,
-    pub critics_vote: f64,
-    pub public_vote: f64,
-    pub total_votes: i64,
+    pub title: Option<String>,
+    pub year: Option<i64>,
+    pub genre: Option<String>,
+    pub duration: Option<i64>,
+    pub country: Option<String>,
+    pub directors: Option<String>,
+    pub actors: Option<String>,
+    pub avg_vote: Option<f64>,
+    pub critics_vote: Option<f64>,
+    pub public_vote: Option<f64>,
+    pub total_votes: Option<i64>,
+    pub description: Option<String>,
+    pub notes: Option<String>,
+    pub humor: Option<i64>,
+    pub rhythm: Option<i64>,
+    pub effort: Option<i64>,
+    pub tension: Option<i64>,
+    pub erotism: Option<i64>,
+    /// Where the movie was read from, if it was ingested from a file.
+    pub source: Option<Provenance>,
+}
+
+/// Movies shared by the tests of the modules that load them.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::TransformedMovie;
+
+    /// Returns a cleaned drama from the USA with every score set and no people.
+    pub(crate) fn transformed_movie(filmtv_id: i64, title: &str) -> TransformedMovie {
+        TransformedMovie {
+            filmtv_id,
+            title: Some(title.to_string()),
+            year: Some(2021),
+            genre: Some("Drama".to_string()),
+            duration: Some(120),
+            country: Some("USA".to_string()),
+            directors: None,
+            actors: None,
+            avg_vote: Some(8.5),
+            critics_vote: Some(9.0),
+            public_vote: Some(8.0),
+            total_votes: Some(1000),
+            description: None,
+            notes: None,
+            humor: None,
+            rhythm: None,
+            effort: None,
+            tension: None,
+            erotism: None,
+            source: None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -103,6 +293,7 @@ mod tests {
             effort: Some(7),
             tension: Some(8),
             erotism: Some(4),
+            source: None,
         };
 
         assert_eq!(movie.filmtv_id, Some(1));
@@ -133,26 +324,54 @@ mod tests {
     fn test_transformed_movie_creation() {
         let transformed_movie = TransformedMovie {
             filmtv_id: 1,
-            title: "Example Movie".to_string(),
-            year: 2021,
-            genre: "Drama".to_string(),
-            duration: 120,
-            country: "USA".to_string(),
-            avg_vote: 8.5,
-            critics_vote: 9.0,
-            public_vote: 8.0,
-            total_votes: 1000,
+            title: Some("Example Movie".to_string()),
+            year: Some(2021),
+            genre: Some("Drama".to_string()),
+            duration: Some(120),
+            country: Some("USA".to_string()),
+            directors: Some("John Doe".to_string()),
+            actors: None,
+            avg_vote: Some(8.5),
+            critics_vote: Some(9.0),
+            public_vote: Some(8.0),
+            total_votes: Some(1000),
+            description: Some("An example movie description.".to_string()),
+            notes: None,
+            humor: Some(3),
+            rhythm: None,
+            effort: Some(0),
+            tension: Some(5),
+            erotism: None,
+            source: None,
         };
 
         assert_eq!(transformed_movie.filmtv_id, 1);
-        assert_eq!(transformed_movie.title, "Example Movie".to_string());
-        assert_eq!(transformed_movie.year, 2021);
-        assert_eq!(transformed_movie.genre, "Drama".to_string());
-        assert_eq!(transformed_movie.duration, 120);
-        assert_eq!(transformed_movie.country, "USA".to_string());
-        assert_eq!(transformed_movie.avg_vote, 8.5);
-        assert_eq!(transformed_movie.critics_vote, 9.0);
-        assert_eq!(transformed_movie.public_vote, 8.0);
-        assert_eq!(transformed_movie.total_votes, 1000);
+        assert_eq!(transformed_movie.title, Some("Example Movie".to_string()));
+        assert_eq!(transformed_movie.year, Some(2021));
+        assert_eq!(transformed_movie.genre, Some("Drama".to_string()));
+        assert_eq!(transformed_movie.duration, Some(120));
+        assert_eq!(transformed_movie.country, Some("USA".to_string()));
+        assert_eq!(transformed_movie.avg_vote, Some(8.5));
+        assert_eq!(transformed_movie.critics_vote, Some(9.0));
+        assert_eq!(transformed_movie.public_vote, Some(8.0));
+        assert_eq!(transformed_movie.total_votes, Some(1000));
+        assert_eq!(transformed_movie.directors, Some("John Doe".to_string()));
+        assert_eq!(transformed_movie.actors, None);
+        assert_eq!(transformed_movie.humor, Some(3));
+        assert_eq!(transformed_movie.rhythm, None);
+    }
+
+    #[test]
+    fn test_ingest_report_error_rate() {
+        let empty = IngestReport::default();
+        assert_eq!(empty.total(), 0);
+        assert_eq!(empty.error_rate(), 0.0);
+
+        let report = IngestReport {
+            accepted: 3,
+            rejected: 1,
+        };
+        assert_eq!(report.total(), 4);
+        assert_eq!(report.error_rate(), 0.25);
     }
 }
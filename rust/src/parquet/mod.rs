@@ -0,0 +1,507 @@
+//! This module writes the cleaned movie `DataFrame` to Parquet files, so the same run can produce a
+//! columnar dataset for analytics alongside the database. It includes the `ParquetConfig` struct,
+//! read from the `[parquet]` section of the configuration file, and the `ParquetOutput` writer.
+//!
+//! Without partitioning all movies are written to a single file. With `partition_by`, the output
+//! is a hive-style directory with one subdirectory per value, e.g. `year=1972/part-00000.parquet`.
+//! Files are written to a `.partial` path first and only moved into place by
+//! `ParquetOutput::finish`, replacing the output of the previous run.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+
+use polars::io::parquet::write::BatchedWriter;
+use polars::prelude::*;
+use serde::Deserialize;
+
+use crate::config::ConfigError;
+use crate::transform;
+use crate::utils::{remove_path, staging_path};
+
+/// Default maximum number of rows per row group, the Polars default.
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 512 * 512;
+
+/// Partition value of rows whose partition column is NULL, as used by Hive and Polars.
+const NULL_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// Enum representing various errors that can occur while writing Parquet files.
+#[derive(Debug)]
+pub enum ParquetError {
+    /// Error that occurs while creating, moving or removing files.
+    Io(io::Error),
+    /// Error that occurs while encoding a `DataFrame`.
+    Polars(PolarsError),
+}
+
+impl fmt::Display for ParquetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParquetError::Io(e) => write!(f, "I/O error: {}", e),
+            ParquetError::Polars(e) => write!(f, "Parquet error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ParquetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParquetError::Io(e) => Some(e),
+            ParquetError::Polars(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for ParquetError {
+    /// Converts an `io::Error` into a `ParquetError`.
+    fn from(err: io::Error) -> Self {
+        ParquetError::Io(err)
+    }
+}
+
+impl From<PolarsError> for ParquetError {
+    /// Converts a `PolarsError` into a `ParquetError`.
+    fn from(err: PolarsError) -> Self {
+        ParquetError::Polars(err)
+    }
+}
+
+/// Enum representing the compression codec of the Parquet files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    Uncompressed,
+    Snappy,
+    Gzip,
+    Lz4,
+    #[default]
+    Zstd,
+    Brotli,
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Compression::Uncompressed => "uncompressed",
+            Compression::Snappy => "snappy",
+            Compression::Gzip => "gzip",
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd",
+            Compression::Brotli => "brotli",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Enum representing the column the Parquet output is partitioned by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionColumn {
+    Year,
+    Genre,
+}
+
+impl PartitionColumn {
+    /// Returns the name of the column.
+    pub fn name(self) -> &'static str {
+        match self {
+            PartitionColumn::Year => "year",
+            PartitionColumn::Genre => "genre",
+        }
+    }
+}
+
+/// Struct representing the `[parquet]` section of the configuration file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParquetConfig {
+    /// The Parquet file, or the dataset directory if the output is partitioned.
+    pub path: PathBuf,
+    /// The compression codec.
+    #[serde(default)]
+    pub compression: Compression,
+    /// The compression level, for the `gzip` (0-9), `brotli` (0-11) and `zstd` (1-22) codecs.
+    #[serde(default)]
+    pub compression_level: Option<u32>,
+    /// The maximum number of rows per row group.
+    #[serde(default)]
+    pub row_group_size: Option<usize>,
+    /// The column the output is partitioned by, if any.
+    #[serde(default)]
+    pub partition_by: Option<PartitionColumn>,
+}
+
+impl ParquetConfig {
+    /// Checks the compression level and row-group size.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `ConfigError` for the first invalid setting.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.polars_compression().is_err() {
+            return Err(ConfigError::InvalidValue {
+                key: "parquet.compression_level".to_string(),
+                value: format!(
+                    "{} for {}",
+                    self.compression_level.unwrap_or_default(),
+                    self.compression
+                ),
+            });
+        }
+        if self.row_group_size == Some(0) {
+            return Err(ConfigError::InvalidValue {
+                key: "parquet.row_group_size".to_string(),
+                value: "0".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns the Polars compression options.
+    ///
+    /// # Returns
+    ///
+    /// A `PolarsResult` containing the `ParquetCompression`, or an error if the level is out of
+    /// range or given for a codec without levels.
+    fn polars_compression(&self) -> PolarsResult<ParquetCompression> {
+        let level = self.compression_level;
+        let out_of_range = || polars_err!(ComputeError: "compression level out of range");
+        Ok(match (self.compression, level) {
+            (Compression::Uncompressed, None) => ParquetCompression::Uncompressed,
+            (Compression::Snappy, None) => ParquetCompression::Snappy,
+            (Compression::Lz4, None) => ParquetCompression::Lz4Raw,
+            (Compression::Gzip, level) => ParquetCompression::Gzip(
+                level
+                    .map(|level| {
+                        GzipLevel::try_new(u8::try_from(level).map_err(|_| out_of_range())?)
+                    })
+                    .transpose()?,
+            ),
+            (Compression::Brotli, level) => {
+                ParquetCompression::Brotli(level.map(BrotliLevel::try_new).transpose()?)
+            }
+            (Compression::Zstd, level) => ParquetCompression::Zstd(
+                level
+                    .map(|level| {
+                        ZstdLevel::try_new(i32::try_from(level).map_err(|_| out_of_range())?)
+                    })
+                    .transpose()?,
+            ),
+            (_, Some(_)) => polars_bail!(ComputeError: "codec has no compression levels"),
+        })
+    }
+
+    /// Returns a Polars `ParquetWriter` with the configured compression and row-group size.
+    fn writer(&self, file: File) -> PolarsResult<ParquetWriter<File>> {
+        Ok(ParquetWriter::new(file)
+            .with_compression(self.polars_compression()?)
+            .with_row_group_size(Some(self.row_group_size())))
+    }
+
+    /// Returns the maximum number of rows per row group.
+    fn row_group_size(&self) -> usize {
+        self.row_group_size.unwrap_or(DEFAULT_ROW_GROUP_SIZE)
+    }
+}
+
+/// Struct summarizing what a `ParquetOutput` wrote.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParquetStats {
+    /// Number of movies written.
+    pub rows: usize,
+    /// Number of Parquet files written.
+    pub files: usize,
+}
+
+/// Struct writing batches of cleaned movies to Parquet files.
+pub struct ParquetOutput {
+    config: ParquetConfig,
+    /// Where the files are written until `finish` moves them to the configured path.
+    staging: PathBuf,
+    /// The writer of the single output file, if the output is not partitioned.
+    writer: Option<BatchedWriter<File>>,
+    batches: usize,
+    stats: ParquetStats,
+}
+
+impl ParquetOutput {
+    /// Creates a Parquet output, removing what a previous failed run left in its staging path.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The Parquet settings.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `ParquetOutput` or a `ParquetError`.
+    pub fn create(config: &ParquetConfig) -> Result<Self, ParquetError> {
+        let staging = staging_path(&config.path);
+        remove_path(&staging)?;
+        if let Some(parent) = staging
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+
+        let writer = match config.partition_by {
+            Some(_) => {
+                fs::create_dir(&staging)?;
+                None
+            }
+            None => {
+                let file = File::create(&staging)?;
+                Some(config.writer(file)?.batched(&transform::output_schema())?)
+            }
+        };
+
+        Ok(ParquetOutput {
+            config: config.clone(),
+            staging,
+            writer,
+            batches: 0,
+            stats: ParquetStats::default(),
+        })
+    }
+
+    /// Writes a batch of cleaned movies. With partitioning, every batch adds one file to each of
+    /// the partitions it has movies for.
+    ///
+    /// # Arguments
+    ///
+    /// * `df` - A cleaned `DataFrame` with the columns of `transform::output_schema`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `ParquetError`.
+    pub fn write(&mut self, df: &DataFrame) -> Result<(), ParquetError> {
+        let df = &transform::select_schema(df, &transform::output_schema())?;
+        match (&mut self.writer, self.config.partition_by) {
+            (Some(writer), _) => {
+                let row_group_size = self.config.row_group_size();
+                for offset in (0..df.height()).step_by(row_group_size) {
+                    let mut row_group = df.slice(offset as i64, row_group_size);
+                    row_group.as_single_chunk();
+                    writer.write_batch(&row_group)?;
+                }
+            }
+            (None, Some(column)) => {
+                for mut partition in df.partition_by_stable([column.name()], true)? {
+                    let dir = self.staging.join(partition_dir(&partition, column.name())?);
+                    fs::create_dir_all(&dir)?;
+                    let file = File::create(dir.join(format!("part-{:05}.parquet", self.batches)))?;
+                    self.config.writer(file)?.finish(&mut partition)?;
+                    self.stats.files += 1;
+                }
+            }
+            (None, None) => unreachable!("unpartitioned outputs always have a writer"),
+        }
+
+        self.batches += 1;
+        self.stats.rows += df.height();
+        Ok(())
+    }
+
+    /// Finishes the Parquet files and moves them to the configured path, replacing the output of
+    /// the previous run.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `ParquetStats` or a `ParquetError`.
+    pub fn finish(mut self) -> Result<ParquetStats, ParquetError> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+            self.stats.files = 1;
+        }
+
+        remove_path(&self.config.path)?;
+        fs::rename(&self.staging, &self.config.path)?;
+
+        Ok(self.stats)
+    }
+
+    /// Discards the files written so far, leaving the output of the previous run in place.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or an I/O error.
+    pub fn abort(mut self) -> io::Result<()> {
+        self.writer.take();
+        remove_path(&self.staging)
+    }
+}
+
+/// Writes a cleaned `DataFrame` to Parquet files in one go.
+///
+/// # Arguments
+///
+/// * `df` - A cleaned `DataFrame` with the columns of `transform::output_schema`.
+/// * `config` - The Parquet settings.
+///
+/// # Returns
+///
+/// A `Result` containing the `ParquetStats` or a `ParquetError`.
+pub fn write_parquet(df: &DataFrame, config: &ParquetConfig) -> Result<ParquetStats, ParquetError> {
+    let mut output = ParquetOutput::create(config)?;
+    if let Err(e) = output.write(df) {
+        output.abort()?;
+        return Err(e);
+    }
+    output.finish()
+}
+
+/// Returns the hive-style directory of a partition, e.g. `genre=Science%20Fiction`.
+///
+/// # Arguments
+///
+/// * `partition` - A non-empty `DataFrame` whose rows share the value of the partition column.
+/// * `column` - The name of the partition column.
+///
+/// # Returns
+///
+/// A `PolarsResult` containing the directory name.
+fn partition_dir(partition: &DataFrame, column: &str) -> PolarsResult<String> {
+    let values = partition
+        .column(column)?
+        .slice(0, 1)
+        .cast(&DataType::String)?;
+    let value = values.str()?.get(0);
+
+    Ok(format!(
+        "{}={}",
+        column,
+        value.map_or_else(|| NULL_PARTITION.to_string(), escape_partition_value)
+    ))
+}
+
+/// Percent-encodes the characters of a partition value that are not allowed in a path segment,
+/// like Polars does when it writes hive-style datasets.
+fn escape_partition_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_control() || b"/\\=: %".contains(&byte) || !byte.is_ascii() {
+            escaped.push_str(&format!("%{:02X}", byte));
+        } else {
+            escaped.push(byte as char);
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn movies() -> DataFrame {
+        let movies = df![
+            "filmtv_id" => [1i64, 2, 3, 4],
+            "year" => [Some(1972i64), Some(1960), Some(1972), None],
+            "genre" => [Some("Drama"), Some("Science Fiction"), Some("Drama"), None],
+            "total_votes" => [100i64, 20, 30, 5],
+        ]
+        .unwrap();
+
+        // Add the remaining columns of the movie schema as NULL columns
+        let columns = transform::movie_schema()
+            .iter()
+            .map(|(name, dtype)| match movies.column(name) {
+                Ok(_) => col(name),
+                Err(_) => lit(NULL).cast(dtype.clone()).alias(name),
+            })
+            .collect::<Vec<_>>();
+        movies.lazy().select(columns).collect().unwrap()
+    }
+
+    fn config(path: PathBuf) -> ParquetConfig {
+        ParquetConfig {
+            path,
+            compression: Compression::Snappy,
+            compression_level: None,
+            row_group_size: Some(2),
+            partition_by: None,
+        }
+    }
+
+    fn read(path: &Path) -> DataFrame {
+        ParquetReader::new(File::open(path).unwrap())
+            .finish()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_write_parquet_single_file() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("lake").join("movies.parquet");
+
+        let stats = write_parquet(&movies(), &config(path.clone())).unwrap();
+
+        assert_eq!(stats, ParquetStats { rows: 4, files: 1 });
+        assert!(!staging_path(&path).exists());
+        let written = read(&path);
+        let expected = transform::select_schema(&movies(), &transform::output_schema()).unwrap();
+        assert!(written.equals_missing(&expected));
+
+        // A later run replaces the file, an aborted one leaves it in place
+        let mut output = ParquetOutput::create(&config(path.clone())).unwrap();
+        output.write(&movies().head(Some(1))).unwrap();
+        output.abort().unwrap();
+        assert_eq!(read(&path).height(), 4);
+        write_parquet(&movies().head(Some(1)), &config(path.clone())).unwrap();
+        assert_eq!(read(&path).height(), 1);
+    }
+
+    #[test]
+    fn test_write_parquet_partitioned() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("movies");
+        let config = ParquetConfig {
+            partition_by: Some(PartitionColumn::Genre),
+            ..config(path.clone())
+        };
+
+        let mut output = ParquetOutput::create(&config).unwrap();
+        output.write(&movies().head(Some(2))).unwrap();
+        output.write(&movies().tail(Some(2))).unwrap();
+        let stats = output.finish().unwrap();
+
+        assert_eq!(stats, ParquetStats { rows: 4, files: 4 });
+        let drama = path.join("genre=Drama");
+        assert_eq!(read(&drama.join("part-00000.parquet")).height(), 1);
+        assert_eq!(read(&drama.join("part-00001.parquet")).height(), 1);
+        assert!(path
+            .join("genre=Science%20Fiction")
+            .join("part-00000.parquet")
+            .exists());
+        assert!(path
+            .join("genre=__HIVE_DEFAULT_PARTITION__")
+            .join("part-00001.parquet")
+            .exists());
+    }
+
+    #[test]
+    fn test_validate_parquet_config() {
+        let config: ParquetConfig = toml::from_str(
+            "path = \"movies.parquet\"\ncompression = \"zstd\"\ncompression_level = 3",
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.partition_by, None);
+
+        for invalid in [
+            "path = \"m\"\ncompression = \"zstd\"\ncompression_level = 30",
+            "path = \"m\"\ncompression = \"snappy\"\ncompression_level = 1",
+            "path = \"m\"\nrow_group_size = 0",
+        ] {
+            let config: ParquetConfig = toml::from_str(invalid).unwrap();
+            assert!(
+                matches!(config.validate(), Err(ConfigError::InvalidValue { .. })),
+                "{}",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! This module provides the top-level error of a pipeline run. A `PipelineError` records the
+//! `Stage` that failed, and every stage maps to its own process exit code so that schedulers can
+//! tell failed runs apart from successful ones, and configuration mistakes from bad data.
+
+use std::error::Error;
+use std::fmt;
+
+use tokio_postgres::Error as PgError;
+
+use crate::config::ConfigError;
+use crate::migrate::MigrationError;
+use crate::models::IngestionError;
+use crate::sink::SinkError;
+use crate::validation::ValidationError;
+
+/// Enum representing the stages of the pipeline a run can fail in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Reading the configuration file, flags and environment variables.
+    Config,
+    /// Reading the input movies.
+    Ingest,
+    /// Checking the data-quality rules and the maximum error rate of tolerant ingestion.
+    Validation,
+    /// Resolving duplicates and cleaning the movies.
+    Transform,
+    /// Writing to the sinks, and the `migrate` and `query` database commands.
+    Load,
+}
+
+impl Stage {
+    /// Returns the process exit code of a run that failed in this stage. Exit code 2 is shared
+    /// with invalid command-line arguments, which are configuration errors too.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Stage::Config => 2,
+            Stage::Ingest => 3,
+            Stage::Validation => 4,
+            Stage::Transform => 5,
+            Stage::Load => 6,
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Stage::Config => "config",
+            Stage::Ingest => "ingest",
+            Stage::Validation => "validation",
+            Stage::Transform => "transform",
+            Stage::Load => "load",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Struct representing the error that ended a pipeline run, with the stage it occurred in.
+#[derive(Debug)]
+pub struct PipelineError {
+    stage: Stage,
+    source: Box<dyn Error>,
+}
+
+impl PipelineError {
+    /// Creates a `PipelineError`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stage` - The stage that failed.
+    /// * `source` - The underlying error, or a message describing it.
+    pub fn new(stage: Stage, source: impl Into<Box<dyn Error>>) -> Self {
+        PipelineError {
+            stage,
+            source: source.into(),
+        }
+    }
+
+    /// Returns the stage that failed.
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    /// Returns the process exit code of the run.
+    pub fn exit_code(&self) -> u8 {
+        self.stage.exit_code()
+    }
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} stage failed: {}", self.stage, self.source)
+    }
+}
+
+impl Error for PipelineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+impl From<ConfigError> for PipelineError {
+    /// Converts a `ConfigError` into a `PipelineError` of the config stage.
+    fn from(err: ConfigError) -> Self {
+        PipelineError::new(Stage::Config, err)
+    }
+}
+
+impl From<IngestionError> for PipelineError {
+    /// Converts an `IngestionError` into a `PipelineError` of the ingest stage, or of the
+    /// validation stage if tolerant ingestion rejected too many rows.
+    fn from(err: IngestionError) -> Self {
+        match err {
+            IngestionError::ErrorRateExceeded { .. } => PipelineError::new(Stage::Validation, err),
+            err => PipelineError::new(Stage::Ingest, err),
+        }
+    }
+}
+
+impl From<ValidationError> for PipelineError {
+    /// Converts a `ValidationError` into a `PipelineError` of the validation stage.
+    fn from(err: ValidationError) -> Self {
+        PipelineError::new(Stage::Validation, err)
+    }
+}
+
+impl From<PgError> for PipelineError {
+    /// Converts a `PgError` of a database command into a `PipelineError` of the load stage.
+    fn from(err: PgError) -> Self {
+        PipelineError::new(Stage::Load, err)
+    }
+}
+
+impl From<MigrationError> for PipelineError {
+    /// Converts a `MigrationError` into a `PipelineError` of the load stage.
+    fn from(err: MigrationError) -> Self {
+        PipelineError::new(Stage::Load, err)
+    }
+}
+
+impl From<SinkError> for PipelineError {
+    /// Converts a `SinkError` into a `PipelineError` of the load stage. A batch that could not be
+    /// produced because of a `PipelineError` keeps the stage of that error, and any other error
+    /// producing a batch is an ingest error.
+    fn from(err: SinkError) -> Self {
+        match err {
+            SinkError::Source { batch, source } => match source.downcast::<PipelineError>() {
+                Ok(err) => PipelineError::new(
+                    err.stage,
+                    SinkError::Source {
+                        batch,
+                        source: err.source,
+                    },
+                ),
+                Err(source) => {
+                    PipelineError::new(Stage::Ingest, SinkError::Source { batch, source })
+                }
+            },
+            err => PipelineError::new(Stage::Load, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let stages = [
+            Stage::Config,
+            Stage::Ingest,
+            Stage::Validation,
+            Stage::Transform,
+            Stage::Load,
+        ];
+        assert_eq!(stages.map(Stage::exit_code), [2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_conversions_keep_the_failed_stage() {
+        let err = PipelineError::from(IngestionError::NoInputFiles("missing.csv".to_string()));
+        assert_eq!(err.stage(), Stage::Ingest);
+        assert_eq!(
+            err.to_string(),
+            "ingest stage failed: no input files match missing.csv"
+        );
+
+        let err = PipelineError::from(IngestionError::ErrorRateExceeded {
+            rejected: 2,
+            total: 10,
+            max_error_rate: 0.1,
+        });
+        assert_eq!(err.exit_code(), 4);
+
+        let err = PipelineError::from(SinkError::Source {
+            batch: 3,
+            source: Box::new(PipelineError::new(Stage::Transform, "bad batch")),
+        });
+        assert_eq!(err.stage(), Stage::Transform);
+        assert_eq!(
+            err.to_string(),
+            "transform stage failed: failed to produce batch 3: bad batch"
+        );
+
+        let err = PipelineError::from(SinkError::Sink {
+            sink: "CSV file movies.csv".to_string(),
+            source: "disk full".into(),
+        });
+        assert_eq!(err.stage(), Stage::Load);
+    }
+}
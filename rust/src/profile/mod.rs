@@ -0,0 +1,192 @@
+//! This module provides column profiling of movie data. `profile` summarizes every column of a
+//! `DataFrame`, with its type, null count, number of distinct values, minimum, maximum and mean,
+//! and `format_profiles` renders the summaries as a table.
+
+use polars::prelude::{AnyValue, DataFrame, PolarsResult, Series};
+use serde::Serialize;
+
+/// Maximum number of characters of a minimum or maximum shown by `format_profiles`.
+const MAX_VALUE_WIDTH: usize = 24;
+
+/// Struct summarizing the values of a single column.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnProfile {
+    /// The name of the column.
+    pub name: String,
+    /// The Polars data type of the column.
+    pub dtype: String,
+    /// Number of rows.
+    pub rows: usize,
+    /// Number of null values.
+    pub nulls: usize,
+    /// Number of distinct non-null values.
+    pub distinct: usize,
+    /// The smallest non-null value, if any.
+    pub min: Option<String>,
+    /// The largest non-null value, if any.
+    pub max: Option<String>,
+    /// The mean of a numeric column, if it has non-null values.
+    pub mean: Option<f64>,
+}
+
+impl ColumnProfile {
+    /// Returns the fraction of null values, or `0.0` for an empty column.
+    pub fn null_rate(&self) -> f64 {
+        if self.rows == 0 {
+            0.0
+        } else {
+            self.nulls as f64 / self.rows as f64
+        }
+    }
+}
+
+/// Profiles every column of a `DataFrame`.
+///
+/// # Arguments
+///
+/// * `df` - The data to profile.
+///
+/// # Returns
+///
+/// A `PolarsResult` containing one `ColumnProfile` per column, in column order.
+pub fn profile(df: &DataFrame) -> PolarsResult<Vec<ColumnProfile>> {
+    df.get_columns().iter().map(profile_column).collect()
+}
+
+/// Profiles a single column.
+fn profile_column(series: &Series) -> PolarsResult<ColumnProfile> {
+    let values = series.drop_nulls();
+    let numeric = series.dtype().is_numeric();
+
+    Ok(ColumnProfile {
+        name: series.name().to_string(),
+        dtype: series.dtype().to_string(),
+        rows: series.len(),
+        nulls: series.null_count(),
+        distinct: values.n_unique()?,
+        min: format_value(values.min_reduce()?.value()),
+        max: format_value(values.max_reduce()?.value()),
+        mean: if numeric { values.mean() } else { None },
+    })
+}
+
+/// Formats a minimum or maximum, or returns `None` for null.
+fn format_value(value: &AnyValue) -> Option<String> {
+    match value {
+        AnyValue::Null => None,
+        value => Some(
+            value
+                .get_str()
+                .map_or_else(|| value.to_string(), str::to_string),
+        ),
+    }
+}
+
+/// Shortens a value to `MAX_VALUE_WIDTH` characters.
+fn truncate(value: &str) -> String {
+    if value.chars().count() <= MAX_VALUE_WIDTH {
+        value.to_string()
+    } else {
+        let prefix = value.chars().take(MAX_VALUE_WIDTH - 1).collect::<String>();
+        format!("{}…", prefix)
+    }
+}
+
+/// Renders column profiles as an aligned table with one row per column.
+///
+/// # Arguments
+///
+/// * `profiles` - The column profiles.
+///
+/// # Returns
+///
+/// The table, including a header row, with a trailing newline.
+pub fn format_profiles(profiles: &[ColumnProfile]) -> String {
+    let name_width = profiles
+        .iter()
+        .map(|profile| profile.name.len())
+        .chain(std::iter::once("column".len()))
+        .max()
+        .unwrap_or_default();
+
+    let mut table = format!(
+        "{:<name_width$}  {:<8} {:>8} {:>7} {:>9}  {:<w$}  {:<w$}  {:>10}\n",
+        "column",
+        "type",
+        "nulls",
+        "null %",
+        "distinct",
+        "min",
+        "max",
+        "mean",
+        w = MAX_VALUE_WIDTH
+    );
+    for profile in profiles {
+        table.push_str(&format!(
+            "{:<name_width$}  {:<8} {:>8} {:>6.1}% {:>9}  {:<w$}  {:<w$}  {:>10}\n",
+            profile.name,
+            profile.dtype,
+            profile.nulls,
+            profile.null_rate() * 100.0,
+            profile.distinct,
+            truncate(profile.min.as_deref().unwrap_or("")),
+            truncate(profile.max.as_deref().unwrap_or("")),
+            profile
+                .mean
+                .map_or_else(String::new, |mean| format!("{:.2}", mean)),
+            w = MAX_VALUE_WIDTH
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::*;
+
+    #[test]
+    fn test_profile() {
+        let df = df![
+            "year" => [Some(1999i64), None, Some(1972), Some(1999)],
+            "title" => [Some("B"), Some("A"), None, None],
+        ]
+        .unwrap();
+
+        let profiles = profile(&df).unwrap();
+
+        assert_eq!(
+            profiles[0],
+            ColumnProfile {
+                name: "year".to_string(),
+                dtype: "i64".to_string(),
+                rows: 4,
+                nulls: 1,
+                distinct: 2,
+                min: Some("1972".to_string()),
+                max: Some("1999".to_string()),
+                mean: Some(1990.0),
+            }
+        );
+        assert_eq!(profiles[1].nulls, 2);
+        assert_eq!(profiles[1].null_rate(), 0.5);
+        assert_eq!(profiles[1].min.as_deref(), Some("A"));
+        assert_eq!(profiles[1].max.as_deref(), Some("B"));
+        assert_eq!(profiles[1].mean, None);
+
+        let table = format_profiles(&profiles);
+        let lines = table.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("column  type"));
+        assert!(lines[1].starts_with("year    i64"));
+        assert!(lines[1].contains("25.0%"));
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short"), "short");
+        let long = "x".repeat(30);
+        assert_eq!(truncate(&long).chars().count(), MAX_VALUE_WIDTH);
+        assert!(truncate(&long).ends_with('…'));
+    }
+}
@@ -0,0 +1,310 @@
+//! This module maintains the normalized relational schema next to the `Movie` table.
+//! The comma-separated `directors`, `actors` and `country` fields are split into the `person` and
+//! `country` entity tables and linked to movies through the `movie_director`, `movie_actor` and
+//! `movie_country` link tables, and the `genre` field is linked through the `genre` entity table
+//! and the `genre_id` column of `Movie`. Entities have surrogate keys and are deduplicated by
+//! normalized name, so "Sergio  Leone" and "sergio leone" are the same person.
+//!
+//! The link tables have no foreign key to `Movie`, since a full refresh links its movies while
+//! they are still in the staging table. Instead, a full refresh starts with `clear_links` and every
+//! written batch goes through `sync_links`, so no link outlives the load that removed its movie.
+
+use crate::models::TransformedMovie;
+use std::collections::HashSet;
+use tokio_postgres::{Error as PgError, GenericClient};
+
+/// Struct describing how a comma-separated field of a movie is linked to an entity table.
+struct LinkRelation {
+    /// The entity table, e.g. `person`.
+    entity_table: &'static str,
+    /// The surrogate key column of the entity table.
+    id_column: &'static str,
+    /// The table linking movies to entities, e.g. `movie_director`.
+    link_table: &'static str,
+    /// Returns the field of a movie.
+    field: fn(&TransformedMovie) -> Option<&str>,
+}
+
+/// The comma-separated fields and the tables they are linked through.
+const LINK_RELATIONS: [LinkRelation; 3] = [
+    LinkRelation {
+        entity_table: "person",
+        id_column: "person_id",
+        link_table: "movie_director",
+        field: |movie| movie.directors.as_deref(),
+    },
+    LinkRelation {
+        entity_table: "person",
+        id_column: "person_id",
+        link_table: "movie_actor",
+        field: |movie| movie.actors.as_deref(),
+    },
+    LinkRelation {
+        entity_table: "country",
+        id_column: "country_id",
+        link_table: "movie_country",
+        field: |movie| movie.country.as_deref(),
+    },
+];
+
+/// Struct holding entities to upsert, as parallel arrays that can be passed to `unnest`.
+#[derive(Debug, Default, PartialEq)]
+struct Entities {
+    /// The display names, as first seen.
+    names: Vec<String>,
+    /// The normalized names.
+    keys: Vec<String>,
+}
+
+/// Struct holding links between movies and entities, as parallel arrays that can be passed to
+/// `unnest`.
+#[derive(Debug, Default, PartialEq)]
+struct Links {
+    /// The movie of each link.
+    filmtv_ids: Vec<i64>,
+    /// The normalized name of the linked entity.
+    keys: Vec<String>,
+    /// The position of the entity in the movie's list, starting at 0.
+    positions: Vec<i32>,
+}
+
+/// Collapses runs of whitespace in a name and trims it.
+///
+/// # Arguments
+///
+/// * `name` - The name to clean.
+///
+/// # Returns
+///
+/// The cleaned name, or `None` if it is blank.
+pub fn clean_name(name: &str) -> Option<String> {
+    let cleaned = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!cleaned.is_empty()).then_some(cleaned)
+}
+
+/// Returns the key entities are deduplicated by: the cleaned name in lowercase.
+///
+/// # Arguments
+///
+/// * `name` - The name to normalize.
+pub fn normalize_name(name: &str) -> String {
+    clean_name(name).unwrap_or_default().to_lowercase()
+}
+
+/// Splits a comma-separated list of names into cleaned names, skipping blank entries.
+///
+/// # Arguments
+///
+/// * `value` - The comma-separated list.
+pub fn split_list(value: &str) -> Vec<String> {
+    value.split(',').filter_map(clean_name).collect()
+}
+
+/// Collects the entities and links of a batch of movies for one field.
+///
+/// # Arguments
+///
+/// * `movies` - The movies to collect from.
+/// * `field` - Returns the field of a movie.
+/// * `split` - Splits the field into names.
+///
+/// # Returns
+///
+/// The distinct entities and the links, where an entity listed twice for the same movie is only
+/// linked at its first position.
+fn collect(
+    movies: &[&TransformedMovie],
+    field: fn(&TransformedMovie) -> Option<&str>,
+    split: fn(&str) -> Vec<String>,
+) -> (Entities, Links) {
+    let mut entities = Entities::default();
+    let mut links = Links::default();
+    let mut seen = HashSet::new();
+
+    for movie in movies {
+        let mut linked = HashSet::new();
+        for (position, name) in field(movie)
+            .map(split)
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+        {
+            let key = normalize_name(&name);
+            if !linked.insert(key.clone()) {
+                continue;
+            }
+            if seen.insert(key.clone()) {
+                entities.names.push(name);
+                entities.keys.push(key.clone());
+            }
+            links.filmtv_ids.push(movie.filmtv_id);
+            links.keys.push(key);
+            links.positions.push(position as i32);
+        }
+    }
+
+    (entities, links)
+}
+
+/// Inserts the entities that do not exist yet into an entity table.
+async fn upsert_entities(
+    client: &impl GenericClient,
+    entity_table: &str,
+    entities: &Entities,
+) -> Result<(), PgError> {
+    client
+        .execute(
+            &format!(
+                "INSERT INTO {} (name, normalized_name)
+                 SELECT * FROM unnest($1::TEXT[], $2::TEXT[])
+                 ON CONFLICT (normalized_name) DO NOTHING",
+                entity_table
+            ),
+            &[&entities.names, &entities.keys],
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Removes every link between movies and entities. A full refresh rebuilds them from scratch.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a `Client` or `Transaction` for database interaction.
+///
+/// # Returns
+///
+/// A `Result` indicating success or a `PgError`.
+pub async fn clear_links(client: &impl GenericClient) -> Result<(), PgError> {
+    for relation in &LINK_RELATIONS {
+        client
+            .execute(&format!("DELETE FROM {}", relation.link_table), &[])
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Replaces the links of a batch of movies with the directors, actors, countries and genre the
+/// movies currently list, creating any entity that does not exist yet.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a `Client` or `Transaction` for database interaction.
+/// * `table` - The movie table the batch was written to, usually `Movie`.
+/// * `movies` - The movies to link, with at most one movie per `filmtv_id`.
+///
+/// # Returns
+///
+/// A `Result` indicating success or a `PgError`.
+pub async fn sync_links(
+    client: &impl GenericClient,
+    table: &str,
+    movies: &[&TransformedMovie],
+) -> Result<(), PgError> {
+    let filmtv_ids = movies.iter().map(|m| m.filmtv_id).collect::<Vec<_>>();
+
+    for relation in &LINK_RELATIONS {
+        let (entities, links) = collect(movies, relation.field, split_list);
+        upsert_entities(client, relation.entity_table, &entities).await?;
+        client
+            .execute(
+                &format!(
+                    "DELETE FROM {} WHERE filmtv_id = ANY($1)",
+                    relation.link_table
+                ),
+                &[&filmtv_ids],
+            )
+            .await?;
+        client
+            .execute(
+                &format!(
+                    "INSERT INTO {link} (filmtv_id, {id}, position)
+                     SELECT l.filmtv_id, e.{id}, l.position
+                     FROM unnest($1::BIGINT[], $2::TEXT[], $3::INT[])
+                         AS l (filmtv_id, normalized_name, position)
+                     JOIN {entity} e USING (normalized_name)",
+                    link = relation.link_table,
+                    id = relation.id_column,
+                    entity = relation.entity_table
+                ),
+                &[&links.filmtv_ids, &links.keys, &links.positions],
+            )
+            .await?;
+    }
+
+    let (entities, links) = collect(
+        movies,
+        |movie| movie.genre.as_deref(),
+        |value| clean_name(value).into_iter().collect(),
+    );
+    upsert_entities(client, "genre", &entities).await?;
+    // Every movie of the batch is updated, so a movie that no longer lists a genre is unlinked
+    client
+        .execute(
+            &format!(
+                "UPDATE {} AS m SET genre_id = g.genre_id
+                 FROM unnest($1::BIGINT[]) AS b (filmtv_id)
+                 LEFT JOIN unnest($2::BIGINT[], $3::TEXT[]) AS l (filmtv_id, normalized_name)
+                     USING (filmtv_id)
+                 LEFT JOIN genre g ON g.normalized_name = l.normalized_name
+                 WHERE m.filmtv_id = b.filmtv_id",
+                table
+            ),
+            &[&filmtv_ids, &links.filmtv_ids, &links.keys],
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::fixtures::transformed_movie;
+
+    fn movie(filmtv_id: i64, directors: Option<&str>, country: &str) -> TransformedMovie {
+        TransformedMovie {
+            country: Some(country.to_string()),
+            directors: directors.map(str::to_string),
+            ..transformed_movie(filmtv_id, "Example Movie")
+        }
+    }
+
+    #[test]
+    fn test_split_list_and_normalize_name() {
+        assert_eq!(
+            split_list(" Sergio  Leone,Sergio Corbucci, ,"),
+            vec!["Sergio Leone".to_string(), "Sergio Corbucci".to_string()]
+        );
+        assert_eq!(normalize_name("  Sergio\tLEONE "), "sergio leone");
+        assert_eq!(clean_name("   "), None);
+    }
+
+    #[test]
+    fn test_collect_deduplicates_entities_by_normalized_name() {
+        let first = movie(
+            1,
+            Some("Sergio Leone, sergio  leone, Ennio Morricone"),
+            "Italy",
+        );
+        let second = movie(2, Some("SERGIO LEONE"), "Italy");
+        let third = movie(3, None, "USA");
+
+        let (entities, links) = collect(
+            &[&first, &second, &third],
+            |movie| movie.directors.as_deref(),
+            split_list,
+        );
+
+        assert_eq!(entities.names, vec!["Sergio Leone", "Ennio Morricone"]);
+        assert_eq!(entities.keys, vec!["sergio leone", "ennio morricone"]);
+        assert_eq!(links.filmtv_ids, vec![1, 1, 2]);
+        assert_eq!(
+            links.keys,
+            vec!["sergio leone", "ennio morricone", "sergio leone"]
+        );
+        assert_eq!(links.positions, vec![0, 2, 0]);
+    }
+}
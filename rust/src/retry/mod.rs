@@ -0,0 +1,491 @@
+//! This module provides a reusable retry policy for transient failures.
+//! It includes the `RetryPolicy` struct describing exponential backoff with jitter, the `Retryable`
+//! trait which classifies errors as transient or permanent, and the `retry` and `retry_async`
+//! functions which re-run an operation until it succeeds, fails permanently, or runs out of attempts.
+//! The policy of the pipeline is declared by the `[retry]` section, see `RetryConfig`.
+
+use rand::Rng;
+use serde::Deserialize;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::{self, ConfigError};
+use crate::db::LoadError;
+use crate::metrics;
+use crate::models::IngestionError;
+
+/// The retry policy used by the pipeline, set by `configure` or the default on first use.
+static POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+/// Struct describing how often and how quickly a failed operation is retried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// The delay before the first retry.
+    pub initial_backoff: Duration,
+    /// The upper bound for the delay between two attempts.
+    pub max_backoff: Duration,
+    /// The factor by which the delay grows after every failed attempt.
+    pub multiplier: f64,
+    /// The fraction (between `0.0` and `1.0`) of each delay that is randomized.
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay to wait after the given number of failed attempts.
+    ///
+    /// The delay grows exponentially from `initial_backoff`, is capped at `max_backoff`, and is
+    /// then reduced by a random amount of up to `jitter` of its length.
+    ///
+    /// # Arguments
+    ///
+    /// * `attempt` - The number of attempts that have failed so far, starting at `1`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        let delay = delay.min(self.max_backoff.as_secs_f64());
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = if jitter > 0.0 {
+            1.0 - rand::thread_rng().gen_range(0.0..=jitter)
+        } else {
+            1.0
+        };
+
+        Duration::from_secs_f64(delay * factor)
+    }
+}
+
+/// Struct representing the `[retry]` section of the pipeline configuration. Every setting is
+/// optional and falls back to the `RetryPolicy` defaults; the environment variables read by
+/// `RetryConfig::from_env` take precedence.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    /// The maximum number of attempts, including the first one.
+    pub max_attempts: Option<u32>,
+    /// The delay before the first retry, in milliseconds.
+    pub backoff_ms: Option<u64>,
+    /// The upper bound for the delay between two attempts, in milliseconds.
+    pub max_backoff_ms: Option<u64>,
+    /// The factor by which the delay grows after every failed attempt.
+    pub multiplier: Option<f64>,
+    /// The fraction (between `0.0` and `1.0`) of each delay that is randomized.
+    pub jitter: Option<f64>,
+}
+
+impl RetryConfig {
+    /// Reads the `PIPELINE_RETRY_MAX_ATTEMPTS`, `PIPELINE_RETRY_BACKOFF_MS`,
+    /// `PIPELINE_RETRY_MAX_BACKOFF_MS`, `PIPELINE_RETRY_MULTIPLIER` and `PIPELINE_RETRY_JITTER`
+    /// environment variables.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `RetryConfig` or a `ConfigError` if a variable is not a number.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Ok(RetryConfig {
+            max_attempts: config::env_parsed("PIPELINE_RETRY_MAX_ATTEMPTS")?,
+            backoff_ms: config::env_parsed("PIPELINE_RETRY_BACKOFF_MS")?,
+            max_backoff_ms: config::env_parsed("PIPELINE_RETRY_MAX_BACKOFF_MS")?,
+            multiplier: config::env_parsed("PIPELINE_RETRY_MULTIPLIER")?,
+            jitter: config::env_parsed("PIPELINE_RETRY_JITTER")?,
+        })
+    }
+
+    /// Merges two configurations, with settings from `other` taking precedence.
+    pub fn merge(self, other: RetryConfig) -> RetryConfig {
+        RetryConfig {
+            max_attempts: other.max_attempts.or(self.max_attempts),
+            backoff_ms: other.backoff_ms.or(self.backoff_ms),
+            max_backoff_ms: other.max_backoff_ms.or(self.max_backoff_ms),
+            multiplier: other.multiplier.or(self.multiplier),
+            jitter: other.jitter.or(self.jitter),
+        }
+    }
+
+    /// Checks that at least one attempt is made, that delays do not shrink and that the jitter
+    /// is a fraction.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `ConfigError` naming the offending setting.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |key: &str, value: String| ConfigError::InvalidValue {
+            key: format!("retry.{}", key),
+            value,
+        };
+
+        if let Some(0) = self.max_attempts {
+            return Err(invalid("max_attempts", "0".to_string()));
+        }
+        if let Some(multiplier) = self.multiplier {
+            if !(multiplier >= 1.0 && multiplier.is_finite()) {
+                return Err(invalid("multiplier", multiplier.to_string()));
+            }
+        }
+        if let Some(jitter) = self.jitter {
+            if !(0.0..=1.0).contains(&jitter) {
+                return Err(invalid("jitter", jitter.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the retry policy, with the defaults for the settings that are not set.
+    pub fn policy(&self) -> RetryPolicy {
+        let defaults = RetryPolicy::default();
+        RetryPolicy {
+            max_attempts: self.max_attempts.unwrap_or(defaults.max_attempts),
+            initial_backoff: self
+                .backoff_ms
+                .map_or(defaults.initial_backoff, Duration::from_millis),
+            max_backoff: self
+                .max_backoff_ms
+                .map_or(defaults.max_backoff, Duration::from_millis),
+            multiplier: self.multiplier.unwrap_or(defaults.multiplier),
+            jitter: self.jitter.unwrap_or(defaults.jitter),
+        }
+    }
+}
+
+/// Sets the retry policy of the pipeline. It is set once, at startup; later calls, and calls
+/// after the policy has been used, are ignored.
+pub fn configure(policy: RetryPolicy) {
+    if POLICY.set(policy).is_err() {
+        tracing::debug!("The retry policy has already been set.");
+    }
+}
+
+/// Returns the retry policy configured for the pipeline, or the default policy if none was.
+pub fn policy() -> &'static RetryPolicy {
+    POLICY.get_or_init(RetryPolicy::default)
+}
+
+/// Trait for errors that can tell whether retrying the failed operation may succeed.
+pub trait Retryable {
+    /// Returns `true` if the error is transient and the operation should be retried.
+    fn is_retryable(&self) -> bool;
+}
+
+impl Retryable for io::Error {
+    fn is_retryable(&self) -> bool {
+        matches!(
+            self.kind(),
+            io::ErrorKind::Interrupted
+                | io::ErrorKind::WouldBlock
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::UnexpectedEof
+        )
+    }
+}
+
+impl Retryable for tokio_postgres::Error {
+    fn is_retryable(&self) -> bool {
+        if self.is_closed() {
+            return true;
+        }
+
+        if let Some(db_error) = self.as_db_error() {
+            let code = db_error.code().code();
+            // Class 08 (connection exception), serialization failures, deadlocks, server
+            // shutdown or startup, and connection limits are all expected to be transient.
+            return code.starts_with("08")
+                || matches!(
+                    code,
+                    "40001" | "40P01" | "57P01" | "57P02" | "57P03" | "53300"
+                );
+        }
+
+        std::error::Error::source(self)
+            .and_then(|source| source.downcast_ref::<io::Error>())
+            .is_some_and(Retryable::is_retryable)
+    }
+}
+
+impl Retryable for IngestionError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            IngestionError::IoError(e) => e.is_retryable(),
+            IngestionError::CsvError(e) => match e.kind() {
+                csv::ErrorKind::Io(e) => e.is_retryable(),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl Retryable for LoadError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            // A batch can be retried from its savepoint, but not once the connection and with it
+            // the load's transaction are gone.
+            LoadError::Batch { source, .. } => !source.is_closed() && source.is_retryable(),
+            LoadError::Database(_)
+            | LoadError::Migration(_)
+            | LoadError::Source { .. }
+            | LoadError::DeadLetter(..)
+            | LoadError::SkippedBatches { .. } => false,
+        }
+    }
+}
+
+/// Runs a blocking operation, retrying it according to `policy` while it fails with a
+/// retryable error. Every retry is logged together with the error that caused it.
+///
+/// # Arguments
+///
+/// * `policy` - The retry policy to apply.
+/// * `operation` - A short description of the operation, used in log messages.
+/// * `f` - The operation to run.
+///
+/// # Returns
+///
+/// The result of the first successful attempt, or the error of the last attempt.
+pub fn retry<T, E, F>(policy: &RetryPolicy, operation: &str, mut f: F) -> Result<T, E>
+where
+    F: FnMut() -> Result<T, E>,
+    E: Retryable + fmt::Display,
+{
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < policy.max_attempts && e.is_retryable() => {
+                let delay = policy.backoff(attempt);
+                log_retry(operation, attempt, policy, &e, delay);
+                std::thread::sleep(delay);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Runs an asynchronous operation, retrying it according to `policy` while it fails with a
+/// retryable error. Every retry is logged together with the error that caused it.
+///
+/// # Arguments
+///
+/// * `policy` - The retry policy to apply.
+/// * `operation` - A short description of the operation, used in log messages.
+/// * `f` - A function creating the future for each attempt.
+///
+/// # Returns
+///
+/// The result of the first successful attempt, or the error of the last attempt.
+pub async fn retry_async<T, E, F, Fut>(
+    policy: &RetryPolicy,
+    operation: &str,
+    mut f: F,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Retryable + fmt::Display,
+{
+    let mut attempt = 1;
+    loop {
+        match f().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < policy.max_attempts && e.is_retryable() => {
+                let delay = policy.backoff(attempt);
+                log_retry(operation, attempt, policy, &e, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Logs and counts a failed attempt that is about to be retried.
+fn log_retry(
+    operation: &str,
+    attempt: u32,
+    policy: &RetryPolicy,
+    error: &dyn fmt::Display,
+    delay: Duration,
+) {
+    tracing::warn!(
+        operation = operation,
+        attempt = attempt,
+        delay_ms = delay.as_millis() as u64,
+        "{} failed (attempt {}/{}): {}; retrying in {:?}",
+        operation,
+        attempt,
+        policy.max_attempts,
+        error,
+        delay
+    );
+    metrics::RETRIES.inc();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn immediate_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            multiplier: 2.0,
+            jitter: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_backoff_grows_exponentially_and_is_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            multiplier: 2.0,
+            jitter: 0.0,
+        };
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_backoff_jitter_stays_within_bounds() {
+        let policy = RetryPolicy {
+            jitter: 0.5,
+            ..RetryPolicy::default()
+        };
+
+        for _ in 0..100 {
+            let delay = policy.backoff(1);
+            assert!(delay <= policy.initial_backoff);
+            assert!(delay >= policy.initial_backoff / 2);
+        }
+    }
+
+    #[test]
+    fn test_retry_succeeds_after_transient_errors() {
+        let mut calls = 0;
+        let result = retry(&immediate_policy(3), "test operation", || {
+            calls += 1;
+            if calls < 3 {
+                Err(io::Error::from(io::ErrorKind::ConnectionReset))
+            } else {
+                Ok(calls)
+            }
+        });
+
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_retry_gives_up_after_max_attempts() {
+        let mut calls = 0;
+        let result: Result<(), io::Error> = retry(&immediate_policy(3), "test operation", || {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::TimedOut))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_retry_does_not_retry_permanent_errors() {
+        let mut calls = 0;
+        let result: Result<(), io::Error> = retry(&immediate_policy(3), "test operation", || {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_async_succeeds_after_transient_errors() {
+        let mut calls = 0;
+        let result = retry_async(&immediate_policy(3), "test operation", || {
+            calls += 1;
+            let calls = calls;
+            async move {
+                if calls < 2 {
+                    Err(IngestionError::IoError(io::Error::from(
+                        io::ErrorKind::Interrupted,
+                    )))
+                } else {
+                    Ok(calls)
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[test]
+    fn test_retry_config() {
+        let config = RetryConfig {
+            max_attempts: Some(3),
+            backoff_ms: Some(50),
+            ..RetryConfig::default()
+        }
+        .merge(RetryConfig {
+            max_attempts: Some(7),
+            ..RetryConfig::default()
+        });
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.policy(),
+            RetryPolicy {
+                max_attempts: 7,
+                initial_backoff: Duration::from_millis(50),
+                ..RetryPolicy::default()
+            }
+        );
+
+        for invalid in [
+            RetryConfig {
+                max_attempts: Some(0),
+                ..RetryConfig::default()
+            },
+            RetryConfig {
+                multiplier: Some(0.5),
+                ..RetryConfig::default()
+            },
+            RetryConfig {
+                jitter: Some(1.5),
+                ..RetryConfig::default()
+            },
+        ] {
+            assert!(matches!(
+                invalid.validate(),
+                Err(ConfigError::InvalidValue { key, .. }) if key.starts_with("retry.")
+            ));
+        }
+    }
+}
@@ -0,0 +1,748 @@
+//! This module provides the `Sink` trait, which abstracts where the cleaned movies are written,
+//! and its implementations: `PostgresSink` loads them into the database, `CsvSink` and
+//! `NdjsonSink` write text files and `ParquetSink` writes Parquet files.
+//!
+//! The sinks of a run are declared in the `[[sinks]]` section of the configuration file (see
+//! `SinkConfig`), and `write_to_sinks` fans a stream of batches out to all of them. Every sink
+//! stages what it writes until it is committed: the database load runs in a transaction and the
+//! files are written to a `.partial` path, so an aborted run leaves the previous output intact.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use polars::io::csv::write::BatchedWriter as CsvBatchedWriter;
+use polars::prelude::{CsvWriter, DataFrame, SerWriter};
+use serde::Deserialize;
+use tokio_postgres::Config;
+
+use crate::config::ConfigError;
+use crate::db::{LoadOptions, LoadTransaction};
+use crate::parquet::{Compression, ParquetConfig, ParquetOutput};
+use crate::source::FileFormat;
+use crate::transform;
+use crate::utils::{remove_path, staging_path};
+
+/// Trait implemented by the destinations the cleaned movies are written to.
+///
+/// A sink is opened once, receives every batch through `write_batch` and is then either committed,
+/// which makes the movies visible and replaces the previous output, or aborted, which discards
+/// them.
+#[async_trait(?Send)]
+pub trait Sink {
+    /// Returns a short description of the destination, used in messages.
+    fn name(&self) -> String;
+
+    /// Prepares the destination, e.g. connects to the database or creates the staging file.
+    async fn open(&mut self) -> Result<(), Box<dyn Error>>;
+
+    /// Writes a batch of cleaned movies with the columns of `transform::output_schema`.
+    async fn write_batch(&mut self, batch: &DataFrame) -> Result<(), Box<dyn Error>>;
+
+    /// Makes the written movies visible, replacing the previous output.
+    async fn commit(&mut self) -> Result<(), Box<dyn Error>>;
+
+    /// Discards the written movies, leaving the previous output intact.
+    async fn abort(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+/// Enum representing the errors that abort writing to the sinks.
+#[derive(Debug)]
+pub enum SinkError {
+    /// Error that prevented a batch from being produced.
+    Source {
+        /// The number of the batch, starting at 1.
+        batch: usize,
+        /// The underlying error.
+        source: Box<dyn Error>,
+    },
+    /// Error that occurs while opening a sink or writing a batch to it.
+    Sink {
+        /// The name of the sink.
+        sink: String,
+        /// The underlying error.
+        source: Box<dyn Error>,
+    },
+    /// Error that occurs while committing a sink. The sinks committed before it keep their new
+    /// output.
+    Commit {
+        /// The name of the sink.
+        sink: String,
+        /// The names of the sinks that were already committed.
+        committed: Vec<String>,
+        /// The underlying error.
+        source: Box<dyn Error>,
+    },
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkError::Source { batch, source } => {
+                write!(f, "failed to produce batch {}: {}", batch, source)
+            }
+            SinkError::Sink { sink, source } => write!(f, "{}: {}", sink, source),
+            SinkError::Commit {
+                sink,
+                committed,
+                source,
+            } if committed.is_empty() => write!(f, "failed to commit {}: {}", sink, source),
+            SinkError::Commit {
+                sink,
+                committed,
+                source,
+            } => write!(
+                f,
+                "failed to commit {} after committing {}: {}",
+                sink,
+                committed.join(", "),
+                source
+            ),
+        }
+    }
+}
+
+impl Error for SinkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SinkError::Source { source, .. }
+            | SinkError::Sink { source, .. }
+            | SinkError::Commit { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+/// Enum representing a sink in the `[[sinks]]` section of the configuration file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SinkConfig {
+    /// The PostgreSQL database, configured by the `[database]` section and the load flags.
+    Postgres,
+    /// A CSV file with a header row.
+    Csv {
+        /// The CSV file.
+        path: PathBuf,
+    },
+    /// A file with one JSON object per movie and line.
+    Ndjson {
+        /// The NDJSON file.
+        path: PathBuf,
+    },
+    /// Parquet files, configured like the `[parquet]` section.
+    Parquet(ParquetConfig),
+}
+
+impl SinkConfig {
+    /// Chooses a file sink for an output file by its extension, like `FileFormat::from_path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The output file. `.ndjson` and `.jsonl` files are written as NDJSON, `.parquet`
+    ///   and `.pq` files as a single Parquet file and every other file as CSV.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `SinkConfig`, or a `ConfigError` for the JSON array and Arrow IPC
+    /// formats, which can only be read.
+    pub fn for_output(path: &Path) -> Result<Self, ConfigError> {
+        let path = path.to_path_buf();
+        match FileFormat::from_path(&path) {
+            FileFormat::Csv => Ok(SinkConfig::Csv { path }),
+            FileFormat::Ndjson => Ok(SinkConfig::Ndjson { path }),
+            FileFormat::Parquet => Ok(SinkConfig::Parquet(ParquetConfig {
+                path,
+                compression: Compression::default(),
+                compression_level: None,
+                row_group_size: None,
+                partition_by: None,
+            })),
+            FileFormat::Json | FileFormat::Ipc => Err(ConfigError::InvalidValue {
+                key: "--output".to_string(),
+                value: path.display().to_string(),
+            }),
+        }
+    }
+
+    /// Checks the settings of the sink.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `ConfigError` for the first invalid setting.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match self {
+            SinkConfig::Parquet(parquet) => parquet.validate(),
+            SinkConfig::Postgres | SinkConfig::Csv { .. } | SinkConfig::Ndjson { .. } => Ok(()),
+        }
+    }
+}
+
+/// Creates the configured sinks.
+///
+/// # Arguments
+///
+/// * `configs` - The sinks to create, in the order they are written and committed.
+/// * `db_config` - The database connection settings, required if a `postgres` sink is configured.
+/// * `load_options` - How movies are written to the database.
+///
+/// # Returns
+///
+/// A `Result` containing the sinks, or `ConfigError::DatabaseNotConfigured` if a `postgres` sink
+/// is configured without connection settings.
+pub fn create_sinks(
+    configs: &[SinkConfig],
+    db_config: Option<&Config>,
+    load_options: &LoadOptions,
+) -> Result<Vec<Box<dyn Sink>>, ConfigError> {
+    configs
+        .iter()
+        .map(|config| -> Result<Box<dyn Sink>, ConfigError> {
+            Ok(match config {
+                SinkConfig::Postgres => {
+                    let db_config = db_config.ok_or(ConfigError::DatabaseNotConfigured)?;
+                    Box::new(PostgresSink::new(db_config.clone(), load_options.clone()))
+                }
+                SinkConfig::Csv { path } => Box::new(CsvSink::new(path.clone())),
+                SinkConfig::Ndjson { path } => Box::new(NdjsonSink::new(path.clone())),
+                SinkConfig::Parquet(parquet) => Box::new(ParquetSink::new(parquet.clone())),
+            })
+        })
+        .collect()
+}
+
+/// Writes a stream of batches to every sink, then commits the sinks in order.
+///
+/// All sinks are opened before the first batch is pulled from the stream, and each batch is
+/// written to every sink before the next one is pulled, so only a single batch needs to be held in
+/// memory. If a batch cannot be produced, or a sink fails to open or to write a batch, every sink
+/// is aborted. If a sink fails to commit, the sinks after it are aborted.
+///
+/// # Arguments
+///
+/// * `sinks` - The sinks to write to.
+/// * `batches` - A stream of batches of cleaned movies, or the error that prevented a batch from
+///   being produced.
+/// * `dry_run` - Whether to abort every sink once all batches were written instead of committing
+///   them, leaving the previous output intact.
+///
+/// # Returns
+///
+/// A `Result` containing the number of movies written to each sink, or a `SinkError`.
+pub async fn write_to_sinks<S, E>(
+    sinks: &mut [Box<dyn Sink>],
+    batches: S,
+    dry_run: bool,
+) -> Result<usize, SinkError>
+where
+    S: Stream<Item = Result<DataFrame, E>>,
+    E: Into<Box<dyn Error>>,
+{
+    for i in 0..sinks.len() {
+        if let Err(source) = sinks[i].open().await {
+            let sink = sinks[i].name();
+            abort_sinks(&mut sinks[..i]).await;
+            return Err(SinkError::Sink { sink, source });
+        }
+    }
+
+    let mut batches = std::pin::pin!(batches.enumerate());
+    let mut rows = 0;
+    while let Some((index, batch)) = batches.next().await {
+        let batch = match batch {
+            Ok(batch) => batch,
+            Err(e) => {
+                abort_sinks(sinks).await;
+                return Err(SinkError::Source {
+                    batch: index + 1,
+                    source: e.into(),
+                });
+            }
+        };
+
+        for i in 0..sinks.len() {
+            if let Err(source) = sinks[i].write_batch(&batch).await {
+                let sink = sinks[i].name();
+                abort_sinks(sinks).await;
+                return Err(SinkError::Sink { sink, source });
+            }
+        }
+        rows += batch.height();
+    }
+
+    if dry_run {
+        abort_sinks(sinks).await;
+        return Ok(rows);
+    }
+
+    for i in 0..sinks.len() {
+        if let Err(source) = sinks[i].commit().await {
+            let sink = sinks[i].name();
+            let committed = sinks[..i].iter().map(|sink| sink.name()).collect();
+            abort_sinks(&mut sinks[i + 1..]).await;
+            return Err(SinkError::Commit {
+                sink,
+                committed,
+                source,
+            });
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Aborts every sink, reporting the sinks that fail to abort without stopping.
+async fn abort_sinks(sinks: &mut [Box<dyn Sink>]) {
+    for sink in sinks {
+        if let Err(e) = sink.abort().await {
+            tracing::error!("Failed to abort {}: {}", sink.name(), e);
+        }
+    }
+}
+
+/// Returns the error of a sink that is used before it is opened or after it is finished.
+fn not_open(name: String) -> Box<dyn Error> {
+    format!("{} is not open", name).into()
+}
+
+/// Struct representing a sink that loads the movies into the PostgreSQL database in a single
+/// transaction.
+pub struct PostgresSink {
+    config: Config,
+    options: LoadOptions,
+    load: Option<LoadTransaction>,
+}
+
+impl PostgresSink {
+    /// Creates a PostgreSQL sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The connection settings.
+    /// * `options` - How movies are written to the database.
+    pub fn new(config: Config, options: LoadOptions) -> Self {
+        PostgresSink {
+            config,
+            options,
+            load: None,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Sink for PostgresSink {
+    fn name(&self) -> String {
+        "PostgreSQL".to_string()
+    }
+
+    async fn open(&mut self) -> Result<(), Box<dyn Error>> {
+        self.load = Some(LoadTransaction::begin(&self.config, &self.options).await?);
+        Ok(())
+    }
+
+    async fn write_batch(&mut self, batch: &DataFrame) -> Result<(), Box<dyn Error>> {
+        let movies = transform::dataframe_to_transformed_movies(batch)?;
+        let name = self.name();
+        let load = self.load.as_mut().ok_or_else(|| not_open(name))?;
+        Ok(load.write_batch(&movies).await?)
+    }
+
+    async fn commit(&mut self) -> Result<(), Box<dyn Error>> {
+        let load = self.load.take().ok_or_else(|| not_open(self.name()))?;
+        load.commit().await?;
+        Ok(())
+    }
+
+    async fn abort(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(load) = self.load.take() {
+            load.rollback().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Struct representing a file that is written to its staging path and only replaces the file at
+/// its path when it is committed.
+struct StagedFile {
+    path: PathBuf,
+    staging: PathBuf,
+}
+
+impl StagedFile {
+    /// Creates the staging file, removing what a previous failed run left there.
+    fn create(path: &Path) -> std::io::Result<(Self, File)> {
+        let staging = staging_path(path);
+        remove_path(&staging)?;
+        if let Some(parent) = staging
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::create(&staging)?;
+
+        Ok((
+            StagedFile {
+                path: path.to_path_buf(),
+                staging,
+            },
+            file,
+        ))
+    }
+
+    /// Moves the staging file to the path, replacing the previous file.
+    fn commit(&self) -> std::io::Result<()> {
+        remove_path(&self.path)?;
+        fs::rename(&self.staging, &self.path)
+    }
+
+    /// Removes the staging file.
+    fn abort(&self) -> std::io::Result<()> {
+        remove_path(&self.staging)
+    }
+}
+
+/// Struct representing a sink that writes the movies to a CSV file with a header row, with the
+/// `source_file` and `source_line` of every movie after its other columns.
+pub struct CsvSink {
+    path: PathBuf,
+    file: Option<(StagedFile, CsvBatchedWriter<File>)>,
+    rows: usize,
+}
+
+impl CsvSink {
+    /// Creates a CSV sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The CSV file.
+    pub fn new(path: PathBuf) -> Self {
+        CsvSink {
+            path,
+            file: None,
+            rows: 0,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Sink for CsvSink {
+    fn name(&self) -> String {
+        format!("CSV file {}", self.path.display())
+    }
+
+    async fn open(&mut self) -> Result<(), Box<dyn Error>> {
+        let (staged, file) = StagedFile::create(&self.path)?;
+        let writer = CsvWriter::new(file).batched(&transform::output_schema())?;
+        self.file = Some((staged, writer));
+        Ok(())
+    }
+
+    async fn write_batch(&mut self, batch: &DataFrame) -> Result<(), Box<dyn Error>> {
+        let name = self.name();
+        let (_, writer) = self.file.as_mut().ok_or_else(|| not_open(name))?;
+        writer.write_batch(&transform::select_schema(
+            batch,
+            &transform::output_schema(),
+        )?)?;
+        self.rows += batch.height();
+        Ok(())
+    }
+
+    async fn commit(&mut self) -> Result<(), Box<dyn Error>> {
+        let (staged, mut writer) = self.file.take().ok_or_else(|| not_open(self.name()))?;
+        writer.finish()?;
+        drop(writer);
+        staged.commit()?;
+        tracing::info!(
+            rows = self.rows,
+            path = %self.path.display(),
+            "{} movies written to {}.",
+            self.rows,
+            self.path.display()
+        );
+        Ok(())
+    }
+
+    async fn abort(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some((staged, writer)) = self.file.take() {
+            drop(writer);
+            staged.abort()?;
+        }
+        Ok(())
+    }
+}
+
+/// Struct representing a sink that writes the movies to a file with one JSON object per line.
+pub struct NdjsonSink {
+    path: PathBuf,
+    file: Option<(StagedFile, BufWriter<File>)>,
+    rows: usize,
+}
+
+impl NdjsonSink {
+    /// Creates an NDJSON sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The NDJSON file.
+    pub fn new(path: PathBuf) -> Self {
+        NdjsonSink {
+            path,
+            file: None,
+            rows: 0,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Sink for NdjsonSink {
+    fn name(&self) -> String {
+        format!("NDJSON file {}", self.path.display())
+    }
+
+    async fn open(&mut self) -> Result<(), Box<dyn Error>> {
+        let (staged, file) = StagedFile::create(&self.path)?;
+        self.file = Some((staged, BufWriter::new(file)));
+        Ok(())
+    }
+
+    async fn write_batch(&mut self, batch: &DataFrame) -> Result<(), Box<dyn Error>> {
+        let name = self.name();
+        let (_, writer) = self.file.as_mut().ok_or_else(|| not_open(name))?;
+        for movie in transform::dataframe_to_movies(batch)? {
+            serde_json::to_writer(&mut *writer, &movie)?;
+            writer.write_all(b"\n")?;
+        }
+        self.rows += batch.height();
+        Ok(())
+    }
+
+    async fn commit(&mut self) -> Result<(), Box<dyn Error>> {
+        let (staged, mut writer) = self.file.take().ok_or_else(|| not_open(self.name()))?;
+        writer.flush()?;
+        drop(writer);
+        staged.commit()?;
+        tracing::info!(
+            rows = self.rows,
+            path = %self.path.display(),
+            "{} movies written to {}.",
+            self.rows,
+            self.path.display()
+        );
+        Ok(())
+    }
+
+    async fn abort(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some((staged, writer)) = self.file.take() {
+            drop(writer);
+            staged.abort()?;
+        }
+        Ok(())
+    }
+}
+
+/// Struct representing a sink that writes the movies to Parquet files.
+pub struct ParquetSink {
+    config: ParquetConfig,
+    output: Option<ParquetOutput>,
+}
+
+impl ParquetSink {
+    /// Creates a Parquet sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The Parquet settings.
+    pub fn new(config: ParquetConfig) -> Self {
+        ParquetSink {
+            config,
+            output: None,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Sink for ParquetSink {
+    fn name(&self) -> String {
+        format!("Parquet output {}", self.config.path.display())
+    }
+
+    async fn open(&mut self) -> Result<(), Box<dyn Error>> {
+        self.output = Some(ParquetOutput::create(&self.config)?);
+        Ok(())
+    }
+
+    async fn write_batch(&mut self, batch: &DataFrame) -> Result<(), Box<dyn Error>> {
+        let name = self.name();
+        let output = self.output.as_mut().ok_or_else(|| not_open(name))?;
+        Ok(output.write(batch)?)
+    }
+
+    async fn commit(&mut self) -> Result<(), Box<dyn Error>> {
+        let output = self.output.take().ok_or_else(|| not_open(self.name()))?;
+        let stats = output.finish()?;
+        tracing::info!(
+            rows = stats.rows,
+            files = stats.files,
+            "{} movies written to {} Parquet file(s) at {}.",
+            stats.rows,
+            stats.files,
+            self.config.path.display()
+        );
+        Ok(())
+    }
+
+    async fn abort(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(output) = self.output.take() {
+            output.abort()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use polars::prelude::*;
+    use tempfile::tempdir;
+
+    fn movies(ids: &[i64]) -> DataFrame {
+        let columns = transform::movie_schema()
+            .iter()
+            .map(|(name, dtype)| match name.as_str() {
+                "filmtv_id" => col("filmtv_id"),
+                "title" => lit("Movie").alias("title"),
+                _ => lit(NULL).cast(dtype.clone()).alias(name),
+            })
+            .collect::<Vec<_>>();
+        df!["filmtv_id" => ids]
+            .unwrap()
+            .lazy()
+            .select(columns)
+            .collect()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_write_to_sinks_fans_out_and_commits() {
+        let temp_dir = tempdir().unwrap();
+        let csv_path = temp_dir.path().join("out").join("movies.csv");
+        let ndjson_path = temp_dir.path().join("movies.ndjson");
+        let mut sinks = create_sinks(
+            &[
+                SinkConfig::Csv {
+                    path: csv_path.clone(),
+                },
+                SinkConfig::Ndjson {
+                    path: ndjson_path.clone(),
+                },
+            ],
+            None,
+            &LoadOptions::default(),
+        )
+        .unwrap();
+
+        let batches = vec![Ok::<_, Box<dyn Error>>(movies(&[1, 2])), Ok(movies(&[3]))];
+        let rows = write_to_sinks(&mut sinks, stream::iter(batches), false)
+            .await
+            .unwrap();
+
+        assert_eq!(rows, 3);
+        let csv = fs::read_to_string(&csv_path).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("filmtv_id,title,year,"));
+        assert!(lines[3].starts_with("3,Movie,,"));
+        let ndjson = fs::read_to_string(&ndjson_path).unwrap();
+        let first: serde_json::Value =
+            serde_json::from_str(ndjson.lines().next().unwrap()).unwrap();
+        assert_eq!(first["filmtv_id"], 1);
+        assert_eq!(first["title"], "Movie");
+        assert_eq!(first["year"], serde_json::Value::Null);
+        assert_eq!(ndjson.lines().count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_write_to_sinks_aborts_on_source_error() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("movies.csv");
+        fs::write(&path, "previous output").unwrap();
+        let mut sinks = create_sinks(
+            &[SinkConfig::Csv { path: path.clone() }],
+            None,
+            &LoadOptions::default(),
+        )
+        .unwrap();
+
+        let batches: Vec<Result<DataFrame, Box<dyn Error>>> =
+            vec![Ok(movies(&[1])), Err("bad batch".into())];
+        let error = write_to_sinks(&mut sinks, stream::iter(batches), false)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, SinkError::Source { batch: 2, .. }));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "previous output");
+        assert!(!staging_path(&path).exists());
+    }
+
+    #[tokio::test]
+    async fn test_write_to_sinks_dry_run_discards_output() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("movies.ndjson");
+        fs::write(&path, "previous output").unwrap();
+        let mut sinks = create_sinks(
+            &[SinkConfig::for_output(&path).unwrap()],
+            None,
+            &LoadOptions::default(),
+        )
+        .unwrap();
+
+        let batches = vec![Ok::<_, Box<dyn Error>>(movies(&[1, 2]))];
+        let rows = write_to_sinks(&mut sinks, stream::iter(batches), true)
+            .await
+            .unwrap();
+
+        assert_eq!(rows, 2);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "previous output");
+        assert!(!staging_path(&path).exists());
+        assert!(SinkConfig::for_output(Path::new("movies.arrow")).is_err());
+    }
+
+    #[test]
+    fn test_sink_configs_and_create_sinks() {
+        #[derive(Deserialize)]
+        struct Sinks {
+            sinks: Vec<SinkConfig>,
+        }
+
+        let sinks: Sinks = toml::from_str(
+            "[[sinks]]\ntype = \"postgres\"\n\
+             [[sinks]]\ntype = \"ndjson\"\npath = \"movies.ndjson\"\n\
+             [[sinks]]\ntype = \"parquet\"\npath = \"lake\"\npartition_by = \"genre\"",
+        )
+        .unwrap();
+        assert_eq!(sinks.sinks[0], SinkConfig::Postgres);
+        assert_eq!(
+            sinks.sinks[1],
+            SinkConfig::Ndjson {
+                path: PathBuf::from("movies.ndjson")
+            }
+        );
+        assert!(
+            matches!(&sinks.sinks[2], SinkConfig::Parquet(parquet) if parquet.path == Path::new("lake"))
+        );
+
+        assert!(matches!(
+            create_sinks(&sinks.sinks, None, &LoadOptions::default()),
+            Err(ConfigError::DatabaseNotConfigured)
+        ));
+        let created =
+            create_sinks(&sinks.sinks, Some(&Config::new()), &LoadOptions::default()).unwrap();
+        assert_eq!(created[0].name(), "PostgreSQL");
+        assert_eq!(created[2].name(), "Parquet output lake");
+    }
+}
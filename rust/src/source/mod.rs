@@ -0,0 +1,856 @@
+//! This module provides the `Source` trait, which abstracts where the raw movies are read from,
+//! and its implementations: `FileSource` reads CSV, NDJSON, JSON array, Parquet and Arrow IPC
+//! files, choosing the format of every file by its extension, `TolerantSource` reads CSV files
+//! in tolerant mode, and `PostgresSource` re-reads an existing database table for re-processing.
+//!
+//! Every source yields batches of `Movie` structs, so the transformation and the sinks are the
+//! same regardless of the input format. The optional `[source]` section of the configuration file
+//! (see `SourceConfig`) overrides the format or selects the database table, and the columns of
+//! files are renamed with the `ColumnMapping` of the `[input.columns]` section.
+//!
+//! Files are opened and read on Tokio's blocking thread pool, so neither the disk nor the retries
+//! of a file that cannot be opened yet stall the other tasks of the runtime.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use futures::stream::{self, Stream};
+use lazy_static::lazy_static;
+use polars::prelude::{col, lit, IdxSize, LazyFrame, ScanArgsIpc, ScanArgsParquet, NULL};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{Number, Value};
+use tokio_postgres::{Client, Config, Row};
+
+use crate::config::{ConfigError, IDENTIFIER};
+use crate::db;
+use crate::ingestion::{self, ColumnMapping, TolerantBatches, TolerantOptions, DEFAULT_BATCH_SIZE};
+use crate::metrics;
+use crate::models::{IngestReport, IngestionError, Movie, Provenance};
+use crate::transform;
+use crate::utils::parse_field;
+
+/// Columns of a `Movie` holding whole numbers.
+const INTEGER_COLUMNS: [&str; 9] = [
+    "filmtv_id",
+    "year",
+    "duration",
+    "total_votes",
+    "humor",
+    "rhythm",
+    "effort",
+    "tension",
+    "erotism",
+];
+
+/// Columns of a `Movie` holding decimal numbers.
+const FLOAT_COLUMNS: [&str; 3] = ["avg_vote", "critics_vote", "public_vote"];
+
+/// Table re-read by a `postgres` source unless another one is configured.
+const DEFAULT_TABLE: &str = "Movie";
+
+lazy_static! {
+    /// Pattern of the table names a `postgres` source accepts: an optionally schema-qualified,
+    /// unquoted identifier.
+    static ref TABLE_NAME: Regex =
+        Regex::new(&format!(r"^{id}(\.{id})?$", id = IDENTIFIER)).unwrap();
+}
+
+/// Trait implemented by the inputs the raw movies are read from.
+#[async_trait(?Send)]
+pub trait Source {
+    /// Returns a short description of the input, used in messages.
+    fn name(&self) -> String;
+
+    /// Reads the next batch of movies.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the next non-empty batch, or `None` once the input is exhausted, or
+    /// an `IngestionError`.
+    async fn next_batch(&mut self) -> Result<Option<Vec<Movie>>, IngestionError>;
+
+    /// Returns the counts of the rows accepted and rejected so far, or `None` if the source
+    /// does not reject rows.
+    fn report(&self) -> Option<IngestReport> {
+        None
+    }
+}
+
+/// Returns the batches of a source as a stream, for consumers that pull them one at a time.
+///
+/// # Arguments
+///
+/// * `source` - The source to read.
+///
+/// # Returns
+///
+/// A stream of batches of `Movie` structs, or the errors that interrupted them.
+pub fn batches(
+    source: &mut dyn Source,
+) -> impl Stream<Item = Result<Vec<Movie>, IngestionError>> + '_ {
+    stream::unfold(source, |source| async move {
+        source
+            .next_batch()
+            .await
+            .transpose()
+            .map(|batch| (batch, source))
+    })
+}
+
+/// Reads every batch of a source into a single vector.
+///
+/// # Arguments
+///
+/// * `source` - The source to read.
+///
+/// # Returns
+///
+/// A `Result` containing all `Movie` structs of the source, or the first `IngestionError`.
+pub async fn read_all(source: &mut dyn Source) -> Result<Vec<Movie>, IngestionError> {
+    let mut movies = Vec::new();
+    while let Some(batch) = source.next_batch().await? {
+        movies.extend(batch);
+    }
+    Ok(movies)
+}
+
+/// Enum representing the formats of the files a `FileSource` reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    /// CSV with a header row.
+    Csv,
+    /// One JSON object per line.
+    Ndjson,
+    /// A JSON array of objects.
+    Json,
+    /// Apache Parquet.
+    Parquet,
+    /// Arrow IPC, also known as Feather.
+    Ipc,
+}
+
+impl FileFormat {
+    /// Chooses the format of a file by its extension.
+    ///
+    /// `.ndjson` and `.jsonl` files are read as NDJSON, `.json` files as JSON arrays, `.parquet`
+    /// and `.pq` files as Parquet and `.arrow`, `.ipc` and `.feather` files as Arrow IPC. Every
+    /// other file is read as CSV.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file.
+    ///
+    /// # Returns
+    ///
+    /// The `FileFormat` of the file.
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("ndjson" | "jsonl") => FileFormat::Ndjson,
+            Some("json") => FileFormat::Json,
+            Some("parquet" | "pq") => FileFormat::Parquet,
+            Some("arrow" | "ipc" | "feather") => FileFormat::Ipc,
+            _ => FileFormat::Csv,
+        }
+    }
+
+    /// Returns the extension of the files selected in an input directory.
+    pub fn extension(self) -> &'static str {
+        match self {
+            FileFormat::Csv => "csv",
+            FileFormat::Ndjson => "ndjson",
+            FileFormat::Json => "json",
+            FileFormat::Parquet => "parquet",
+            FileFormat::Ipc => "arrow",
+        }
+    }
+}
+
+/// Enum representing the `[source]` section of the configuration file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SourceConfig {
+    /// Read every input file as CSV.
+    Csv,
+    /// Read every input file as NDJSON.
+    Ndjson,
+    /// Read every input file as a JSON array.
+    Json,
+    /// Read every input file as Parquet.
+    Parquet,
+    /// Read every input file as Arrow IPC.
+    Ipc,
+    /// Re-read an existing table of the database configured by the `[database]` section instead
+    /// of the input files.
+    Postgres {
+        /// The table to read, which must have the columns of the `Movie` table.
+        #[serde(default = "default_table")]
+        table: String,
+    },
+}
+
+/// Returns the table a `postgres` source reads unless another one is configured.
+fn default_table() -> String {
+    DEFAULT_TABLE.to_string()
+}
+
+impl SourceConfig {
+    /// Returns the format the input files are read as, or `None` for a `postgres` source.
+    pub fn format(&self) -> Option<FileFormat> {
+        match self {
+            SourceConfig::Csv => Some(FileFormat::Csv),
+            SourceConfig::Ndjson => Some(FileFormat::Ndjson),
+            SourceConfig::Json => Some(FileFormat::Json),
+            SourceConfig::Parquet => Some(FileFormat::Parquet),
+            SourceConfig::Ipc => Some(FileFormat::Ipc),
+            SourceConfig::Postgres { .. } => None,
+        }
+    }
+
+    /// Checks the settings of the source.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `ConfigError` if the table name is not a plain,
+    /// optionally schema-qualified identifier.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match self {
+            SourceConfig::Postgres { table } if !TABLE_NAME.is_match(table) => {
+                Err(ConfigError::InvalidValue {
+                    key: "source.table".to_string(),
+                    value: table.clone(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Iterator over batches of `Movie` structs.
+type MovieBatchIter = Box<dyn Iterator<Item = Result<Vec<Movie>, IngestionError>> + Send>;
+
+/// Runs a blocking function on Tokio's blocking thread pool and waits for its result. The
+/// function runs in the caller's span, so its messages keep the run and stage, and a panic of the
+/// function is resumed in the caller.
+pub async fn blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || span.in_scope(f))
+        .await
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
+/// Reads the next batch of an iterator on the blocking thread pool.
+///
+/// # Returns
+///
+/// The iterator, to be read again, and its next batch.
+async fn next_blocking<I>(mut batches: I) -> (I, Option<Result<Vec<Movie>, IngestionError>>)
+where
+    I: Iterator<Item = Result<Vec<Movie>, IngestionError>> + Send + 'static,
+{
+    blocking(move || {
+        let batch = batches.next();
+        (batches, batch)
+    })
+    .await
+}
+
+/// Struct representing a source that reads one file after another; a batch never spans two
+/// files.
+pub struct FileSource {
+    paths: VecDeque<PathBuf>,
+    files: usize,
+    format: Option<FileFormat>,
+    columns: ColumnMapping,
+    batch_size: usize,
+    /// The format and batches of the file being read.
+    current: Option<(FileFormat, MovieBatchIter)>,
+}
+
+impl FileSource {
+    /// Creates a file source.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - The files to read.
+    /// * `format` - The format of every file, or `None` to choose it by each file's extension.
+    /// * `columns` - The mapping of the files' column names to the `Movie` columns.
+    /// * `batch_size` - The maximum number of movies per batch. A value of `0` is treated as `1`.
+    pub fn new(
+        paths: Vec<PathBuf>,
+        format: Option<FileFormat>,
+        columns: ColumnMapping,
+        batch_size: usize,
+    ) -> Self {
+        FileSource {
+            files: paths.len(),
+            paths: paths.into(),
+            format,
+            columns,
+            batch_size: batch_size.max(1),
+            current: None,
+        }
+    }
+
+    /// Opens a file as an iterator of batches in the given format. This blocks, see `blocking`.
+    fn open(
+        path: PathBuf,
+        format: FileFormat,
+        columns: &ColumnMapping,
+        batch_size: usize,
+    ) -> Result<MovieBatchIter, IngestionError> {
+        Ok(match format {
+            FileFormat::Csv => Box::new(ingestion::ingest_csv_batches(&path, columns, batch_size)?),
+            FileFormat::Ndjson => Box::new(NdjsonBatches {
+                lines: BufReader::new(ingestion::open_file(&path)?).lines(),
+                path,
+                columns: columns.clone(),
+                batch_size,
+                line: 0,
+            }),
+            FileFormat::Json => json_batches(path, columns, batch_size)?,
+            FileFormat::Parquet => {
+                let lf = LazyFrame::scan_parquet(&path, ScanArgsParquet::default())?;
+                frame_batches(lf, path, columns, batch_size)?
+            }
+            FileFormat::Ipc => {
+                let lf = LazyFrame::scan_ipc(&path, ScanArgsIpc::default())?;
+                frame_batches(lf, path, columns, batch_size)?
+            }
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl Source for FileSource {
+    fn name(&self) -> String {
+        format!("{} file(s)", self.files)
+    }
+
+    async fn next_batch(&mut self) -> Result<Option<Vec<Movie>>, IngestionError> {
+        loop {
+            if let Some((format, batches)) = self.current.take() {
+                let (batches, batch) = next_blocking(batches).await;
+                if let Some(batch) = batch {
+                    self.current = Some((format, batches));
+                    let batch = batch?;
+                    // CSV batches are counted by `ingestion::ingest_csv_batches`
+                    if format != FileFormat::Csv {
+                        metrics::ROWS_READ
+                            .with_label_values(&[format.extension()])
+                            .inc_by(batch.len() as u64);
+                    }
+                    return Ok(Some(batch));
+                }
+            }
+            let Some(path) = self.paths.pop_front() else {
+                return Ok(None);
+            };
+            let format = self.format.unwrap_or_else(|| FileFormat::from_path(&path));
+            let columns = self.columns.clone();
+            let batch_size = self.batch_size;
+            let batches =
+                blocking(move || FileSource::open(path, format, &columns, batch_size)).await?;
+            self.current = Some((format, batches));
+        }
+    }
+}
+
+/// Struct representing a source that reads CSV files in tolerant mode, writing the rows that
+/// cannot be parsed to the dead-letter file instead of failing.
+pub struct TolerantSource {
+    files: usize,
+    /// The batches, `None` only while a batch is being read.
+    batches: Option<TolerantBatches>,
+}
+
+impl TolerantSource {
+    /// Creates a tolerant source.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - The CSV files to read.
+    /// * `columns` - The mapping of the files' column names to the `Movie` columns.
+    /// * `batch_size` - The maximum number of movies per batch. A value of `0` is treated as `1`.
+    /// * `options` - The tolerant ingestion options.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the source, or an `IngestionError` if the dead-letter file cannot be
+    /// created.
+    pub fn new(
+        paths: Vec<PathBuf>,
+        columns: ColumnMapping,
+        batch_size: usize,
+        options: &TolerantOptions,
+    ) -> Result<Self, IngestionError> {
+        Ok(TolerantSource {
+            files: paths.len(),
+            batches: Some(ingestion::ingest_files_tolerant_batches(
+                paths, columns, batch_size, options,
+            )?),
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl Source for TolerantSource {
+    fn name(&self) -> String {
+        format!("{} file(s) in tolerant mode", self.files)
+    }
+
+    async fn next_batch(&mut self) -> Result<Option<Vec<Movie>>, IngestionError> {
+        let Some(batches) = self.batches.take() else {
+            return Ok(None);
+        };
+        let (batches, batch) = next_blocking(batches).await;
+        self.batches = Some(batches);
+        batch.transpose()
+    }
+
+    fn report(&self) -> Option<IngestReport> {
+        self.batches
+            .as_ref()
+            .map(|batches| batches.report().clone())
+    }
+}
+
+/// Iterator over batches of `Movie` structs read lazily from an NDJSON file. Blank lines are
+/// skipped.
+struct NdjsonBatches {
+    lines: io::Lines<BufReader<File>>,
+    path: PathBuf,
+    columns: ColumnMapping,
+    batch_size: usize,
+    line: u64,
+}
+
+impl Iterator for NdjsonBatches {
+    type Item = Result<Vec<Movie>, IngestionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut batch = Vec::with_capacity(self.batch_size.min(DEFAULT_BATCH_SIZE));
+
+        while batch.len() < self.batch_size {
+            let Some(line) = self.lines.next() else {
+                break;
+            };
+            self.line += 1;
+
+            let movie = line.map_err(IngestionError::from).and_then(|line| {
+                if line.trim().is_empty() {
+                    return Ok(None);
+                }
+                let value = serde_json::from_str(&line)?;
+                json_to_movie(value, &self.path, &self.columns, self.line).map(Some)
+            });
+            match movie {
+                Ok(Some(movie)) => batch.push(movie),
+                Ok(None) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        if batch.is_empty() {
+            None
+        } else {
+            Some(Ok(batch))
+        }
+    }
+}
+
+/// Reads a JSON array file, which is parsed as a whole, and splits its movies into batches.
+fn json_batches(
+    path: PathBuf,
+    columns: &ColumnMapping,
+    batch_size: usize,
+) -> Result<MovieBatchIter, IngestionError> {
+    let reader = BufReader::new(ingestion::open_file(&path)?);
+    let values: Vec<Value> = serde_json::from_reader(reader)?;
+    let mut movies = values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| json_to_movie(value, &path, columns, index as u64 + 1))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter();
+
+    Ok(Box::new(std::iter::from_fn(move || {
+        let batch = movies.by_ref().take(batch_size).collect::<Vec<_>>();
+        (!batch.is_empty()).then_some(Ok(batch))
+    })))
+}
+
+/// Converts a JSON object into a `Movie` struct.
+///
+/// Like CSV fields, numbers may be given as strings, and strings that cannot be parsed as the
+/// column's number type are read as null.
+///
+/// # Arguments
+///
+/// * `value` - The JSON object.
+/// * `path` - The file the object was read from, recorded as the movie's provenance.
+/// * `columns` - The mapping of the object's keys to the `Movie` columns.
+/// * `line` - The line of the object, or its one-based position in a JSON array.
+///
+/// # Returns
+///
+/// A `Result` containing a `Movie` struct or an `IngestionError`.
+fn json_to_movie(
+    mut value: Value,
+    path: &Path,
+    columns: &ColumnMapping,
+    line: u64,
+) -> Result<Movie, IngestionError> {
+    if let Value::Object(fields) = &mut value {
+        *fields = std::mem::take(fields)
+            .into_iter()
+            .map(|(name, field)| (columns.target(&name).to_string(), field))
+            .collect();
+        for (name, field) in fields.iter_mut() {
+            let Value::String(text) = field else {
+                continue;
+            };
+            if INTEGER_COLUMNS.contains(&name.as_str()) {
+                *field = parse_field::<i64>(text).map_or(Value::Null, Value::from);
+            } else if FLOAT_COLUMNS.contains(&name.as_str()) {
+                *field = parse_field::<f64>(text)
+                    .and_then(Number::from_f64)
+                    .map_or(Value::Null, Value::Number);
+            }
+        }
+    }
+
+    let mut movie: Movie = serde_json::from_value(value)?;
+    movie.source = Some(Provenance {
+        file: path.to_path_buf(),
+        line,
+    });
+
+    Ok(movie)
+}
+
+/// Reads a scanned Parquet or Arrow IPC file one slice of rows at a time.
+///
+/// The columns are renamed with `columns` and cast to the types of `transform::movie_schema`;
+/// missing columns are read as null and other columns are ignored.
+fn frame_batches(
+    lf: LazyFrame,
+    path: PathBuf,
+    columns: &ColumnMapping,
+    batch_size: usize,
+) -> Result<MovieBatchIter, IngestionError> {
+    let mut lf = lf;
+    let available = lf.schema()?;
+    let selected = transform::movie_schema()
+        .iter()
+        .map(|(name, dtype)| {
+            let source = columns.source(name);
+            if available.contains(source) {
+                col(source).cast(dtype.clone()).alias(name)
+            } else {
+                lit(NULL).cast(dtype.clone()).alias(name)
+            }
+        })
+        .collect::<Vec<_>>();
+    let lf = lf.select(selected);
+
+    let mut offset = 0;
+    let mut done = false;
+    Ok(Box::new(std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let batch = lf
+            .clone()
+            .slice(offset as i64, batch_size as IdxSize)
+            .collect()
+            .and_then(|df| transform::dataframe_to_movies(&df))
+            .map(|movies| {
+                let first_row = offset as u64 + 1;
+                movies
+                    .into_iter()
+                    .zip(first_row..)
+                    .map(|(mut movie, line)| {
+                        movie.source = Some(Provenance {
+                            file: path.clone(),
+                            line,
+                        });
+                        movie
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .map_err(IngestionError::from);
+        match &batch {
+            Ok(movies) if movies.is_empty() => return None,
+            Ok(movies) => offset += movies.len(),
+            Err(_) => done = true,
+        }
+        Some(batch)
+    })))
+}
+
+/// Struct representing a source that re-reads an existing table with the columns of the `Movie`
+/// table, in `filmtv_id` order.
+///
+/// The table is paged by `filmtv_id`, so only one batch is held in memory at a time and every
+/// page is a cheap index range scan.
+pub struct PostgresSource {
+    client: Client,
+    table: String,
+    batch_size: usize,
+    last_id: Option<i64>,
+    done: bool,
+}
+
+impl PostgresSource {
+    /// Connects to the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The connection settings.
+    /// * `table` - The table to read, checked by `SourceConfig::validate`.
+    /// * `batch_size` - The maximum number of movies per batch. A value of `0` is treated as `1`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `PostgresSource` or an `IngestionError`.
+    pub async fn connect(
+        config: &Config,
+        table: &str,
+        batch_size: usize,
+    ) -> Result<Self, IngestionError> {
+        Ok(PostgresSource {
+            client: db::connect_db(config).await?,
+            table: table.to_string(),
+            batch_size: batch_size.max(1),
+            last_id: None,
+            done: false,
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl Source for PostgresSource {
+    fn name(&self) -> String {
+        format!("PostgreSQL table {}", self.table)
+    }
+
+    async fn next_batch(&mut self) -> Result<Option<Vec<Movie>>, IngestionError> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let query = format!(
+            "SELECT {} FROM {} WHERE $1::BIGINT IS NULL OR filmtv_id > $1 \
+             ORDER BY filmtv_id LIMIT $2",
+            db::column_list(),
+            self.table
+        );
+        let rows = self
+            .client
+            .query(&query, &[&self.last_id, &(self.batch_size as i64)])
+            .await?;
+        self.done = rows.len() < self.batch_size;
+
+        let movies = rows
+            .iter()
+            .map(row_to_movie)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.last_id = movies.last().and_then(|movie| movie.filmtv_id);
+        metrics::ROWS_READ
+            .with_label_values(&["postgres"])
+            .inc_by(movies.len() as u64);
+
+        Ok((!movies.is_empty()).then_some(movies))
+    }
+}
+
+/// Converts a row of the `Movie` table into a `Movie` struct.
+fn row_to_movie(row: &Row) -> Result<Movie, IngestionError> {
+    Ok(Movie {
+        filmtv_id: row.try_get("filmtv_id")?,
+        title: row.try_get("title")?,
+        year: row.try_get("year")?,
+        genre: row.try_get("genre")?,
+        duration: row.try_get("duration")?,
+        country: row.try_get("country")?,
+        directors: row.try_get("directors")?,
+        actors: row.try_get("actors")?,
+        avg_vote: row.try_get("avg_vote")?,
+        critics_vote: row.try_get("critics_vote")?,
+        public_vote: row.try_get("public_vote")?,
+        total_votes: row.try_get("total_votes")?,
+        description: row.try_get("description")?,
+        notes: row.try_get("notes")?,
+        humor: row.try_get("humor")?,
+        rhythm: row.try_get("rhythm")?,
+        effort: row.try_get("effort")?,
+        tension: row.try_get("tension")?,
+        erotism: row.try_get("erotism")?,
+        source: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::{df, IpcWriter, ParquetWriter, SerWriter};
+    use std::fs;
+    use tempfile::tempdir;
+
+    async fn read_batches(source: &mut dyn Source) -> Vec<Vec<Movie>> {
+        let mut batches = Vec::new();
+        while let Some(batch) = source.next_batch().await.unwrap() {
+            batches.push(batch);
+        }
+        batches
+    }
+
+    #[test]
+    fn test_file_format_from_path() {
+        assert_eq!(FileFormat::from_path(Path::new("a.CSV")), FileFormat::Csv);
+        assert_eq!(FileFormat::from_path(Path::new("a.txt")), FileFormat::Csv);
+        assert_eq!(
+            FileFormat::from_path(Path::new("a.jsonl")),
+            FileFormat::Ndjson
+        );
+        assert_eq!(FileFormat::from_path(Path::new("a.json")), FileFormat::Json);
+        assert_eq!(
+            FileFormat::from_path(Path::new("a.pq")),
+            FileFormat::Parquet
+        );
+        assert_eq!(
+            FileFormat::from_path(Path::new("a.feather")),
+            FileFormat::Ipc
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_source_reads_json_formats() {
+        let temp_dir = tempdir().unwrap();
+        let ndjson = temp_dir.path().join("movies.ndjson");
+        fs::write(
+            &ndjson,
+            "{\"filmtv_id\": 1, \"title\": \"A\", \"year\": \"1999\"}\n\n\
+             {\"filmtv_id\": 2, \"avg_vote\": \"n/a\", \"extra\": true}\n\
+             {\"filmtv_id\": 3}\n",
+        )
+        .unwrap();
+        let json = temp_dir.path().join("movies.json");
+        fs::write(&json, "[{\"filmtv_id\": 4, \"avg_vote\": 7.5}]").unwrap();
+
+        let mut source = FileSource::new(
+            vec![ndjson.clone(), json.clone()],
+            None,
+            ColumnMapping::default(),
+            2,
+        );
+        let batches = read_batches(&mut source).await;
+
+        let sizes = batches.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![2, 1, 1]);
+        assert_eq!(batches[0][0].year, Some(1999));
+        assert_eq!(batches[0][1].avg_vote, None);
+        assert_eq!(batches[1][0].source.as_ref().unwrap().line, 4);
+        assert_eq!(batches[2][0].avg_vote, Some(7.5));
+        assert_eq!(
+            batches[2][0].source,
+            Some(Provenance {
+                file: json,
+                line: 1
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_source_reads_parquet_and_ipc() {
+        let temp_dir = tempdir().unwrap();
+        let mut frame = df![
+            "filmtv_id" => [1i32, 2, 3],
+            "title" => ["A", "B", "C"],
+            "avg_vote" => [6.5, 7.0, 8.5],
+            "unused" => [true, false, true],
+        ]
+        .unwrap();
+        let parquet = temp_dir.path().join("movies.parquet");
+        ParquetWriter::new(File::create(&parquet).unwrap())
+            .finish(&mut frame)
+            .unwrap();
+        let ipc = temp_dir.path().join("movies.arrow");
+        IpcWriter::new(File::create(&ipc).unwrap())
+            .finish(&mut frame)
+            .unwrap();
+
+        let mut source = FileSource::new(
+            vec![parquet, ipc.clone()],
+            None,
+            ColumnMapping::default(),
+            2,
+        );
+        let batches = read_batches(&mut source).await;
+
+        let sizes = batches.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![2, 1, 2, 1]);
+        assert_eq!(batches[1][0].filmtv_id, Some(3));
+        assert_eq!(batches[1][0].title, Some("C".to_string()));
+        assert_eq!(batches[1][0].avg_vote, Some(8.5));
+        assert_eq!(batches[1][0].year, None);
+        assert_eq!(
+            batches[3][0].source,
+            Some(Provenance { file: ipc, line: 3 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_all_stops_at_the_first_error() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("movies.ndjson");
+        fs::write(&path, "{\"filmtv_id\": 1}\nnot json\n").unwrap();
+
+        let mut source = FileSource::new(vec![path], None, ColumnMapping::default(), 10);
+
+        assert!(matches!(
+            read_all(&mut source).await,
+            Err(IngestionError::DeserializationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_source_config() {
+        #[derive(Deserialize)]
+        struct File {
+            source: SourceConfig,
+        }
+
+        let file: File = toml::from_str("[source]\ntype = \"postgres\"").unwrap();
+        assert_eq!(
+            file.source,
+            SourceConfig::Postgres {
+                table: "Movie".to_string()
+            }
+        );
+        assert_eq!(file.source.format(), None);
+        assert!(file.source.validate().is_ok());
+
+        let file: File = toml::from_str("[source]\ntype = \"ipc\"").unwrap();
+        assert_eq!(file.source.format(), Some(FileFormat::Ipc));
+
+        let invalid = SourceConfig::Postgres {
+            table: "movie; DROP TABLE movie".to_string(),
+        };
+        assert!(matches!(
+            invalid.validate(),
+            Err(ConfigError::InvalidValue { key, .. }) if key == "source.table"
+        ));
+    }
+}
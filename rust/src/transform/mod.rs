@@ -1,35 +1,438 @@
 //! This module provides functions to transform movie data from a list of `Movie` structs
-//! to a list of `TransformedMovie` structs. The transformation process includes converting
-//! the data to a DataFrame, cleaning and validating the data, and then converting it back
-//! to the desired format.
+//! into a cleaned Polars `DataFrame`. The transformation process includes converting the data
+//! to a DataFrame and cleaning and validating it; `dataframe_to_transformed_movies` converts the
+//! result to `TransformedMovie` structs for the database. `transform_lazy` runs the same cleaning
+//! and validation on a `LazyFrame` scanned from the CSV files, so they are part of one optimized
+//! query plan.
+//!
+//! Every movie keeps where it was read from in the `source_file` and `source_line` columns, which
+//! follow the columns of `movie_schema` (see `output_schema`), so cleaned rows, sink outputs and
+//! report samples can be traced back to their input.
+//!
+//! Missing values are handled according to a per-column `NullPolicy`, declared in the `[nulls]`
+//! section of the configuration file. By default they are kept as NULL. Movies that share a
+//! `filmtv_id` are resolved by the `dedup` module first, and the cleaned movies are then checked
+//! against the data-quality rules of the `validation` module.
 
-use crate::models::{Movie, TransformedMovie};
+use crate::config::ConfigError;
+use crate::dedup::{self, Duplicate, DuplicateConfig};
+use crate::models::{Movie, Provenance, TransformedMovie};
+use crate::validation::{self, DataQualityReport, ValidationConfig};
 use polars::prelude::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
 
-/// Transforms a list of `Movie` structs into a list of `TransformedMovie` structs.
+/// Column holding the file a movie was read from.
+pub const SOURCE_FILE: &str = "source_file";
+
+/// Column holding the line of its file a movie was read from.
+pub const SOURCE_LINE: &str = "source_line";
+
+/// Text columns, which are trimmed and left null when blank.
+const TEXT_COLUMNS: [&str; 7] = [
+    "title",
+    "genre",
+    "country",
+    "directors",
+    "actors",
+    "description",
+    "notes",
+];
+
+/// FilmTV mood score columns, which are nullable and range from 0 to 5.
+const MOOD_COLUMNS: [&str; 5] = ["humor", "rhythm", "effort", "tension", "erotism"];
+
+/// Highest valid mood score.
+const MAX_MOOD_SCORE: i64 = 5;
+
+/// Enum representing the type of a column, which decides the null policies and checks it accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ColumnKind {
+    Int,
+    Float,
+    Text,
+}
+
+impl ColumnKind {
+    /// Returns the Polars data type of the column.
+    fn data_type(self) -> DataType {
+        match self {
+            ColumnKind::Int => DataType::Int64,
+            ColumnKind::Float => DataType::Float64,
+            ColumnKind::Text => DataType::String,
+        }
+    }
+}
+
+/// Columns that may be NULL, with their types. `filmtv_id` is the primary key, so rows without it
+/// are always dropped.
+const NULLABLE_COLUMNS: [(&str, ColumnKind); 18] = [
+    ("title", ColumnKind::Text),
+    ("year", ColumnKind::Int),
+    ("genre", ColumnKind::Text),
+    ("duration", ColumnKind::Int),
+    ("country", ColumnKind::Text),
+    ("directors", ColumnKind::Text),
+    ("actors", ColumnKind::Text),
+    ("avg_vote", ColumnKind::Float),
+    ("critics_vote", ColumnKind::Float),
+    ("public_vote", ColumnKind::Float),
+    ("total_votes", ColumnKind::Int),
+    ("description", ColumnKind::Text),
+    ("notes", ColumnKind::Text),
+    ("humor", ColumnKind::Int),
+    ("rhythm", ColumnKind::Int),
+    ("effort", ColumnKind::Int),
+    ("tension", ColumnKind::Int),
+    ("erotism", ColumnKind::Int),
+];
+
+/// Returns the type of a column of the cleaned `DataFrame`.
+///
+/// # Arguments
+///
+/// * `name` - The name of the column.
+///
+/// # Returns
+///
+/// The `ColumnKind` of the column, or `None` if there is no such column.
+pub(crate) fn column_kind(name: &str) -> Option<ColumnKind> {
+    if name == "filmtv_id" {
+        return Some(ColumnKind::Int);
+    }
+
+    NULLABLE_COLUMNS
+        .iter()
+        .find(|(column, _)| *column == name)
+        .map(|(_, kind)| *kind)
+}
+
+/// Returns the schema of the movie CSV files and of the `Movie` table.
+pub fn movie_schema() -> Schema {
+    let mut schema = Schema::with_capacity(NULLABLE_COLUMNS.len() + 1);
+    schema.with_column("filmtv_id".into(), DataType::Int64);
+    for (name, kind) in NULLABLE_COLUMNS {
+        schema.with_column(name.into(), kind.data_type());
+    }
+    schema
+}
+
+/// Returns the schema of the cleaned `DataFrame`: the columns of `movie_schema` followed by the
+/// `source_file` and `source_line` columns.
+pub fn output_schema() -> Schema {
+    let mut schema = movie_schema();
+    schema.with_column(SOURCE_FILE.into(), DataType::String);
+    schema.with_column(SOURCE_LINE.into(), DataType::UInt64);
+    schema
+}
+
+/// Selects the columns of a schema from a `DataFrame`, in order. Missing columns, such as the
+/// source columns of movies that were not read from a file, are filled with nulls.
+///
+/// # Arguments
+///
+/// * `df` - The `DataFrame` to select from.
+/// * `schema` - The columns to select.
+///
+/// # Returns
+///
+/// A `Result` containing the selected `DataFrame` or a `PolarsError`.
+pub fn select_schema(df: &DataFrame, schema: &Schema) -> Result<DataFrame, PolarsError> {
+    let columns = schema
+        .iter()
+        .map(|(name, dtype)| match df.column(name) {
+            Ok(column) => column.cast(dtype),
+            Err(_) => Ok(Series::full_null(name, df.height(), dtype)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    DataFrame::new(columns)
+}
+
+/// Struct holding the configurable parts of the transformation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransformOptions {
+    /// How movies that share a `filmtv_id` are resolved.
+    pub duplicates: DuplicateConfig,
+    /// How missing values are handled.
+    pub null_policies: NullPolicies,
+    /// The data-quality rules and where their report is written.
+    pub validation: ValidationConfig,
+}
+
+impl TransformOptions {
+    /// Checks the duplicate settings, the null policies and the data-quality rules.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `ConfigError` for the first invalid setting.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.duplicates.validate()?;
+        self.null_policies.validate()?;
+        self.validation.validate()
+    }
+
+    /// Returns the first setting that needs every movie at once, which streaming mode cannot
+    /// honour because it transforms one batch at a time: a duplicate policy other than
+    /// `keep_last`, the fuzzy stage, a `unique` rule, or a `mean`, `median` or `mode` null policy.
+    ///
+    /// # Returns
+    ///
+    /// A description of the setting, or `None` if every setting can be applied batch by batch.
+    pub fn whole_input_setting(&self) -> Option<String> {
+        if self.duplicates.policy != dedup::DuplicatePolicy::KeepLast {
+            return Some(format!("duplicate policy {}", self.duplicates.policy));
+        }
+        if self.duplicates.fuzzy.is_some() {
+            return Some("the fuzzy duplicate stage".to_string());
+        }
+        if let Some(rule) = self
+            .validation
+            .rules
+            .iter()
+            .find(|rule| rule.check == validation::Check::Unique)
+        {
+            return Some(format!("unique rule {:?}", rule.name()));
+        }
+        self.null_policies
+            .0
+            .iter()
+            .find(|(_, policy)| {
+                matches!(
+                    policy,
+                    NullPolicy::Mean | NullPolicy::Median | NullPolicy::Mode
+                )
+            })
+            .map(|(column, policy)| format!("{} null policy of {}", policy, column))
+    }
+}
+
+/// Enum representing how missing values in a column are handled.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NullPolicy {
+    /// Keep missing values as NULL.
+    #[default]
+    Keep,
+    /// Replace missing values with a constant.
+    Fill(FillValue),
+    /// Replace missing values with the mean of the column, rounded for integer columns.
+    Mean,
+    /// Replace missing values with the median of the column, rounded for integer columns.
+    Median,
+    /// Replace missing values with the most frequent value of the column.
+    Mode,
+    /// Drop rows where the column is missing.
+    Drop,
+}
+
+impl fmt::Display for NullPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NullPolicy::Keep => write!(f, "keep"),
+            NullPolicy::Fill(value) => write!(f, "fill {}", value),
+            NullPolicy::Mean => write!(f, "mean"),
+            NullPolicy::Median => write!(f, "median"),
+            NullPolicy::Mode => write!(f, "mode"),
+            NullPolicy::Drop => write!(f, "drop"),
+        }
+    }
+}
+
+/// Enum representing the constant a `NullPolicy::Fill` replaces missing values with.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum FillValue {
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl FillValue {
+    /// Returns the value as a literal expression.
+    fn lit(&self) -> Expr {
+        match self {
+            FillValue::Int(value) => lit(*value),
+            FillValue::Float(value) => lit(*value),
+            FillValue::Text(value) => lit(value.clone()),
+        }
+    }
+}
+
+impl fmt::Display for FillValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FillValue::Int(value) => write!(f, "{}", value),
+            FillValue::Float(value) => write!(f, "{}", value),
+            FillValue::Text(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+/// Struct mapping column names to their `NullPolicy`. Columns without a policy keep NULL.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct NullPolicies(BTreeMap<String, NullPolicy>);
+
+impl NullPolicies {
+    /// Returns the policy of a column.
+    pub fn get(&self, column: &str) -> &NullPolicy {
+        const KEEP: &NullPolicy = &NullPolicy::Keep;
+        self.0.get(column).unwrap_or(KEEP)
+    }
+
+    /// Checks that every policy names a nullable column and suits its type: means and medians
+    /// need a numeric column, fill values must have the column's type and mood scores must be
+    /// filled with a valid score.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `ConfigError` for the first invalid policy.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (column, policy) in &self.0 {
+            let kind = column_kind(column).filter(|_| column != "filmtv_id");
+            let valid = match (kind, policy) {
+                (None, _) => false,
+                (Some(ColumnKind::Text), NullPolicy::Mean | NullPolicy::Median) => false,
+                (Some(kind), NullPolicy::Fill(value)) => match (kind, value) {
+                    (ColumnKind::Int, FillValue::Int(value)) => {
+                        !MOOD_COLUMNS.contains(&column.as_str())
+                            || (0..=MAX_MOOD_SCORE).contains(value)
+                    }
+                    (ColumnKind::Float, FillValue::Int(_) | FillValue::Float(_)) => true,
+                    (ColumnKind::Text, FillValue::Text(_)) => true,
+                    _ => false,
+                },
+                _ => true,
+            };
+
+            if !valid {
+                return Err(ConfigError::InvalidValue {
+                    key: format!("nulls.{}", column),
+                    value: policy.to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Transforms a list of `Movie` structs into a cleaned `DataFrame`.
+///
+/// Duplicates, fuzzy duplicates, means, medians, modes and uniqueness are computed over the movies
+/// passed in, which is why streaming mode rejects the settings listed by
+/// `TransformOptions::whole_input_setting`.
 ///
 /// # Arguments
 ///
 /// * `movies` - A vector of `Movie` structs to be transformed.
+/// * `options` - The duplicate policy, null policies and data-quality rules.
 ///
 /// # Returns
 ///
-/// A `Result` containing a vector of `TransformedMovie` structs or an error.
-pub fn transform_movies(movies: Vec<Movie>) -> Result<Vec<TransformedMovie>, Box<dyn Error>> {
+/// A `Result` containing the cleaned `DataFrame`, with the columns of `movie_schema`, and the
+/// `DataQualityReport`, or an error, e.g. a `DuplicateError` under the `fail` duplicate policy.
+/// Failed rules with the `fail` severity are reported, not raised; see `DataQualityReport::check`.
+pub fn transform_movies(
+    movies: Vec<Movie>,
+    options: &TransformOptions,
+) -> Result<(DataFrame, DataQualityReport), Box<dyn Error>> {
+    // Resolve movies that share a filmtv_id
+    let (movies, duplicates) = dedup::resolve_duplicates(movies, options.duplicates.policy)?;
+
     // Convert Vec<Movie> to DataFrame
     let df = movies_to_dataframe(movies)?;
 
-    // Clean and validate data
-    let cleaned_df = clean_and_validate_data(df)?;
+    clean_and_check(df.lazy(), Some(duplicates), options)
+}
 
-    // Convert cleaned DataFrame to Vec<TransformedMovie>
-    let transformed_movies = dataframe_to_transformed_movies(&cleaned_df)?;
+/// Transforms the movies of a `LazyFrame`, usually scanned with `ingestion::scan_csv`, into a
+/// cleaned `DataFrame`.
+///
+/// Duplicate resolution, cleaning, null handling and the data-quality rules are added to the
+/// query plan, which is collected once, so the input is only read once. Duplicates are read from
+/// the collected movies, so a duplicate whose kept movie is then dropped by the cleaning, for a
+/// `filmtv_id` that is not positive or a missing value under the `drop` null policy, is neither
+/// reported nor, under the `fail` policy, an error.
+///
+/// # Arguments
+///
+/// * `lf` - A `LazyFrame` with the columns of `output_schema`.
+/// * `options` - The duplicate policy, null policies and data-quality rules.
+///
+/// # Returns
+///
+/// A `Result` containing the cleaned `DataFrame` and the `DataQualityReport`, or an error, as for
+/// `transform_movies`.
+pub fn transform_lazy(
+    lf: LazyFrame,
+    options: &TransformOptions,
+) -> Result<(DataFrame, DataQualityReport), Box<dyn Error>> {
+    // Resolve movies that share a filmtv_id
+    let lf = dedup::resolve_duplicates_lazy(lf, options.duplicates.policy);
 
-    Ok(transformed_movies)
+    clean_and_check(lf, None, options)
+}
+
+/// Cleans a `LazyFrame`, runs the fuzzy duplicate stage if it is enabled and checks the
+/// data-quality rules.
+///
+/// The query plan is collected once: together with the rules or, if the fuzzy stage is enabled,
+/// before it, as it compares the cleaned movies in memory.
+///
+/// # Arguments
+///
+/// * `lf` - A `LazyFrame` with one movie per `filmtv_id`.
+/// * `duplicates` - The duplicates that were resolved, for the report, or `None` if they are in
+///   the plan of `lf`, see `dedup::resolve_duplicates_lazy`.
+/// * `options` - The duplicate policy, null policies, fuzzy stage settings and data-quality
+///   rules.
+///
+/// # Returns
+///
+/// A `Result` containing the movies that passed the rules and the `DataQualityReport`, or an
+/// error.
+fn clean_and_check(
+    lf: LazyFrame,
+    duplicates: Option<Vec<Duplicate>>,
+    options: &TransformOptions,
+) -> Result<(DataFrame, DataQualityReport), Box<dyn Error>> {
+    let take_duplicates = |df: DataFrame| match duplicates {
+        Some(duplicates) => Ok((df, duplicates)),
+        None => dedup::take_duplicates(df, options.duplicates.policy),
+    };
+    let rules = &options.validation.rules;
+
+    // Clean and validate data
+    let cleaned = clean(lf, &options.null_policies);
+    let ((valid_df, mut report), duplicates, clusters) = match &options.duplicates.fuzzy {
+        Some(fuzzy) => {
+            let (cleaned_df, duplicates) = take_duplicates(cleaned.collect()?)?;
+            let (cleaned_df, clusters) = dedup::fuzzy_dedup(cleaned_df, fuzzy)?;
+            let checked = validation::apply_rules(cleaned_df.lazy(), rules)?;
+            (checked, duplicates, clusters)
+        }
+        None => {
+            let collected = validation::add_rule_masks(cleaned, rules).collect()?;
+            let (checked_df, duplicates) = take_duplicates(collected)?;
+            (
+                validation::check_rules(checked_df, rules)?,
+                duplicates,
+                Vec::new(),
+            )
+        }
+    };
+    report.duplicates = duplicates;
+    report.fuzzy_clusters = clusters;
+
+    Ok((valid_df, report))
 }
 
-/// Converts a vector of `Movie` structs into a Polars `DataFrame`.
+/// Converts a vector of `Movie` structs into a Polars `DataFrame` with the columns of
+/// `output_schema`.
 ///
 /// # Arguments
 ///
@@ -38,17 +441,44 @@ pub fn transform_movies(movies: Vec<Movie>) -> Result<Vec<TransformedMovie>, Box
 /// # Returns
 ///
 /// A `Result` containing a `DataFrame` or a `PolarsError`.
-fn movies_to_dataframe(movies: Vec<Movie>) -> Result<DataFrame, PolarsError> {
+pub fn movies_to_dataframe(movies: Vec<Movie>) -> Result<DataFrame, PolarsError> {
     let filmtv_id = movies.iter().map(|m| m.filmtv_id).collect::<Vec<_>>();
     let title = movies.iter().map(|m| m.title.clone()).collect::<Vec<_>>();
     let year = movies.iter().map(|m| m.year).collect::<Vec<_>>();
     let genre = movies.iter().map(|m| m.genre.clone()).collect::<Vec<_>>();
     let duration = movies.iter().map(|m| m.duration).collect::<Vec<_>>();
     let country = movies.iter().map(|m| m.country.clone()).collect::<Vec<_>>();
+    let directors = movies
+        .iter()
+        .map(|m| m.directors.clone())
+        .collect::<Vec<_>>();
+    let actors = movies.iter().map(|m| m.actors.clone()).collect::<Vec<_>>();
     let avg_vote = movies.iter().map(|m| m.avg_vote).collect::<Vec<_>>();
     let critics_vote = movies.iter().map(|m| m.critics_vote).collect::<Vec<_>>();
     let public_vote = movies.iter().map(|m| m.public_vote).collect::<Vec<_>>();
     let total_votes = movies.iter().map(|m| m.total_votes).collect::<Vec<_>>();
+    let description = movies
+        .iter()
+        .map(|m| m.description.clone())
+        .collect::<Vec<_>>();
+    let notes = movies.iter().map(|m| m.notes.clone()).collect::<Vec<_>>();
+    let humor = movies.iter().map(|m| m.humor).collect::<Vec<_>>();
+    let rhythm = movies.iter().map(|m| m.rhythm).collect::<Vec<_>>();
+    let effort = movies.iter().map(|m| m.effort).collect::<Vec<_>>();
+    let tension = movies.iter().map(|m| m.tension).collect::<Vec<_>>();
+    let erotism = movies.iter().map(|m| m.erotism).collect::<Vec<_>>();
+    let source_file = movies
+        .iter()
+        .map(|m| {
+            m.source
+                .as_ref()
+                .map(|source| source.file.display().to_string())
+        })
+        .collect::<Vec<_>>();
+    let source_line = movies
+        .iter()
+        .map(|m| m.source.as_ref().map(|source| source.line))
+        .collect::<Vec<_>>();
 
     DataFrame::new(vec![
         Series::new("filmtv_id", filmtv_id),
@@ -57,42 +487,125 @@ fn movies_to_dataframe(movies: Vec<Movie>) -> Result<DataFrame, PolarsError> {
         Series::new("genre", genre),
         Series::new("duration", duration),
         Series::new("country", country),
+        Series::new("directors", directors),
+        Series::new("actors", actors),
         Series::new("avg_vote", avg_vote),
         Series::new("critics_vote", critics_vote),
         Series::new("public_vote", public_vote),
         Series::new("total_votes", total_votes),
+        Series::new("description", description),
+        Series::new("notes", notes),
+        Series::new("humor", humor),
+        Series::new("rhythm", rhythm),
+        Series::new("effort", effort),
+        Series::new("tension", tension),
+        Series::new("erotism", erotism),
+        Series::new(SOURCE_FILE, source_file),
+        Series::new(SOURCE_LINE, source_line),
     ])
 }
 
-/// Cleans and validates the data in a `DataFrame`.
+/// Adds the cleaning steps to a `LazyFrame`.
+///
+/// Rows without a positive `filmtv_id` are dropped. The text fields are trimmed and set to null
+/// when they are blank, and the mood scores are set to null when they fall outside the valid
+/// range of 0 to 5. Missing values are then handled according to the null policies.
 ///
 /// # Arguments
 ///
-/// * `df` - A `DataFrame` to be cleaned and validated.
+/// * `lf` - The `LazyFrame` to clean.
+/// * `null_policies` - How missing values are handled.
 ///
 /// # Returns
 ///
-/// A `Result` containing a cleaned `DataFrame` or a `PolarsError`.
-fn clean_and_validate_data(df: DataFrame) -> Result<DataFrame, PolarsError> {
-    let cleaned_df = df
-        .lazy()
-        .with_column(col("filmtv_id").fill_null(lit(0)))
-        .with_column(col("title").fill_null(lit("Unknown")))
-        .with_column(col("year").fill_null(lit(0)))
-        .with_column(col("genre").fill_null(lit("Unknown")))
-        .with_column(col("duration").fill_null(lit(0)))
-        .with_column(col("country").fill_null(lit("Unknown")))
-        .with_column(col("avg_vote").fill_null(lit(0.0)))
-        .with_column(col("critics_vote").fill_null(lit(0.0)))
-        .with_column(col("public_vote").fill_null(lit(0.0)))
-        .with_column(col("total_votes").fill_null(lit(0)))
+/// The cleaned `LazyFrame`.
+fn clean(lf: LazyFrame, null_policies: &NullPolicies) -> LazyFrame {
+    let cleaned = lf
         .filter(col("filmtv_id").gt(0))
-        .collect()?;
+        .with_columns(
+            TEXT_COLUMNS
+                .iter()
+                .map(|name| blank_to_null(col(name).str().strip_chars(lit(NULL))).alias(name))
+                .collect::<Vec<_>>(),
+        )
+        .with_columns(
+            MOOD_COLUMNS
+                .iter()
+                .map(|name| valid_mood_score(col(name)).alias(name))
+                .collect::<Vec<_>>(),
+        );
+
+    apply_null_policies(cleaned, null_policies)
+}
+
+/// Applies the null policies to a `LazyFrame`. Fill values are computed before any row is
+/// dropped.
+///
+/// # Arguments
+///
+/// * `lf` - The `LazyFrame` to apply the policies to.
+/// * `null_policies` - How missing values are handled.
+///
+/// # Returns
+///
+/// The resulting `LazyFrame`.
+fn apply_null_policies(lf: LazyFrame, null_policies: &NullPolicies) -> LazyFrame {
+    let fills = NULLABLE_COLUMNS
+        .iter()
+        .filter_map(|(name, kind)| {
+            let column = col(name);
+            let fill = match null_policies.get(name) {
+                NullPolicy::Keep | NullPolicy::Drop => return None,
+                NullPolicy::Fill(value) => value.lit(),
+                NullPolicy::Mean => round_if_int(column.clone().mean(), *kind),
+                NullPolicy::Median => round_if_int(column.clone().median(), *kind),
+                NullPolicy::Mode => column.clone().mode().first(),
+            };
+            Some(column.fill_null(fill.cast(kind.data_type())).alias(name))
+        })
+        .collect::<Vec<_>>();
+
+    let required = NULLABLE_COLUMNS
+        .iter()
+        .filter(|(name, _)| *null_policies.get(name) == NullPolicy::Drop)
+        .map(|(name, _)| col(name).is_not_null())
+        .reduce(|all, next| all.and(next));
+
+    let lf = lf.with_columns(fills);
+    match required {
+        Some(predicate) => lf.filter(predicate),
+        None => lf,
+    }
+}
 
-    Ok(cleaned_df)
+/// Rounds a statistic to the nearest integer if it is used to fill an integer column.
+fn round_if_int(expr: Expr, kind: ColumnKind) -> Expr {
+    match kind {
+        ColumnKind::Int => expr.round(0),
+        ColumnKind::Float | ColumnKind::Text => expr,
+    }
+}
+
+/// Returns the expression replacing empty strings with null.
+fn blank_to_null(expr: Expr) -> Expr {
+    when(expr.clone().eq(lit("")))
+        .then(lit(NULL))
+        .otherwise(expr)
 }
 
-/// Converts a cleaned `DataFrame` into a vector of `TransformedMovie` structs.
+/// Returns the expression replacing mood scores outside the valid range with null.
+fn valid_mood_score(expr: Expr) -> Expr {
+    when(
+        expr.clone()
+            .gt_eq(lit(0))
+            .and(expr.clone().lt_eq(lit(MAX_MOOD_SCORE))),
+    )
+    .then(expr)
+    .otherwise(lit(NULL))
+}
+
+/// Converts a cleaned `DataFrame` into a vector of `TransformedMovie` structs, with their source
+/// if the `DataFrame` has the source columns.
 ///
 /// # Arguments
 ///
@@ -101,7 +614,7 @@ fn clean_and_validate_data(df: DataFrame) -> Result<DataFrame, PolarsError> {
 /// # Returns
 ///
 /// A `Result` containing a vector of `TransformedMovie` structs or an error.
-fn dataframe_to_transformed_movies(
+pub fn dataframe_to_transformed_movies(
     df: &DataFrame,
 ) -> Result<Vec<TransformedMovie>, Box<dyn Error>> {
     let filmtv_id = df.column("filmtv_id")?.i64()?;
@@ -110,24 +623,44 @@ fn dataframe_to_transformed_movies(
     let genre = df.column("genre")?.str()?;
     let duration = df.column("duration")?.i64()?;
     let country = df.column("country")?.str()?;
+    let directors = df.column("directors")?.str()?;
+    let actors = df.column("actors")?.str()?;
     let avg_vote = df.column("avg_vote")?.f64()?;
     let critics_vote = df.column("critics_vote")?.f64()?;
     let public_vote = df.column("public_vote")?.f64()?;
     let total_votes = df.column("total_votes")?.i64()?;
+    let description = df.column("description")?.str()?;
+    let notes = df.column("notes")?.str()?;
+    let humor = df.column("humor")?.i64()?;
+    let rhythm = df.column("rhythm")?.i64()?;
+    let effort = df.column("effort")?.i64()?;
+    let tension = df.column("tension")?.i64()?;
+    let erotism = df.column("erotism")?.i64()?;
+    let sources = sources(df)?;
 
     let transformed_movies = (0..df.height())
         .map(|i| {
             Ok(TransformedMovie {
                 filmtv_id: filmtv_id.get(i).unwrap_or(0),
-                title: title.get(i).unwrap_or("").to_string(),
-                year: year.get(i).unwrap_or(0),
-                genre: genre.get(i).unwrap_or("").to_string(),
-                duration: duration.get(i).unwrap_or(0),
-                country: country.get(i).unwrap_or("").to_string(),
-                avg_vote: avg_vote.get(i).unwrap_or(0.0),
-                critics_vote: critics_vote.get(i).unwrap_or(0.0),
-                public_vote: public_vote.get(i).unwrap_or(0.0),
-                total_votes: total_votes.get(i).unwrap_or(0),
+                title: title.get(i).map(str::to_string),
+                year: year.get(i),
+                genre: genre.get(i).map(str::to_string),
+                duration: duration.get(i),
+                country: country.get(i).map(str::to_string),
+                directors: directors.get(i).map(str::to_string),
+                actors: actors.get(i).map(str::to_string),
+                avg_vote: avg_vote.get(i),
+                critics_vote: critics_vote.get(i),
+                public_vote: public_vote.get(i),
+                total_votes: total_votes.get(i),
+                description: description.get(i).map(str::to_string),
+                notes: notes.get(i).map(str::to_string),
+                humor: humor.get(i),
+                rhythm: rhythm.get(i),
+                effort: effort.get(i),
+                tension: tension.get(i),
+                erotism: erotism.get(i),
+                source: sources[i].clone(),
             })
         })
         .collect::<Result<Vec<TransformedMovie>, Box<dyn Error>>>()?;
@@ -135,6 +668,94 @@ fn dataframe_to_transformed_movies(
     Ok(transformed_movies)
 }
 
+/// Converts a `DataFrame` with the columns of `movie_schema` into `Movie` structs.
+///
+/// Unlike `dataframe_to_transformed_movies`, movies without a `filmtv_id` are kept. Their source
+/// is read from the source columns, if the `DataFrame` has them.
+///
+/// # Arguments
+///
+/// * `df` - A reference to a `DataFrame` with the columns of `movie_schema`.
+///
+/// # Returns
+///
+/// A `Result` containing a vector of `Movie` structs or a `PolarsError`.
+pub fn dataframe_to_movies(df: &DataFrame) -> Result<Vec<Movie>, PolarsError> {
+    let filmtv_id = df.column("filmtv_id")?.i64()?;
+    let title = df.column("title")?.str()?;
+    let year = df.column("year")?.i64()?;
+    let genre = df.column("genre")?.str()?;
+    let duration = df.column("duration")?.i64()?;
+    let country = df.column("country")?.str()?;
+    let directors = df.column("directors")?.str()?;
+    let actors = df.column("actors")?.str()?;
+    let avg_vote = df.column("avg_vote")?.f64()?;
+    let critics_vote = df.column("critics_vote")?.f64()?;
+    let public_vote = df.column("public_vote")?.f64()?;
+    let total_votes = df.column("total_votes")?.i64()?;
+    let description = df.column("description")?.str()?;
+    let notes = df.column("notes")?.str()?;
+    let humor = df.column("humor")?.i64()?;
+    let rhythm = df.column("rhythm")?.i64()?;
+    let effort = df.column("effort")?.i64()?;
+    let tension = df.column("tension")?.i64()?;
+    let erotism = df.column("erotism")?.i64()?;
+    let mut sources = sources(df)?.into_iter();
+
+    Ok((0..df.height())
+        .map(|i| Movie {
+            filmtv_id: filmtv_id.get(i),
+            title: title.get(i).map(str::to_string),
+            year: year.get(i),
+            genre: genre.get(i).map(str::to_string),
+            duration: duration.get(i),
+            country: country.get(i).map(str::to_string),
+            directors: directors.get(i).map(str::to_string),
+            actors: actors.get(i).map(str::to_string),
+            avg_vote: avg_vote.get(i),
+            critics_vote: critics_vote.get(i),
+            public_vote: public_vote.get(i),
+            total_votes: total_votes.get(i),
+            description: description.get(i).map(str::to_string),
+            notes: notes.get(i).map(str::to_string),
+            humor: humor.get(i),
+            rhythm: rhythm.get(i),
+            effort: effort.get(i),
+            tension: tension.get(i),
+            erotism: erotism.get(i),
+            source: sources.next().flatten(),
+        })
+        .collect())
+}
+
+/// Reads the source of every movie of a `DataFrame` from its source columns.
+///
+/// # Arguments
+///
+/// * `df` - A reference to a `DataFrame`, with or without the source columns.
+///
+/// # Returns
+///
+/// A `Result` containing the source of every row, all `None` without the source columns, or a
+/// `PolarsError`.
+fn sources(df: &DataFrame) -> Result<Vec<Option<Provenance>>, PolarsError> {
+    let (Ok(file), Ok(line)) = (df.column(SOURCE_FILE), df.column(SOURCE_LINE)) else {
+        return Ok(vec![None; df.height()]);
+    };
+    let line = line.cast(&DataType::UInt64)?;
+    Ok(file
+        .str()?
+        .into_iter()
+        .zip(line.u64()?)
+        .map(|(file, line)| {
+            file.zip(line).map(|(file, line)| Provenance {
+                file: PathBuf::from(file),
+                line,
+            })
+        })
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -163,6 +784,7 @@ mod tests {
                 effort: Some(7),
                 tension: Some(8),
                 erotism: Some(4),
+                source: None,
             },
             Movie {
                 filmtv_id: Some(2),
@@ -184,33 +806,48 @@ mod tests {
                 effort: Some(6),
                 tension: Some(4),
                 erotism: Some(3),
+                source: None,
             },
         ];
 
-        let transformed_movies = transform_movies(movies).unwrap();
+        let (df, _) = transform_movies(movies, &TransformOptions::default()).unwrap();
+        let transformed_movies = dataframe_to_transformed_movies(&df).unwrap();
 
         assert_eq!(transformed_movies.len(), 2);
         assert_eq!(transformed_movies[0].filmtv_id, 1);
-        assert_eq!(transformed_movies[0].title, "Example Movie".to_string());
-        assert_eq!(transformed_movies[0].year, 2021);
-        assert_eq!(transformed_movies[0].genre, "Drama".to_string());
-        assert_eq!(transformed_movies[0].duration, 120);
-        assert_eq!(transformed_movies[0].country, "USA".to_string());
-        assert_eq!(transformed_movies[0].avg_vote, 8.5);
-        assert_eq!(transformed_movies[0].critics_vote, 9.0);
-        assert_eq!(transformed_movies[0].public_vote, 8.0);
-        assert_eq!(transformed_movies[0].total_votes, 1000);
+        assert_eq!(
+            transformed_movies[0].title,
+            Some("Example Movie".to_string())
+        );
+        assert_eq!(transformed_movies[0].year, Some(2021));
+        assert_eq!(transformed_movies[0].genre, Some("Drama".to_string()));
+        assert_eq!(transformed_movies[0].duration, Some(120));
+        assert_eq!(transformed_movies[0].country, Some("USA".to_string()));
+        assert_eq!(transformed_movies[0].avg_vote, Some(8.5));
+        assert_eq!(transformed_movies[0].critics_vote, Some(9.0));
+        assert_eq!(transformed_movies[0].public_vote, Some(8.0));
+        assert_eq!(transformed_movies[0].total_votes, Some(1000));
 
         assert_eq!(transformed_movies[1].filmtv_id, 2);
-        assert_eq!(transformed_movies[1].title, "Unknown".to_string());
-        assert_eq!(transformed_movies[1].year, 2020);
-        assert_eq!(transformed_movies[1].genre, "Comedy".to_string());
-        assert_eq!(transformed_movies[1].duration, 90);
-        assert_eq!(transformed_movies[1].country, "Unknown".to_string());
-        assert_eq!(transformed_movies[1].avg_vote, 7.5);
-        assert_eq!(transformed_movies[1].critics_vote, 8.0);
-        assert_eq!(transformed_movies[1].public_vote, 7.0);
-        assert_eq!(transformed_movies[1].total_votes, 500);
+        assert_eq!(transformed_movies[1].title, None);
+        assert_eq!(transformed_movies[1].year, Some(2020));
+        assert_eq!(transformed_movies[1].genre, Some("Comedy".to_string()));
+        assert_eq!(transformed_movies[1].duration, Some(90));
+        assert_eq!(transformed_movies[1].country, None);
+        assert_eq!(transformed_movies[1].avg_vote, Some(7.5));
+        assert_eq!(transformed_movies[1].critics_vote, Some(8.0));
+        assert_eq!(transformed_movies[1].public_vote, Some(7.0));
+        assert_eq!(transformed_movies[1].total_votes, Some(500));
+        assert_eq!(transformed_movies[1].directors, None);
+        assert_eq!(transformed_movies[1].description, None);
+
+        // Scores above 5 are invalid and become null rather than being clamped
+        assert_eq!(transformed_movies[0].humor, Some(5));
+        assert_eq!(transformed_movies[0].rhythm, None);
+        assert_eq!(transformed_movies[0].erotism, Some(4));
+        assert_eq!(transformed_movies[1].rhythm, Some(5));
+        assert_eq!(transformed_movies[1].tension, Some(4));
+        assert_eq!(transformed_movies[1].humor, None);
     }
 
     #[test]
@@ -235,15 +872,28 @@ mod tests {
             effort: Some(7),
             tension: Some(8),
             erotism: Some(4),
+            source: Some(Provenance {
+                file: PathBuf::from("movies.csv"),
+                line: 7,
+            }),
         }];
 
         let df = movies_to_dataframe(movies).unwrap();
 
-        assert_eq!(df.shape(), (1, 10));
+        assert_eq!(df.shape(), (1, 21));
+        assert_eq!(df.schema(), output_schema());
+        let transformed = dataframe_to_transformed_movies(&df).unwrap();
+        assert_eq!(
+            transformed[0].source,
+            Some(Provenance {
+                file: PathBuf::from("movies.csv"),
+                line: 7,
+            })
+        );
     }
 
     #[test]
-    fn test_clean_and_validate_data() {
+    fn test_clean() {
         let movies = vec![
             Movie {
                 filmtv_id: Some(1),
@@ -265,6 +915,7 @@ mod tests {
                 effort: Some(7),
                 tension: Some(8),
                 erotism: Some(4),
+                source: None,
             },
             Movie {
                 filmtv_id: None,
@@ -286,13 +937,16 @@ mod tests {
                 effort: None,
                 tension: None,
                 erotism: None,
+                source: None,
             },
         ];
 
         let df = movies_to_dataframe(movies).unwrap();
-        let cleaned_df = clean_and_validate_data(df).unwrap();
+        let cleaned_df = clean(df.lazy(), &NullPolicies::default())
+            .collect()
+            .unwrap();
 
-        assert_eq!(cleaned_df.shape(), (1, 10));
+        assert_eq!(cleaned_df.shape(), (1, 21));
     }
 
     #[test]
@@ -317,22 +971,240 @@ mod tests {
             effort: Some(7),
             tension: Some(8),
             erotism: Some(4),
+            source: None,
         }];
 
         let df = movies_to_dataframe(movies).unwrap();
-        let cleaned_df = clean_and_validate_data(df).unwrap();
+        let cleaned_df = clean(df.lazy(), &NullPolicies::default())
+            .collect()
+            .unwrap();
         let transformed_movies = dataframe_to_transformed_movies(&cleaned_df).unwrap();
 
         assert_eq!(transformed_movies.len(), 1);
         assert_eq!(transformed_movies[0].filmtv_id, 1);
-        assert_eq!(transformed_movies[0].title, "Example Movie".to_string());
-        assert_eq!(transformed_movies[0].year, 2021);
-        assert_eq!(transformed_movies[0].genre, "Drama".to_string());
-        assert_eq!(transformed_movies[0].duration, 120);
-        assert_eq!(transformed_movies[0].country, "USA".to_string());
-        assert_eq!(transformed_movies[0].avg_vote, 8.5);
-        assert_eq!(transformed_movies[0].critics_vote, 9.0);
-        assert_eq!(transformed_movies[0].public_vote, 8.0);
-        assert_eq!(transformed_movies[0].total_votes, 1000);
+        assert_eq!(
+            transformed_movies[0].title,
+            Some("Example Movie".to_string())
+        );
+        assert_eq!(transformed_movies[0].year, Some(2021));
+        assert_eq!(transformed_movies[0].genre, Some("Drama".to_string()));
+        assert_eq!(transformed_movies[0].duration, Some(120));
+        assert_eq!(transformed_movies[0].country, Some("USA".to_string()));
+        assert_eq!(transformed_movies[0].avg_vote, Some(8.5));
+        assert_eq!(transformed_movies[0].critics_vote, Some(9.0));
+        assert_eq!(transformed_movies[0].public_vote, Some(8.0));
+        assert_eq!(transformed_movies[0].total_votes, Some(1000));
+        assert_eq!(
+            transformed_movies[0].directors,
+            Some("John Doe".to_string())
+        );
+        assert_eq!(transformed_movies[0].actors, Some("Jane Doe".to_string()));
+        assert_eq!(
+            transformed_movies[0].description,
+            Some("An example movie description.".to_string())
+        );
+        assert_eq!(transformed_movies[0].notes, Some("Some notes.".to_string()));
+    }
+
+    #[test]
+    fn test_clean_text_and_mood_fields() {
+        let df = df![
+            "filmtv_id" => [1i64, 2],
+            "title" => ["A", "B"],
+            "year" => [2021i64, 2022],
+            "genre" => ["Drama", "Comedy"],
+            "duration" => [120i64, 90],
+            "country" => ["USA", "Italy"],
+            "directors" => [Some("  John Doe "), Some("   ")],
+            "actors" => [None, Some("")],
+            "avg_vote" => [8.5, 7.0],
+            "critics_vote" => [9.0, 6.0],
+            "public_vote" => [8.0, 8.0],
+            "total_votes" => [1000i64, 10],
+            "description" => [Some("Text.\n"), None],
+            "notes" => [Some(""), Some("Note")],
+            "humor" => [Some(0i64), None],
+            "rhythm" => [Some(5i64), Some(-1)],
+            "effort" => [None::<i64>, Some(3)],
+            "tension" => [Some(2i64), Some(9)],
+            "erotism" => [Some(1i64), Some(0)],
+        ]
+        .unwrap();
+
+        let transformed_movies = dataframe_to_transformed_movies(
+            &clean(df.lazy(), &NullPolicies::default())
+                .collect()
+                .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            transformed_movies[0].directors,
+            Some("John Doe".to_string())
+        );
+        assert_eq!(transformed_movies[0].actors, None);
+        assert_eq!(transformed_movies[0].description, Some("Text.".to_string()));
+        assert_eq!(transformed_movies[0].notes, None);
+        assert_eq!(transformed_movies[1].directors, None);
+        assert_eq!(transformed_movies[1].actors, None);
+        assert_eq!(transformed_movies[1].notes, Some("Note".to_string()));
+        assert_eq!(transformed_movies[0].humor, Some(0));
+        assert_eq!(transformed_movies[0].rhythm, Some(5));
+        assert_eq!(transformed_movies[0].effort, None);
+        assert_eq!(transformed_movies[1].rhythm, None);
+        assert_eq!(transformed_movies[1].effort, Some(3));
+        assert_eq!(transformed_movies[1].tension, None);
+    }
+
+    #[test]
+    fn test_clean_applies_null_policies() {
+        let df = df![
+            "filmtv_id" => [1i64, 2, 3, 4],
+            "title" => [Some("A"), None, Some(" "), Some("D")],
+            "year" => [Some(2021i64), Some(2022), Some(2023), None],
+            "genre" => [Some("Drama"), None, Some("Drama"), Some("Comedy")],
+            "duration" => [Some(100i64), None, Some(121), Some(90)],
+            "country" => [None::<&str>, None, None, None],
+            "directors" => [None::<&str>, None, None, None],
+            "actors" => [None::<&str>, None, None, None],
+            "avg_vote" => [Some(8.0), None, Some(6.0), Some(7.0)],
+            "critics_vote" => [None::<f64>, None, None, None],
+            "public_vote" => [None::<f64>, None, None, None],
+            "total_votes" => [None::<i64>, None, None, None],
+            "description" => [None::<&str>, None, None, None],
+            "notes" => [None::<&str>, None, None, None],
+            "humor" => [Some(9i64), None, None, None],
+            "rhythm" => [None::<i64>, None, None, None],
+            "effort" => [None::<i64>, None, None, None],
+            "tension" => [None::<i64>, None, None, None],
+            "erotism" => [None::<i64>, None, None, None],
+        ]
+        .unwrap();
+        let null_policies: NullPolicies = toml::from_str(
+            r#"
+            title = { fill = "Unknown" }
+            year = "drop"
+            genre = "mode"
+            duration = "mean"
+            avg_vote = "median"
+            humor = { fill = 0 }
+            critics_vote = { fill = 0 }
+            "#,
+        )
+        .unwrap();
+        null_policies.validate().unwrap();
+
+        let transformed_movies =
+            dataframe_to_transformed_movies(&clean(df.lazy(), &null_policies).collect().unwrap())
+                .unwrap();
+
+        assert_eq!(transformed_movies.len(), 3);
+        assert_eq!(transformed_movies[1].title, Some("Unknown".to_string()));
+        assert_eq!(transformed_movies[2].title, Some("Unknown".to_string()));
+        assert_eq!(transformed_movies[1].genre, Some("Drama".to_string()));
+        assert_eq!(transformed_movies[1].duration, Some(104));
+        assert_eq!(transformed_movies[1].avg_vote, Some(7.0));
+        assert_eq!(transformed_movies[0].humor, Some(0));
+        assert_eq!(transformed_movies[0].critics_vote, Some(0.0));
+        assert_eq!(transformed_movies[0].country, None);
+    }
+
+    #[test]
+    fn test_null_policies_validate() {
+        let valid: NullPolicies =
+            toml::from_str("avg_vote = { fill = 5 }\nnotes = \"mode\"").unwrap();
+        assert!(valid.validate().is_ok());
+
+        for invalid in [
+            "filmtv_id = \"drop\"",
+            "budget = \"keep\"",
+            "country = \"median\"",
+            "year = { fill = \"Unknown\" }",
+            "erotism = { fill = 6 }",
+        ] {
+            let policies: NullPolicies = toml::from_str(invalid).unwrap();
+            assert!(
+                matches!(policies.validate(), Err(ConfigError::InvalidValue { .. })),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_whole_input_setting() {
+        assert_eq!(TransformOptions::default().whole_input_setting(), None);
+
+        let options = |config: &str| -> TransformOptions {
+            let config: crate::config::PipelineConfig = toml::from_str(config).unwrap();
+            config.transform_options()
+        };
+        assert_eq!(
+            options("[nulls]\nyear = { fill = 2000 }\nnotes = \"drop\"").whole_input_setting(),
+            None
+        );
+        for (config, setting) in [
+            ("[duplicates]\npolicy = \"merge\"", "duplicate policy merge"),
+            (
+                "[duplicates.fuzzy]\nthreshold = 0.9",
+                "the fuzzy duplicate stage",
+            ),
+            (
+                "[[validation.rules]]\ncolumn = \"title\"\ncheck = \"unique\"",
+                "unique rule \"title unique\"",
+            ),
+            (
+                "[nulls]\navg_vote = \"median\"",
+                "median null policy of avg_vote",
+            ),
+        ] {
+            assert_eq!(
+                options(config).whole_input_setting().as_deref(),
+                Some(setting)
+            );
+        }
+    }
+
+    #[test]
+    fn test_transform_lazy_matches_transform_movies() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("movies.csv");
+        std::fs::write(
+            &path,
+            "filmtv_id,title,year,genre,duration,country,directors,actors,avg_vote,critics_vote,\
+             public_vote,total_votes,description,notes,humor,rhythm,effort,tension,erotism\n\
+             1,Example Movie,2021,Drama,120,USA,John Doe,Jane Doe,8.5,9.0,8.0,1000,,,5,3,2,1,0\n\
+             2,  ,,,90,UK,,,11.0,,7.0,500,  Padded  ,,9,,1,1,1\n\
+             1,Example Movie,2021,,120,USA,John Doe,,8.0,9.0,7.0,2000,,,1,1,1,1,1\n\
+             -4,Negative,1999,Drama,100,Italy,,,6.0,6.0,6.0,10,,,1,1,1,1,1\n\
+             3,Third Movie,,Comedy,95,Italy,,,6.5,6.0,7.0,20,,,,,,,\n",
+        )
+        .unwrap();
+
+        let options = TransformOptions {
+            duplicates: DuplicateConfig {
+                policy: dedup::DuplicatePolicy::MostVotes,
+                fuzzy: None,
+            },
+            null_policies: toml::from_str("year = \"median\"\ngenre = { fill = \"Unknown\" }")
+                .unwrap(),
+            validation: toml::from_str(
+                "[[rules]]\ncolumn = \"avg_vote\"\ncheck = \"between\"\nmin = 0.0\nmax = 10.0\n\
+                 severity = \"drop\"",
+            )
+            .unwrap(),
+        };
+
+        let movies = crate::ingestion::ingest_csv(&path, &Default::default()).unwrap();
+        let (expected, expected_report) = transform_movies(movies, &options).unwrap();
+        let lf =
+            crate::ingestion::scan_csv(std::slice::from_ref(&path), &Default::default()).unwrap();
+        let (actual, report) = transform_lazy(lf, &options).unwrap();
+
+        assert_eq!(actual.height(), 2);
+        assert!(actual.equals_missing(&expected));
+        assert_eq!(report.rows_checked, expected_report.rows_checked);
+        assert_eq!(report.rows_dropped, 1);
+        assert_eq!(report.duplicates, expected_report.duplicates);
     }
 }
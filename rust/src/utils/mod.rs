@@ -1,5 +1,10 @@
-//! This module provides utility functions for parsing fields.
-//! It includes a generic `parse_field` function that attempts to parse a string into a specified type.
+//! This module provides utility functions for parsing fields and writing output files.
+//! It includes a generic `parse_field` function that attempts to parse a string into a specified type,
+//! and the `staging_path` and `remove_path` helpers used to replace output files atomically.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
 /// Parses a string field into a specified type.
 ///
@@ -18,6 +23,40 @@ pub fn parse_field<T: std::str::FromStr>(field: &str) -> Option<T> {
     field.parse().ok()
 }
 
+/// Returns the path an output is written to before it replaces `path`, e.g.
+/// `movies.csv.partial`.
+///
+/// # Arguments
+///
+/// * `path` - The path of the output.
+///
+/// # Returns
+///
+/// The staging path.
+pub fn staging_path(path: &Path) -> PathBuf {
+    let mut staging = path.as_os_str().to_owned();
+    staging.push(".partial");
+    PathBuf::from(staging)
+}
+
+/// Removes a file or directory, if it exists.
+///
+/// # Arguments
+///
+/// * `path` - The file or directory to remove.
+///
+/// # Returns
+///
+/// A `Result` indicating success or an I/O error.
+pub fn remove_path(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -48,4 +87,23 @@ mod tests {
         assert_eq!(parse_field::<bool>("false"), Some(false));
         assert_eq!(parse_field::<bool>("yes"), None); // Invalid boolean
     }
+
+    #[test]
+    fn test_staging_path_and_remove_path() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("movies.csv");
+        assert_eq!(
+            staging_path(&path),
+            temp_dir.path().join("movies.csv.partial")
+        );
+
+        fs::write(&path, "filmtv_id\n").unwrap();
+        remove_path(&path).unwrap();
+        assert!(!path.exists());
+        remove_path(&path).unwrap(); // Missing paths are ignored
+
+        fs::create_dir_all(path.join("year=1972")).unwrap();
+        remove_path(&path).unwrap();
+        assert!(!path.exists());
+    }
 }
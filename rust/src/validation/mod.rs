@@ -0,0 +1,632 @@
+//! This module provides the declarative data-quality rules checked by the transformation.
+//! Rules are declared in the `[validation]` section of the configuration file. Each rule checks
+//! one column, and its severity decides whether failing movies are only reported, dropped or fail
+//! the whole run. Every run produces a `DataQualityReport` with the pass and fail counts of each
+//! rule and a sample of the offending movies, which is written as JSON.
+
+use crate::config::ConfigError;
+use crate::dedup::{Duplicate, FuzzyCluster};
+use crate::metrics;
+use crate::models::TransformedMovie;
+use crate::transform::{self, ColumnKind};
+use chrono::Datelike;
+use polars::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Maximum number of offending movies kept as samples per rule.
+pub const MAX_SAMPLES: usize = 5;
+
+/// Enum representing what happens to movies that fail a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Report the failing movies and keep them.
+    #[default]
+    Warn,
+    /// Report the failing movies and drop them.
+    Drop,
+    /// Report the failing movies and abort the run without loading anything.
+    Fail,
+}
+
+/// Enum representing a bound of a `between` check.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Bound {
+    /// A constant bound.
+    Value(f64),
+    /// A bound computed when the run starts.
+    Keyword(BoundKeyword),
+}
+
+/// Enum representing the bounds computed when the run starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundKeyword {
+    /// The current calendar year.
+    CurrentYear,
+}
+
+impl Bound {
+    /// Returns the value of the bound.
+    fn value(&self) -> f64 {
+        match self {
+            Bound::Value(value) => *value,
+            Bound::Keyword(BoundKeyword::CurrentYear) => chrono::Local::now().year() as f64,
+        }
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bound::Value(value) => write!(f, "{}", value),
+            Bound::Keyword(BoundKeyword::CurrentYear) => write!(f, "current_year"),
+        }
+    }
+}
+
+/// Enum representing the check a rule applies to its column. Missing values pass every check
+/// except `not_empty`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "check", rename_all = "snake_case")]
+pub enum Check {
+    /// The value lies between `min` and `max`, both inclusive and both optional.
+    Between {
+        min: Option<Bound>,
+        max: Option<Bound>,
+    },
+    /// The value is strictly greater than `value`.
+    GreaterThan { value: f64 },
+    /// The value is present and, for text columns, not blank.
+    NotEmpty,
+    /// No other movie has the same value.
+    Unique,
+    /// The text matches the regular expression `regex`.
+    Pattern { regex: String },
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Check::Between { min, max } => {
+                let bound = |bound: &Option<Bound>| {
+                    bound
+                        .as_ref()
+                        .map_or_else(|| "..".to_string(), Bound::to_string)
+                };
+                write!(f, "between {} and {}", bound(min), bound(max))
+            }
+            Check::GreaterThan { value } => write!(f, "> {}", value),
+            Check::NotEmpty => write!(f, "not empty"),
+            Check::Unique => write!(f, "unique"),
+            Check::Pattern { regex } => write!(f, "matches /{}/", regex),
+        }
+    }
+}
+
+/// Struct representing a data-quality rule.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Rule {
+    /// The name used in the report. Defaults to the column followed by the check.
+    pub name: Option<String>,
+    /// The column that is checked.
+    pub column: String,
+    /// The check applied to the column.
+    #[serde(flatten)]
+    pub check: Check,
+    /// What happens to movies that fail the check.
+    #[serde(default)]
+    pub severity: Severity,
+}
+
+impl Rule {
+    /// Returns the name of the rule.
+    pub fn name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("{} {}", self.column, self.check))
+    }
+
+    /// Returns a boolean expression that is true for the movies that pass the rule.
+    fn passes(&self) -> Expr {
+        let column = col(&self.column);
+        let passes = match &self.check {
+            Check::Between { min, max } => {
+                let mut passes = lit(true);
+                if let Some(min) = min {
+                    passes = passes.and(column.clone().gt_eq(lit(min.value())));
+                }
+                if let Some(max) = max {
+                    passes = passes.and(column.clone().lt_eq(lit(max.value())));
+                }
+                passes
+            }
+            Check::GreaterThan { value } => column.clone().gt(lit(*value)),
+            Check::NotEmpty => {
+                return match transform::column_kind(&self.column) {
+                    Some(ColumnKind::Text) => column
+                        .str()
+                        .strip_chars(lit(NULL))
+                        .str()
+                        .len_chars()
+                        .gt(0)
+                        .fill_null(lit(false)),
+                    _ => column.is_not_null(),
+                };
+            }
+            Check::Unique => column.clone().is_duplicated().not(),
+            Check::Pattern { regex } => column.clone().str().contains(lit(regex.clone()), true),
+        };
+
+        column.is_null().or(passes)
+    }
+
+    /// Checks that the rule names a known column and that the check suits its type.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a description of the problem.
+    fn validate(&self) -> Result<(), String> {
+        let kind = transform::column_kind(&self.column)
+            .ok_or_else(|| format!("unknown column {:?}", self.column))?;
+
+        match (&self.check, kind) {
+            (Check::Between { .. } | Check::GreaterThan { .. }, ColumnKind::Text) => Err(format!(
+                "{} needs a numeric column, {:?} is text",
+                self.check, self.column
+            )),
+            (Check::Between { min, max }, _) => match (min, max) {
+                (None, None) => Err("between needs min or max".to_string()),
+                (Some(min), Some(max)) if min.value() > max.value() => {
+                    Err(format!("min {} is greater than max {}", min, max))
+                }
+                _ => Ok(()),
+            },
+            (Check::Pattern { .. }, ColumnKind::Int | ColumnKind::Float) => Err(format!(
+                "pattern needs a text column, {:?} is numeric",
+                self.column
+            )),
+            (Check::Pattern { regex }, ColumnKind::Text) => Regex::new(regex)
+                .map(|_| ())
+                .map_err(|e| format!("invalid regex: {}", e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Struct representing the `[validation]` section of the configuration file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ValidationConfig {
+    /// The rules checked on every movie.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// Where the data-quality report is written.
+    #[serde(default = "default_report_path")]
+    pub report: PathBuf,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        ValidationConfig {
+            rules: Vec::new(),
+            report: default_report_path(),
+        }
+    }
+}
+
+/// Returns the default path of the data-quality report.
+fn default_report_path() -> PathBuf {
+    PathBuf::from("data_quality_report.json")
+}
+
+impl ValidationConfig {
+    /// Checks every rule.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `ConfigError` for the first invalid rule.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (i, rule) in self.rules.iter().enumerate() {
+            rule.validate()
+                .map_err(|reason| ConfigError::InvalidValue {
+                    key: format!("validation.rules[{}]", i),
+                    value: format!("{}: {}", rule.name(), reason),
+                })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Enum representing the errors raised by failing rules.
+#[derive(Debug)]
+pub enum ValidationError {
+    /// Error that occurs when movies fail a rule with the `fail` severity.
+    RuleFailed {
+        /// The name of the rule.
+        rule: String,
+        /// The number of failing movies.
+        failed: usize,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::RuleFailed { rule, failed } => {
+                write!(f, "{} movie(s) failed the rule {:?}", failed, rule)
+            }
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+/// Struct representing the outcome of one rule.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleReport {
+    /// The name of the rule.
+    pub rule: String,
+    /// The column that was checked.
+    pub column: String,
+    /// The severity of the rule.
+    pub severity: Severity,
+    /// The number of movies that passed.
+    pub passed: usize,
+    /// The number of movies that failed.
+    pub failed: usize,
+    /// Up to `MAX_SAMPLES` movies that failed.
+    pub samples: Vec<TransformedMovie>,
+}
+
+/// Struct representing the data-quality report of a run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DataQualityReport {
+    /// The number of movies checked.
+    pub rows_checked: usize,
+    /// The number of movies dropped by rules with the `drop` severity.
+    pub rows_dropped: usize,
+    /// The outcome of every rule, in declaration order.
+    pub rules: Vec<RuleReport>,
+    /// The `filmtv_id`s that occurred more than once, and how they were resolved.
+    pub duplicates: Vec<Duplicate>,
+    /// The movies that are probably the same film under different `filmtv_id`s.
+    pub fuzzy_clusters: Vec<FuzzyCluster>,
+}
+
+impl DataQualityReport {
+    /// Adds the counts and samples of another report, e.g. of the next batch in streaming mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The report to add, for the same rules.
+    pub fn merge(&mut self, other: DataQualityReport) {
+        self.rows_checked += other.rows_checked;
+        self.rows_dropped += other.rows_dropped;
+        self.duplicates.extend(other.duplicates);
+        self.fuzzy_clusters.extend(other.fuzzy_clusters);
+
+        if self.rules.is_empty() {
+            self.rules = other.rules;
+            return;
+        }
+
+        for (rule, other) in self.rules.iter_mut().zip(other.rules) {
+            rule.passed += other.passed;
+            rule.failed += other.failed;
+            let room = MAX_SAMPLES.saturating_sub(rule.samples.len());
+            rule.samples.extend(other.samples.into_iter().take(room));
+        }
+    }
+
+    /// Checks whether any movie failed a rule with the `fail` severity.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `ValidationError` naming the first failed rule.
+    pub fn check(&self) -> Result<(), ValidationError> {
+        match self
+            .rules
+            .iter()
+            .find(|rule| rule.severity == Severity::Fail && rule.failed > 0)
+        {
+            Some(rule) => Err(ValidationError::RuleFailed {
+                rule: rule.rule.clone(),
+                failed: rule.failed,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the report as pretty-printed JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to write.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or an `io::Error`.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+}
+
+/// Checks the rules on cleaned movies and drops the movies that fail a rule with the `drop`
+/// severity, like `check_rules`. The rules are added to the query plan of the movies, which is
+/// collected once.
+///
+/// # Arguments
+///
+/// * `lf` - A `LazyFrame` of cleaned movies.
+/// * `rules` - The rules to check.
+///
+/// # Returns
+///
+/// A `Result` containing the remaining movies and the `DataQualityReport`, or an error.
+pub fn apply_rules(
+    lf: LazyFrame,
+    rules: &[Rule],
+) -> Result<(DataFrame, DataQualityReport), Box<dyn Error>> {
+    check_rules(add_rule_masks(lf, rules).collect()?, rules)
+}
+
+/// Adds the outcome of every rule to the query plan of cleaned movies, as a temporary boolean
+/// column per rule, so the plan can be collected together with the rules.
+///
+/// # Arguments
+///
+/// * `lf` - A `LazyFrame` of cleaned movies.
+/// * `rules` - The rules to check.
+///
+/// # Returns
+///
+/// The `LazyFrame` with the outcome columns, to be collected and passed to `check_rules`.
+pub fn add_rule_masks(lf: LazyFrame, rules: &[Rule]) -> LazyFrame {
+    let masks = rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| rule.passes().alias(&mask_name(i)))
+        .collect::<Vec<_>>();
+    lf.with_columns(masks)
+}
+
+/// Counts the movies that passed and failed every rule and drops the movies that fail a rule
+/// with the `drop` severity. Every rule is checked against the same movies, so a movie dropped by
+/// one rule is still counted by the others.
+///
+/// # Arguments
+///
+/// * `checked` - The cleaned movies, collected from the plan returned by `add_rule_masks`.
+/// * `rules` - The rules that were added to the plan.
+///
+/// # Returns
+///
+/// A `Result` containing the remaining movies, without the outcome columns, and the
+/// `DataQualityReport`, or an error.
+pub fn check_rules(
+    checked: DataFrame,
+    rules: &[Rule],
+) -> Result<(DataFrame, DataQualityReport), Box<dyn Error>> {
+    let mut df = checked.clone();
+    for i in 0..rules.len() {
+        df = df.drop(&mask_name(i))?;
+    }
+    let mut report = DataQualityReport {
+        rows_checked: df.height(),
+        ..DataQualityReport::default()
+    };
+
+    let mut keep = BooleanChunked::full("keep", true, df.height());
+    for (i, rule) in rules.iter().enumerate() {
+        let passes = checked.column(&mask_name(i))?.bool()?;
+        let passed = passes.sum().unwrap_or(0) as usize;
+        let failed = df.height() - passed;
+
+        let samples = if failed > 0 {
+            let offending = df.filter(&!passes)?.head(Some(MAX_SAMPLES));
+            transform::dataframe_to_transformed_movies(&offending)?
+        } else {
+            Vec::new()
+        };
+
+        if rule.severity == Severity::Drop {
+            keep = &keep & passes;
+        }
+        let name = rule.name();
+        if rule.severity != Severity::Warn {
+            metrics::ROWS_REJECTED
+                .with_label_values(&[name.as_str()])
+                .inc_by(failed as u64);
+        }
+
+        report.rules.push(RuleReport {
+            rule: name,
+            column: rule.column.clone(),
+            severity: rule.severity,
+            passed,
+            failed,
+            samples,
+        });
+    }
+
+    let valid = df.filter(&keep)?;
+    report.rows_dropped = df.height() - valid.height();
+
+    Ok((valid, report))
+}
+
+/// Returns the name of the temporary column holding the outcome of a rule.
+fn mask_name(i: usize) -> String {
+    format!("__rule_{}", i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn movies() -> DataFrame {
+        df![
+            "filmtv_id" => [1i64, 2, 2, 4],
+            "title" => [Some("A"), Some("B"), None, Some("d")],
+            "year" => [Some(1850i64), Some(2020), Some(2021), None],
+            "genre" => [None::<&str>, None, None, None],
+            "duration" => [Some(90i64), Some(0), Some(100), Some(120)],
+            "country" => [None::<&str>, None, None, None],
+            "directors" => [None::<&str>, None, None, None],
+            "actors" => [None::<&str>, None, None, None],
+            "avg_vote" => [Some(7.0), Some(11.0), None, Some(6.5)],
+            "critics_vote" => [None::<f64>, None, None, None],
+            "public_vote" => [None::<f64>, None, None, None],
+            "total_votes" => [None::<i64>, None, None, None],
+            "description" => [None::<&str>, None, None, None],
+            "notes" => [None::<&str>, None, None, None],
+            "humor" => [None::<i64>, None, None, None],
+            "rhythm" => [None::<i64>, None, None, None],
+            "effort" => [None::<i64>, None, None, None],
+            "tension" => [None::<i64>, None, None, None],
+            "erotism" => [None::<i64>, None, None, None],
+        ]
+        .unwrap()
+    }
+
+    fn rules(toml: &str) -> Vec<Rule> {
+        let config: ValidationConfig = toml::from_str(toml).unwrap();
+        config.validate().unwrap();
+        config.rules
+    }
+
+    #[test]
+    fn test_apply_rules_counts_and_drops() {
+        let rules = rules(
+            r#"
+            [[rules]]
+            column = "year"
+            check = "between"
+            min = 1890
+            max = "current_year"
+            severity = "drop"
+
+            [[rules]]
+            name = "votes in range"
+            column = "avg_vote"
+            check = "between"
+            min = 0
+            max = 10
+
+            [[rules]]
+            column = "duration"
+            check = "greater_than"
+            value = 0
+            severity = "drop"
+
+            [[rules]]
+            column = "title"
+            check = "not_empty"
+
+            [[rules]]
+            column = "filmtv_id"
+            check = "unique"
+
+            [[rules]]
+            column = "title"
+            check = "pattern"
+            regex = "^[A-Z]"
+            "#,
+        );
+
+        let (valid, report) = apply_rules(movies().lazy(), &rules).unwrap();
+
+        let counts = report
+            .rules
+            .iter()
+            .map(|rule| (rule.rule.as_str(), rule.passed, rule.failed))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            vec![
+                ("year between 1890 and current_year", 3, 1),
+                ("votes in range", 3, 1),
+                ("duration > 0", 3, 1),
+                ("title not empty", 3, 1),
+                ("filmtv_id unique", 2, 2),
+                ("title matches /^[A-Z]/", 3, 1),
+            ]
+        );
+        assert_eq!(report.rules[0].samples[0].filmtv_id, 1);
+        assert_eq!(report.rules[1].severity, Severity::Warn);
+        assert_eq!(report.rows_checked, 4);
+        assert_eq!(report.rows_dropped, 2);
+        assert_eq!(
+            valid.column("filmtv_id").unwrap().i64().unwrap().to_vec(),
+            vec![Some(2), Some(4)]
+        );
+        assert!(report.check().is_ok());
+    }
+
+    #[test]
+    fn test_fail_severity_and_report() {
+        let rules = rules(
+            r#"
+            [[rules]]
+            column = "duration"
+            check = "greater_than"
+            value = 0
+            severity = "fail"
+            "#,
+        );
+
+        let (_, report) = apply_rules(movies().lazy(), &rules).unwrap();
+        let mut merged = DataQualityReport::default();
+        merged.merge(report.clone());
+        merged.merge(report);
+
+        assert_eq!(merged.rows_checked, 8);
+        assert_eq!(merged.rules[0].failed, 2);
+        assert_eq!(merged.rules[0].samples.len(), 2);
+        assert!(matches!(
+            merged.check(),
+            Err(ValidationError::RuleFailed { failed: 2, .. })
+        ));
+
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path().join("report.json");
+        merged.write(&path).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["rules"][0]["rule"], "duration > 0");
+        assert_eq!(json["rules"][0]["severity"], "fail");
+        assert_eq!(json["rules"][0]["samples"][0]["filmtv_id"], 2);
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_rules() {
+        for invalid in [
+            "[[rules]]\ncolumn = \"budget\"\ncheck = \"unique\"",
+            "[[rules]]\ncolumn = \"title\"\ncheck = \"greater_than\"\nvalue = 0",
+            "[[rules]]\ncolumn = \"year\"\ncheck = \"pattern\"\nregex = \"^1\"",
+            "[[rules]]\ncolumn = \"title\"\ncheck = \"pattern\"\nregex = \"(\"",
+            "[[rules]]\ncolumn = \"year\"\ncheck = \"between\"\nmin = 2000\nmax = 1900",
+            "[[rules]]\ncolumn = \"year\"\ncheck = \"between\"",
+        ] {
+            let config: ValidationConfig = toml::from_str(invalid).unwrap();
+            assert!(
+                matches!(config.validate(), Err(ConfigError::InvalidValue { .. })),
+                "{}",
+                invalid
+            );
+        }
+    }
+}